@@ -0,0 +1,141 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// Compares the plain interpreter with one whose bus caches decoded
+// instructions, on plain RAM and on a bus with devices mapped into it. Run
+// with `cargo bench`.
+
+#![feature(test)]
+
+extern crate emu6502;
+extern crate test;
+
+use test::Bencher;
+
+use emu6502::address::Address;
+use emu6502::assembler;
+use emu6502::bus::Bus;
+use emu6502::code_cache::CodeCache;
+use emu6502::device::{Device, DeviceBus};
+use emu6502::instruction::Variant;
+use emu6502::machine::Machine;
+use emu6502::memory::Memory;
+use emu6502::range_incl::AddressRangeIncl;
+
+// Checksums a page over and over, with a mix of addressing modes
+static PROGRAM: &'static str = "
+        .org $0200
+start:  LDA #$00
+        STA $10
+        LDX #$00
+loop:   LDA $0400,X
+        EOR $10
+        ASL A
+        ADC ($20),Y
+        STA $10
+        INC $11
+        INX
+        BNE loop
+        JMP start
+
+        .org $0020
+        .word $0400
+
+        .org $FFFC
+        .word start
+";
+
+const INSTRUCTIONS: u64 = 100000;
+
+fn load() -> Memory {
+    let program = assembler::assemble(PROGRAM, Variant::Nmos).unwrap();
+    let mut memory = Memory::new();
+    program.load_into(&mut memory);
+    for i in 0..0x100 {
+        memory.set_byte(Address(0x0400 + i), (i * 7) as u8);
+    }
+    memory
+}
+
+// A one-byte register, standing in for an I/O chip
+struct Latch(u8);
+
+impl Device for Latch {
+    fn read(&mut self, _offset: u16) -> u8 {
+        self.0
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.0
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.0 = value;
+    }
+}
+
+// The program in RAM, with a device on each of pages $D0 to $D3. Every read
+// and write has to check whether it's for one of them.
+fn load_with_devices() -> DeviceBus {
+    let mut bus = DeviceBus::with_backing(load());
+    for page in 0xD0..0xD4 {
+        let range = AddressRangeIncl::new(Address(page << 8),
+                                          Address((page << 8) | 0xFF));
+        bus.register_device(range, Box::new(Latch(0)));
+    }
+    bus
+}
+
+fn run<B: Bus>(machine: &mut Machine<B>) {
+    let result = machine.run_for_instructions(INSTRUCTIONS);
+    assert_eq!(result.instructions, INSTRUCTIONS);
+}
+
+#[bench]
+fn plain_memory(b: &mut Bencher) {
+    let mut machine = Machine::with_bus(load());
+    b.iter(|| run(&mut machine));
+}
+
+#[bench]
+fn code_cache(b: &mut Bencher) {
+    let mut machine = Machine::with_bus(CodeCache::with_bus(load()));
+    b.iter(|| run(&mut machine));
+}
+
+#[bench]
+fn device_bus(b: &mut Bencher) {
+    let mut machine = Machine::with_bus(load_with_devices());
+    b.iter(|| run(&mut machine));
+}
+
+#[bench]
+fn device_bus_code_cache(b: &mut Bencher) {
+    let bus = CodeCache::with_bus(load_with_devices());
+    let mut machine = Machine::with_bus(bus);
+    b.iter(|| run(&mut machine));
+}
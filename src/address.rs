@@ -86,7 +86,7 @@ impl Address {
     }
 
     pub fn get_page_number(&self) -> u8 {
-        (self.to_u16() & 0xff00 >> 8) as u8
+        ((self.to_u16() & 0xff00) >> 8) as u8
     }
 
     pub fn get_offset(&self) -> u8 {
@@ -94,3 +94,38 @@ impl Address {
     }
 }
 
+
+// A 24-bit address, as used by the 65C816: a bank number in the top byte and
+// an `Address` within the bank. Adding an `AddressDiff` carries into the bank
+// and wraps around at the end of the 16 MiB address space.
+#[derive(Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct LongAddress(pub u32);
+
+impl Add<AddressDiff> for LongAddress {
+    type Output = LongAddress;
+
+    fn add(self, AddressDiff(rhs): AddressDiff) -> LongAddress {
+        let LongAddress(lhs) = self;
+        LongAddress(((lhs as i32).wrapping_add(rhs) as u32) & 0xFFFFFF)
+    }
+}
+
+impl LongAddress {
+    pub fn new(bank: u8, address: Address) -> LongAddress {
+        LongAddress(((bank as u32) << 16) | address.to_u16() as u32)
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        let LongAddress(address) = *self;
+        address
+    }
+
+    pub fn get_bank(&self) -> u8 {
+        (self.to_u32() >> 16) as u8
+    }
+
+    // The address within the bank
+    pub fn get_address(&self) -> Address {
+        Address(self.to_u32() as u16)
+    }
+}
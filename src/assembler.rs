@@ -0,0 +1,1079 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// A two-pass assembler for the usual 6502 syntax:
+//
+//         ; comments start with a semicolon
+//         count = 10             ; constants
+//         .org $C000             ; sets the address of what follows
+//  start: LDX #count             ; labels end with a colon
+//  loop:  LDA table,X
+//         STA $0200,X
+//         DEX
+//         BPL loop
+//         JMP (vector)
+//  table: .byte 1, 2, $03, %100, 'a', "text"
+//  vector: .word start
+//
+// Numbers are decimal, $hex or %binary, and `*` is the address of the current
+// statement. Expressions can use + - * /, and `<` and `>` to take the low and
+// high byte of a value. Zero page addressing is used whenever the operand is
+// known to fit in the zero page when the instruction is first seen (so
+// forward references to the zero page get the absolute form).
+//
+// Instructions are encoded by looking them up in the opcode table of the
+// variant being assembled for. The 65C02 adds `(zp)` and `(abs,X)` operands,
+// and BBRn/BBSn take a zero page address and a branch target: `BBR0 $10,loop`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use instruction::{AddressingMode, Instruction, Variant};
+
+// A contiguous run of assembled bytes
+#[derive(Debug)]
+pub struct Segment {
+    pub origin: Address,
+    pub bytes:  Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    pub symbols:  HashMap<String, i32>,
+}
+
+impl Program {
+    pub fn load_into<B: Bus>(&self, bus: &mut B) {
+        for segment in self.segments.iter() {
+            bus.set_bytes(segment.origin, &segment.bytes);
+        }
+    }
+
+    // The value of a label or constant, as an address
+    pub fn symbol(&self, name: &str) -> Option<Address> {
+        self.symbols.get(name).map(|&value| Address(value as u16))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub line:    usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub fn assemble(source: &str, variant: Variant)
+    -> Result<Program, AssemblerError>
+{
+    let mut lines = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = try!(parse_line(text, variant).map_err(|message| {
+            AssemblerError { line: i + 1, message: message }
+        }));
+        lines.push(line);
+    }
+
+    let mut assembler = Assembler {
+        variant: variant,
+        symbols: HashMap::new(),
+        modes:   Vec::new(),
+    };
+    try!(assembler.first_pass(&lines));
+    assembler.second_pass(&lines)
+}
+
+// Finds the opcode for the given instruction and addressing mode. Where an
+// undocumented opcode duplicates a documented one (like the extra NOPs), the
+// documented one wins.
+pub fn find_opcode(instr: Instruction, am: AddressingMode, variant: Variant)
+    -> Option<u8>
+{
+    let mut found = None;
+    for (opcode, entry) in variant.opcodes().iter().enumerate() {
+        match *entry {
+            Some((i, m, _)) if i == instr && m == am => {
+                if !variant.is_undocumented_opcode(opcode as u8) {
+                    return Some(opcode as u8);
+                }
+                if found.is_none() {
+                    found = Some(opcode as u8);
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+fn find_instruction(mnemonic: &str, variant: Variant) -> Option<Instruction> {
+    let mnemonic = to_upper(mnemonic);
+    for entry in variant.opcodes().iter() {
+        match *entry {
+            Some((instr, _, _)) if format!("{:?}", instr) == mnemonic => {
+                return Some(instr)
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn has_mode(instr: Instruction, am: AddressingMode, variant: Variant) -> bool {
+    find_opcode(instr, am, variant).is_some()
+}
+
+fn to_upper(s: &str) -> String {
+    s.chars()
+     .map(|c| if 'a' <= c && c <= 'z' { (c as u8 - b'a' + b'A') as char }
+              else { c })
+     .collect()
+}
+
+// Lexing
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i32),
+    Str(Vec<u8>),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        if c == ';' {
+            break;
+        } else if c == ' ' || c == '\t' || c == '\r' {
+            continue;
+        } else if c == '$' || c == '%' || c.is_digit(10) {
+            let radix = match c { '$' => 16, '%' => 2, _ => 10 };
+            let mut value: i64 = if radix == 10 {
+                c.to_digit(10).unwrap() as i64
+            } else {
+                match chars.peek() {
+                    Some(&d) if d.is_digit(radix) => 0,
+                    _ => return Err(format!("expected a number after '{}'", c)),
+                }
+            };
+            loop {
+                let digit = match chars.peek() {
+                    Some(&d) if d.is_digit(radix) => d.to_digit(radix).unwrap(),
+                    _ => break,
+                };
+                chars.next();
+                value = value * (radix as i64) + (digit as i64);
+                if value > 0xFFFF {
+                    return Err("number too large".to_string());
+                }
+            }
+            tokens.push(Token::Number(value as i32));
+        } else if c.is_alphabetic() || c == '_' || c == '.' {
+            let mut ident = c.to_string();
+            loop {
+                match chars.peek() {
+                    Some(&d) if d.is_alphanumeric() || d == '_' => {
+                        ident.push(d);
+                        chars.next();
+                    }
+                    _ => break,
+                }
+            }
+            tokens.push(Token::Ident(ident));
+        } else if c == '\'' {
+            let value = match chars.next() {
+                Some(d) if (d as u32) < 0x80 => d as i32,
+                _ => return Err("bad character literal".to_string()),
+            };
+            if chars.next() != Some('\'') {
+                return Err("unterminated character literal".to_string());
+            }
+            tokens.push(Token::Number(value));
+        } else if c == '"' {
+            let mut bytes = Vec::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(d) if (d as u32) < 0x80 => bytes.push(d as u8),
+                    Some(_) => return Err("non-ASCII character in string"
+                                          .to_string()),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(Token::Str(bytes));
+        } else if "#(),+-*/<>=:".contains(c) {
+            tokens.push(Token::Punct(c));
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Parsing
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i32),
+    Symbol(String),
+    CurrentAddress,
+    LowByte(Box<Expr>),
+    HighByte(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, symbols: &HashMap<String, i32>, pc: i32)
+        -> Result<i32, String>
+    {
+        match *self {
+            Expr::Number(n) => Ok(n),
+            Expr::Symbol(ref name) => match symbols.get(name) {
+                Some(&value) => Ok(value),
+                None => Err(format!("undefined symbol '{}'", name)),
+            },
+            Expr::CurrentAddress => Ok(pc),
+            Expr::LowByte(ref e) => Ok(try!(e.eval(symbols, pc)) & 0xff),
+            Expr::HighByte(ref e) =>
+                Ok((try!(e.eval(symbols, pc)) >> 8) & 0xff),
+            Expr::Negate(ref e) => Ok(-try!(e.eval(symbols, pc))),
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = try!(lhs.eval(symbols, pc));
+                let rhs = try!(rhs.eval(symbols, pc));
+                match op {
+                    '+' => Ok(lhs + rhs),
+                    '-' => Ok(lhs - rhs),
+                    '*' => Ok(lhs * rhs),
+                    _ if rhs == 0 => Err("division by zero".to_string()),
+                    _ => Ok(lhs / rhs),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Nothing,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr),           // ZeroPage, Absolute or Relative
+    IndexedX(Expr),         // ZeroPageX or AbsoluteX
+    IndexedY(Expr),         // ZeroPageY or AbsoluteY
+    Indirect(Expr),
+    IndexedIndirectX(Expr),
+    IndirectIndexedY(Expr),
+    ZeroPageRelative(Expr, Expr),
+}
+
+#[derive(Clone, Debug)]
+enum DataItem {
+    Value(Expr),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Org(Expr),
+    Byte(Vec<DataItem>),
+    Word(Vec<Expr>),
+    Constant(String, Expr),
+    Instr(Instruction, Operand),
+}
+
+struct Line {
+    label:     Option<String>,
+    statement: Option<Statement>,
+}
+
+struct Parser {
+    tokens:  Vec<Token>,
+    pos:     usize,
+    variant: Variant,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) { Ok(()) } else { Err(format!("expected '{}'", c)) }
+    }
+
+    fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    // Consumes an X or Y register name, if that's what's next
+    fn eat_register(&mut self, name: &str) -> bool {
+        let is_register = match self.peek() {
+            Some(&Token::Ident(ref ident)) => to_upper(ident) == name,
+            _ => false,
+        };
+        if is_register {
+            self.pos += 1;
+        }
+        is_register
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = try!(self.term());
+        loop {
+            let op = match self.peek() {
+                Some(&Token::Punct(c)) if c == '+' || c == '-' => c,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = try!(self.term());
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = try!(self.unary());
+        loop {
+            let op = match self.peek() {
+                Some(&Token::Punct(c)) if c == '*' || c == '/' => c,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = try!(self.unary());
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // unary := ('<' | '>' | '-') unary | number | symbol | '*'
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Punct('<')) =>
+                Ok(Expr::LowByte(Box::new(try!(self.unary())))),
+            Some(Token::Punct('>')) =>
+                Ok(Expr::HighByte(Box::new(try!(self.unary())))),
+            Some(Token::Punct('-')) =>
+                Ok(Expr::Negate(Box::new(try!(self.unary())))),
+            Some(Token::Punct('*')) => Ok(Expr::CurrentAddress),
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) => Ok(Expr::Symbol(name)),
+            Some(token) => Err(format!("expected an expression, found {:?}",
+                                       token)),
+            None => Err("expected an expression".to_string()),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        if self.at_end() {
+            return Ok(Operand::Nothing);
+        }
+
+        if self.tokens.len() == self.pos + 1 && self.eat_register("A") {
+            return Ok(Operand::Accumulator);
+        }
+
+        if self.eat('#') {
+            return Ok(Operand::Immediate(try!(self.expr())));
+        }
+
+        if self.eat('(') {
+            let expr = try!(self.expr());
+            if self.eat(',') {
+                if !self.eat_register("X") {
+                    return Err("expected X".to_string());
+                }
+                try!(self.expect(')'));
+                return Ok(Operand::IndexedIndirectX(expr));
+            }
+            try!(self.expect(')'));
+            if self.eat(',') {
+                if !self.eat_register("Y") {
+                    return Err("expected Y".to_string());
+                }
+                return Ok(Operand::IndirectIndexedY(expr));
+            }
+            return Ok(Operand::Indirect(expr));
+        }
+
+        let expr = try!(self.expr());
+        if self.eat(',') {
+            if self.eat_register("X") {
+                Ok(Operand::IndexedX(expr))
+            } else if self.eat_register("Y") {
+                Ok(Operand::IndexedY(expr))
+            } else if self.variant == Variant::Cmos {
+                Ok(Operand::ZeroPageRelative(expr, try!(self.expr())))
+            } else {
+                Err("expected X or Y".to_string())
+            }
+        } else {
+            Ok(Operand::Direct(expr))
+        }
+    }
+
+    fn data_items(&mut self) -> Result<Vec<DataItem>, String> {
+        let mut items = Vec::new();
+        loop {
+            let item = match self.peek() {
+                Some(&Token::Str(ref bytes)) =>
+                    Some(DataItem::Bytes(bytes.clone())),
+                _ => None,
+            };
+            match item {
+                Some(item) => {
+                    self.pos += 1;
+                    items.push(item);
+                }
+                None => items.push(DataItem::Value(try!(self.expr()))),
+            }
+            if !self.eat(',') {
+                return Ok(items);
+            }
+        }
+    }
+
+    fn exprs(&mut self) -> Result<Vec<Expr>, String> {
+        let mut exprs = vec![try!(self.expr())];
+        while self.eat(',') {
+            exprs.push(try!(self.expr()));
+        }
+        Ok(exprs)
+    }
+
+    fn statement(&mut self) -> Result<Option<Statement>, String> {
+        let ident = match self.next() {
+            None => return Ok(None),
+            Some(Token::Ident(ident)) => ident,
+            Some(token) => return Err(format!("unexpected {:?}", token)),
+        };
+
+        let statement = if self.eat('=') {
+            Statement::Constant(ident, try!(self.expr()))
+        } else {
+            match &to_upper(&ident)[..] {
+                ".ORG" => Statement::Org(try!(self.expr())),
+                ".BYTE" => Statement::Byte(try!(self.data_items())),
+                ".WORD" => Statement::Word(try!(self.exprs())),
+                _ => match find_instruction(&ident, self.variant) {
+                    Some(instr) =>
+                        Statement::Instr(instr, try!(self.operand())),
+                    None => return Err(format!("unknown instruction or \
+                                                directive '{}'", ident)),
+                },
+            }
+        };
+
+        try!(self.expect_end());
+        Ok(Some(statement))
+    }
+}
+
+fn parse_line(text: &str, variant: Variant) -> Result<Line, String> {
+    let mut parser = Parser { tokens:  try!(tokenize(text)),
+                              pos:     0,
+                              variant: variant };
+
+    let label = match (parser.peek().cloned(), parser.peek_at(1).cloned()) {
+        (Some(Token::Ident(name)), Some(Token::Punct(':'))) => {
+            parser.pos += 2;
+            Some(name)
+        }
+        _ => None,
+    };
+
+    let statement = try!(parser.statement());
+    Ok(Line { label: label, statement: statement })
+}
+
+// Assembly
+
+struct Assembler {
+    variant: Variant,
+    symbols: HashMap<String, i32>,
+
+    // The addressing mode chosen for each line in the first pass, so that the
+    // second pass generates instructions of the same length.
+    modes: Vec<Option<AddressingMode>>,
+}
+
+// Picks the addressing mode for an operand. `value` is the value of the
+// operand if it's known.
+fn choose_mode(instr: Instruction, operand: &Operand, value: Option<i32>,
+               variant: Variant)
+    -> Result<AddressingMode, String>
+{
+    let supports = |am| has_mode(instr, am, variant);
+
+    let fits_zero_page = match value {
+        Some(v) => 0 <= v && v <= 0xff,
+        None => false,
+    };
+
+    // Picks the zero page form if possible, otherwise the absolute form.
+    let pick = |zp: AddressingMode, abs: AddressingMode| {
+        if fits_zero_page && supports(zp) {
+            Some(zp)
+        } else if supports(abs) {
+            Some(abs)
+        } else if supports(zp) {
+            Some(zp)
+        } else {
+            None
+        }
+    };
+
+    let am = match *operand {
+        Operand::Nothing => {
+            if supports(AddressingMode::Implied) {
+                Some(AddressingMode::Implied)
+            } else {
+                Some(AddressingMode::Accumulator)
+            }
+        }
+        Operand::Accumulator => Some(AddressingMode::Accumulator),
+        Operand::Immediate(_) => Some(AddressingMode::Immediate),
+        Operand::Direct(_) => {
+            if supports(AddressingMode::Relative) {
+                Some(AddressingMode::Relative)
+            } else {
+                pick(AddressingMode::ZeroPage, AddressingMode::Absolute)
+            }
+        }
+        Operand::IndexedX(_) =>
+            pick(AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+        Operand::IndexedY(_) =>
+            pick(AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+        Operand::Indirect(_) => {
+            if supports(AddressingMode::Indirect) {
+                Some(AddressingMode::Indirect)
+            } else {
+                Some(AddressingMode::ZeroPageIndirect)
+            }
+        }
+        Operand::IndexedIndirectX(_) => {
+            if supports(AddressingMode::AbsoluteIndexedIndirect) {
+                Some(AddressingMode::AbsoluteIndexedIndirect)
+            } else {
+                Some(AddressingMode::IndexedIndirectX)
+            }
+        }
+        Operand::IndirectIndexedY(_) => Some(AddressingMode::IndirectIndexedY),
+        Operand::ZeroPageRelative(..) => Some(AddressingMode::ZeroPageRelative),
+    };
+
+    match am {
+        Some(am) if supports(am) => Ok(am),
+        _ => Err(format!("addressing mode not supported by {:?}", instr)),
+    }
+}
+
+fn operand_expr(operand: &Operand) -> Option<&Expr> {
+    match *operand {
+        Operand::Nothing | Operand::Accumulator => None,
+        Operand::Immediate(ref e) | Operand::Direct(ref e) |
+        Operand::IndexedX(ref e) | Operand::IndexedY(ref e) |
+        Operand::Indirect(ref e) | Operand::IndexedIndirectX(ref e) |
+        Operand::IndirectIndexedY(ref e) |
+        Operand::ZeroPageRelative(ref e, _) => Some(e),
+    }
+}
+
+fn check_range(value: i32, lo: i32, hi: i32, what: &str)
+    -> Result<i32, String>
+{
+    if lo <= value && value <= hi {
+        Ok(value)
+    } else {
+        Err(format!("{} out of range: {}", what, value))
+    }
+}
+
+impl Assembler {
+    fn define(&mut self, name: &str, value: i32) -> Result<(), String> {
+        if self.symbols.contains_key(name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+        self.symbols.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    // Defines every label and works out the length of every statement.
+    fn first_pass(&mut self, lines: &[Line]) -> Result<(), AssemblerError> {
+        let mut pc: i32 = 0;
+        let mut pending_constants = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            let result = self.first_pass_line(line, &mut pc,
+                                              &mut pending_constants);
+            try!(result.map_err(|message| {
+                AssemblerError { line: i + 1, message: message }
+            }));
+        }
+
+        // Constants that refer to labels further down can be resolved now.
+        // Keep going while that makes progress, since constants can refer to
+        // each other.
+        loop {
+            let mut unresolved = Vec::new();
+            let count = pending_constants.len();
+            for (line, pc, name, expr) in pending_constants.into_iter() {
+                let value = expr.eval(&self.symbols, pc);
+                match value {
+                    Ok(value) => { self.symbols.insert(name, value); }
+                    Err(_) => unresolved.push((line, pc, name, expr)),
+                }
+            }
+            if unresolved.is_empty() {
+                return Ok(());
+            }
+            if unresolved.len() == count {
+                let (line, pc, _, ref expr) = unresolved[0];
+                let message = expr.eval(&self.symbols, pc).err().unwrap();
+                return Err(AssemblerError { line: line, message: message });
+            }
+            pending_constants = unresolved;
+        }
+    }
+
+    fn first_pass_line(&mut self, line: &Line, pc: &mut i32,
+                       pending_constants:
+                           &mut Vec<(usize, i32, String, Expr)>)
+        -> Result<(), String>
+    {
+        if let Some(ref label) = line.label {
+            try!(self.define(label, *pc));
+        }
+
+        let mut mode = None;
+        match line.statement {
+            None => {}
+            Some(Statement::Org(ref expr)) => {
+                let value = try!(expr.eval(&self.symbols, *pc));
+                *pc = try!(check_range(value, 0, 0xffff, "address"));
+            }
+            Some(Statement::Byte(ref items)) => {
+                for item in items.iter() {
+                    *pc += match *item {
+                        DataItem::Value(_) => 1,
+                        DataItem::Bytes(ref bytes) => bytes.len() as i32,
+                    };
+                }
+            }
+            Some(Statement::Word(ref exprs)) => {
+                *pc += 2 * exprs.len() as i32;
+            }
+            Some(Statement::Constant(ref name, ref expr)) => {
+                if self.symbols.contains_key(name) {
+                    return Err(format!("'{}' is already defined", name));
+                }
+                match expr.eval(&self.symbols, *pc) {
+                    Ok(value) => try!(self.define(name, value)),
+                    Err(_) => {
+                        let line_number = self.modes.len() + 1;
+                        // `*` is the address of this line, not of
+                        // wherever it gets resolved
+                        pending_constants.push((line_number, *pc,
+                                                name.clone(), expr.clone()));
+                    }
+                }
+            }
+            Some(Statement::Instr(instr, ref operand)) => {
+                let value = operand_expr(operand)
+                    .and_then(|expr| expr.eval(&self.symbols, *pc).ok());
+                let am = try!(choose_mode(instr, operand, value,
+                                          self.variant));
+                let AddressDiff(extra_bytes) = am.extra_bytes();
+                *pc += 1 + extra_bytes;
+                mode = Some(am);
+            }
+        }
+
+        self.modes.push(mode);
+        Ok(())
+    }
+
+    fn second_pass(&self, lines: &[Line]) -> Result<Program, AssemblerError> {
+        let mut segments = Vec::new();
+        let mut current = Segment { origin: Address(0), bytes: Vec::new() };
+
+        for (i, line) in lines.iter().enumerate() {
+            let result = self.second_pass_line(line, self.modes[i],
+                                               &mut current, &mut segments);
+            try!(result.map_err(|message| {
+                AssemblerError { line: i + 1, message: message }
+            }));
+        }
+
+        if !current.bytes.is_empty() {
+            segments.push(current);
+        }
+
+        Ok(Program { segments: segments, symbols: self.symbols.clone() })
+    }
+
+    fn second_pass_line(&self, line: &Line, mode: Option<AddressingMode>,
+                        current: &mut Segment, segments: &mut Vec<Segment>)
+        -> Result<(), String>
+    {
+        let pc = current.origin.to_u16() as i32 + current.bytes.len() as i32;
+        if pc > 0x10000 {
+            return Err("program runs past the end of memory".to_string());
+        }
+        let eval = |expr: &Expr| expr.eval(&self.symbols, pc);
+
+        match line.statement {
+            None | Some(Statement::Constant(..)) => {}
+            Some(Statement::Org(ref expr)) => {
+                let origin = Address(try!(eval(expr)) as u16);
+                let previous = ::std::mem::replace(
+                    current, Segment { origin: origin, bytes: Vec::new() });
+                if !previous.bytes.is_empty() {
+                    segments.push(previous);
+                }
+            }
+            Some(Statement::Byte(ref items)) => {
+                for item in items.iter() {
+                    match *item {
+                        DataItem::Value(ref expr) => {
+                            let value = try!(check_range(try!(eval(expr)),
+                                                         -128, 0xff, "byte"));
+                            current.bytes.push(value as u8);
+                        }
+                        DataItem::Bytes(ref bytes) => {
+                            for &byte in bytes.iter() {
+                                current.bytes.push(byte);
+                            }
+                        }
+                    }
+                }
+            }
+            Some(Statement::Word(ref exprs)) => {
+                for expr in exprs.iter() {
+                    let value = try!(check_range(try!(eval(expr)),
+                                                 -0x8000, 0xffff, "word"));
+                    current.bytes.push((value & 0xff) as u8);
+                    current.bytes.push(((value >> 8) & 0xff) as u8);
+                }
+            }
+            Some(Statement::Instr(instr, ref operand)) => {
+                let am = mode.unwrap();
+                current.bytes.push(find_opcode(instr, am, self.variant)
+                                       .unwrap());
+
+                let value = match operand_expr(operand) {
+                    Some(expr) => try!(eval(expr)),
+                    None => 0,
+                };
+
+                match am.extra_bytes() {
+                    AddressDiff(0) => {}
+                    _ if am == AddressingMode::ZeroPageRelative => {
+                        let target = match *operand {
+                            Operand::ZeroPageRelative(_, ref e) =>
+                                try!(eval(e)),
+                            _ => unreachable!(),
+                        };
+                        let zp = try!(check_range(value, 0, 0xff,
+                                                  "zero page address"));
+                        let offset = try!(check_range(target - (pc + 3),
+                                                      -128, 127,
+                                                      "branch offset"));
+                        current.bytes.push(zp as u8);
+                        current.bytes.push((offset & 0xff) as u8);
+                    }
+                    AddressDiff(1) => {
+                        let byte = match am {
+                            AddressingMode::Immediate =>
+                                try!(check_range(value, -128, 0xff, "byte")),
+                            AddressingMode::Relative =>
+                                try!(check_range(value - (pc + 2), -128, 127,
+                                                 "branch offset")),
+                            _ => try!(check_range(value, 0, 0xff,
+                                                  "zero page address")),
+                        };
+                        current.bytes.push((byte & 0xff) as u8);
+                    }
+                    _ => {
+                        let word = try!(check_range(value, 0, 0xffff,
+                                                    "address"));
+                        current.bytes.push((word & 0xff) as u8);
+                        current.bytes.push((word >> 8) as u8);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use memory::Memory;
+
+#[cfg(test)]
+use disassembler::disassemble;
+
+#[cfg(test)]
+fn assemble_bytes(source: &str) -> Vec<u8> {
+    let program = assemble(source, Variant::Nmos).unwrap();
+    assert_eq!(program.segments.len(), 1);
+    program.segments[0].bytes.clone()
+}
+
+#[test]
+fn assemble_addressing_modes_test() {
+    let bytes = assemble_bytes("
+        .org $C000
+        LDA #$01
+        ASL A
+        ASL
+        LDA ($10),Y
+        ADC ($20,X)
+        JMP ($1234)
+        STA $8000,X
+        LDX $7F,Y
+        LDA $10,X
+        LDA $10
+        LDA $0010 + $100
+        RTS
+    ");
+
+    assert_eq!(bytes, vec![
+        0xA9, 0x01,
+        0x0A,
+        0x0A,
+        0xB1, 0x10,
+        0x61, 0x20,
+        0x6C, 0x34, 0x12,
+        0x9D, 0x00, 0x80,
+        0xB6, 0x7F,
+        0xB5, 0x10,
+        0xA5, 0x10,
+        0xAD, 0x10, 0x01,
+        0x60,
+    ]);
+}
+
+#[test]
+fn assemble_labels_test() {
+    let program = assemble("
+        count = 3
+        .org $1000
+start:  LDX #count
+loop:   DEX
+        BNE loop
+        BEQ done
+        JMP start
+done:   JMP (vector)
+vector: .word start
+    ", Variant::Nmos).unwrap();
+
+    assert_eq!(program.symbol("start"), Some(Address(0x1000)));
+    assert_eq!(program.symbol("done"), Some(Address(0x100A)));
+    assert_eq!(program.segments[0].bytes, vec![
+        0xA2, 0x03,         // LDX #3
+        0xCA,               // DEX
+        0xD0, 0xFD,         // BNE loop
+        0xF0, 0x03,         // BEQ done
+        0x4C, 0x00, 0x10,   // JMP start
+        0x6C, 0x0D, 0x10,   // JMP (vector)
+        0x00, 0x10,
+    ]);
+}
+
+#[test]
+fn assemble_zero_page_forward_reference_test() {
+    // Forward references don't know they fit in the zero page, so they get
+    // the absolute form. Backward references get the zero page form.
+    let bytes = assemble_bytes("
+        .org $0000
+        LDA later
+later:  LDA later
+    ");
+    assert_eq!(bytes, vec![0xAD, 0x03, 0x00, 0xA5, 0x03]);
+}
+
+#[test]
+fn assemble_expressions_test() {
+    let program = assemble("
+        base = $1234
+        half = size / 2
+        .org $0300
+        LDA #<base
+        LDX #>base
+        .byte <base, >base + 1, 2 * 3 + 1, -1, 'A', \"hi\"
+        .word base - 4, *
+here    = * + size
+size    = 10
+    ", Variant::Nmos).unwrap();
+
+    assert_eq!(program.symbols.get("half"), Some(&5));
+
+    // `*` in a forward-referencing constant is still its own line's address
+    assert_eq!(program.symbols.get("here"), Some(&0x0319));
+    assert_eq!(program.segments[0].bytes, vec![
+        0xA9, 0x34,
+        0xA2, 0x12,
+        0x34, 0x13, 0x07, 0xFF, 0x41, 0x68, 0x69,
+        0x30, 0x12, 0x0B, 0x03,
+    ]);
+}
+
+#[test]
+fn assemble_segments_test() {
+    let mut memory = Memory::new();
+    let program = assemble("
+        .org $FFFC
+        .word reset
+        .org $8000
+reset:  NOP
+    ", Variant::Nmos).unwrap();
+    program.load_into(&mut memory);
+
+    assert_eq!(program.segments.len(), 2);
+    assert_eq!(memory.get_byte(Address(0xFFFC)), 0x00);
+    assert_eq!(memory.get_byte(Address(0xFFFD)), 0x80);
+    assert_eq!(memory.get_byte(Address(0x8000)), 0xEA);
+}
+
+#[test]
+fn assemble_errors_test() {
+    let error = |source: &str| assemble(source, Variant::Nmos).err().unwrap();
+
+    assert_eq!(error("  LDA missing"), AssemblerError {
+        line: 1,
+        message: "undefined symbol 'missing'".to_string(),
+    });
+    assert_eq!(error("\n  FOO #1").line, 2);
+    assert_eq!(error("  LDA #$100").message, "byte out of range: 256");
+    assert_eq!(error("x: NOP\nx: NOP").message, "'x' is already defined");
+    assert_eq!(error("  JMP $10,X").message,
+               "addressing mode not supported by JMP");
+    assert_eq!(error(".org $1000\n  BNE $1100").message,
+               "branch offset out of range: 254");
+}
+
+#[test]
+fn assemble_disassemble_round_trip_test() {
+    let source = ["LDA #$01", "ASL A", "LDA ($10),Y", "ADC ($20,X)",
+                  "JMP ($1234)", "STA $8000,X", "LDX $7F,Y", "BNE $C002",
+                  "RTS"];
+
+    let mut memory = Memory::new();
+    let source_text = format!("  .org $C000\n  {}", source.connect("\n  "));
+    let program = assemble(&source_text, Variant::Nmos).unwrap();
+    program.load_into(&mut memory);
+
+    let end = Address(0xC000 + program.segments[0].bytes.len() as u16 - 1);
+    let text: Vec<String> = disassemble(&memory, Address(0xC000), end,
+                                        Variant::Nmos)
+                                .into_iter()
+                                .map(|instr| instr.text)
+                                .collect();
+    assert_eq!(text, source.to_vec());
+}
+
+#[test]
+fn assemble_undocumented_test() {
+    // The documented NOP and SBC are picked over their undocumented twins
+    let bytes = assemble_bytes("NOP\nSBC #1\nLAX $10\nDCP $1234,Y\nJAM");
+    assert_eq!(bytes, vec![0xEA, 0xE9, 0x01, 0xA7, 0x10, 0xDB, 0x34, 0x12,
+                           0x02]);
+}
+
+#[test]
+fn assemble_cmos_test() {
+    let program = assemble("
+        .org $0200
+start:  LDA ($10)
+        JMP ($3000,X)
+        JMP ($3000)
+        BBS0 $20,start
+        STZ $30
+        BRA start
+    ", Variant::Cmos).unwrap();
+    assert_eq!(program.segments[0].bytes, vec![
+        0xB2, 0x10,
+        0x7C, 0x00, 0x30,
+        0x6C, 0x00, 0x30,
+        0x8F, 0x20, 0xF5,
+        0x64, 0x30,
+        0x80, 0xF1,
+    ]);
+
+    // None of these exist on the NMOS 6502
+    let error = |source: &str| assemble(source, Variant::Nmos).err().unwrap();
+    assert_eq!(error("LDA ($10)").message,
+               "addressing mode not supported by LDA");
+    assert_eq!(error("STZ $30").message,
+               "unknown instruction or directive 'STZ'");
+    assert_eq!(error("BBR0 $20,$0200").message,
+               "unknown instruction or directive 'BBR0'");
+}
@@ -28,129 +28,623 @@
 extern crate emu6502;
 
 #[cfg(not(test))]
-use emu6502::machine;
+use std::cell::RefCell;
+
+#[cfg(not(test))]
+use std::env;
+
+#[cfg(not(test))]
+use std::fs::File;
+
+#[cfg(not(test))]
+use std::io;
+
+#[cfg(not(test))]
+use std::io::{BufWriter, Read, Write};
+
+#[cfg(not(test))]
+use std::rc::Rc;
 
 #[cfg(not(test))]
 use emu6502::address::Address;
 
+#[cfg(not(test))]
+use emu6502::assembler;
+
+#[cfg(not(test))]
+use emu6502::bus::Bus;
+
+#[cfg(not(test))]
+use emu6502::instruction::Variant;
+
+#[cfg(not(test))]
+use emu6502::machine::{Machine, StopReason};
+
+#[cfg(not(test))]
+use emu6502::memory::Memory;
+
+#[cfg(not(test))]
+use emu6502::monitor::Monitor;
+
+#[cfg(not(test))]
+use emu6502::registers::{StackPointer, Status};
+
+#[cfg(not(test))]
+use emu6502::trace;
+
+#[cfg(not(test))]
+static USAGE: &'static str = "\
+usage: emu6502 [options] [program]
+
+Loads a binary image into memory and runs it until it hits an invalid opcode,
+a JAM or a limit, then prints the registers. With no program, runs a small
+demo.
+
+options:
+    -l, --load ADDR           load the program at ADDR (default $0000)
+    -s, --start ADDR          start at ADDR instead of the reset vector
+    -c, --cycles N            stop after N cycles
+    -i, --instructions N      stop after N instructions
+    -m, --monitor             start the monitor instead of running
+        --load-state FILE     carry on from a save state instead of starting
+                              from the program
+        --save-state FILE     save the machine's state when it stops
+        --trace FILE          write a line of trace per instruction to FILE,
+                              in the layout of nestest.log
+        --diff-log FILE       run against a log in that layout and report
+                              the first line that differs, within the limits
+        --strict              treat undocumented opcodes as invalid
+        --stop-on-brk         stop at a BRK instead of running it
+        --cmos                emulate a 65C02 instead of an NMOS 6502
+        --nes                 emulate the NES's 2A03, which has no decimal mode
+    -h, --help                show this message
+
+Numbers can be decimal, $hex or 0xhex.
+
+The exit status is 0 when the program stops itself with a JAM, STP or (with
+--stop-on-brk) BRK, 1 on an error, 2 when a limit is reached and 3 on an
+invalid opcode. With --diff-log it's 0 when every line of the log matches, 2
+when a limit is reached first and 4 when the run differs from the log or
+stops before the log ends.";
+
+#[cfg(not(test))]
+const EXIT_STOPPED: i32 = 0;
+
+#[cfg(not(test))]
+const EXIT_ERROR: i32 = 1;
+
+#[cfg(not(test))]
+const EXIT_LIMIT: i32 = 2;
+
+#[cfg(not(test))]
+const EXIT_INVALID_OPCODE: i32 = 3;
+
+#[cfg(not(test))]
+const EXIT_DIVERGED: i32 = 4;
+
+#[cfg(not(test))]
+struct Options {
+    program:          Option<String>,
+    load_address:     Address,
+    start_address:    Option<Address>,
+    max_cycles:       Option<u64>,
+    max_instructions: Option<u64>,
+    monitor:          bool,
+    strict:           bool,
+    stop_on_brk:      bool,
+    variant:          Variant,
+    load_state:       Option<String>,
+    save_state:       Option<String>,
+    trace:            Option<String>,
+    diff_log:         Option<String>,
+}
+
+#[cfg(not(test))]
+fn parse_number(text: &str) -> Result<u64, String> {
+    let (digits, radix) = if text.starts_with("$") {
+        (&text[1..], 16)
+    } else if text.starts_with("0x") || text.starts_with("0X") {
+        (&text[2..], 16)
+    } else {
+        (text, 10)
+    };
+
+    if digits.is_empty() {
+        return Err(format!("invalid number: {}", text));
+    }
+
+    let mut value: u64 = 0;
+    for c in digits.chars() {
+        let digit = match c.to_digit(radix) {
+            Some(digit) => digit as u64,
+            None => return Err(format!("invalid number: {}", text)),
+        };
+        value = match value.checked_mul(radix as u64)
+                           .and_then(|v| v.checked_add(digit)) {
+            Some(value) => value,
+            None => return Err(format!("number too large: {}", text)),
+        };
+    }
+    Ok(value)
+}
+
+#[cfg(not(test))]
+fn parse_address(text: &str) -> Result<Address, String> {
+    let value = try!(parse_number(text));
+    if value > 0xFFFF {
+        return Err(format!("address out of range: {}", text));
+    }
+    Ok(Address(value as u16))
+}
+
+// Returns None if the usage message was asked for
+#[cfg(not(test))]
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        program:          None,
+        load_address:     Address(0),
+        start_address:    None,
+        max_cycles:       None,
+        max_instructions: None,
+        monitor:          false,
+        strict:           false,
+        stop_on_brk:      false,
+        variant:          Variant::Nmos,
+        load_state:       None,
+        save_state:       None,
+        trace:            None,
+        diff_log:         None,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i][..];
+        i += 1;
+
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+
+        if arg == "-m" || arg == "--monitor" {
+            options.monitor = true;
+            continue;
+        }
+
+        if arg == "--strict" {
+            options.strict = true;
+            continue;
+        }
+
+        if arg == "--stop-on-brk" {
+            options.stop_on_brk = true;
+            continue;
+        }
+
+        if arg == "--cmos" {
+            options.variant = Variant::Cmos;
+            continue;
+        }
+
+        if arg == "--nes" {
+            options.variant = Variant::Ricoh2A03;
+            continue;
+        }
+
+        if !arg.starts_with("-") {
+            if options.program.is_some() {
+                return Err(format!("unexpected argument: {}", arg));
+            }
+            options.program = Some(arg.to_string());
+            continue;
+        }
+
+        let known = ["-l", "--load", "-s", "--start", "-c", "--cycles",
+                     "-i", "--instructions", "--load-state", "--save-state",
+                     "--trace", "--diff-log"];
+        if !known.contains(&arg) {
+            return Err(format!("unknown option: {}", arg));
+        }
+
+        let value = match args.get(i) {
+            Some(value) => &value[..],
+            None => return Err(format!("{} needs a value", arg)),
+        };
+        i += 1;
+
+        match arg {
+            "-l" | "--load" =>
+                options.load_address = try!(parse_address(value)),
+            "-s" | "--start" =>
+                options.start_address = Some(try!(parse_address(value))),
+            "-c" | "--cycles" =>
+                options.max_cycles = Some(try!(parse_number(value))),
+            "-i" | "--instructions" =>
+                options.max_instructions = Some(try!(parse_number(value))),
+            "--load-state" => options.load_state = Some(value.to_string()),
+            "--save-state" => options.save_state = Some(value.to_string()),
+            "--trace" => options.trace = Some(value.to_string()),
+            "--diff-log" => options.diff_log = Some(value.to_string()),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(Some(options))
+}
+
+#[cfg(not(test))]
+fn load_memory(options: &Options) -> Result<Memory, String> {
+    let mut memory = Memory::new();
+
+    match options.program {
+        None => {
+            // The 65C02 has no JAM, but STP does the same job
+            let source = match options.variant {
+                Variant::Nmos | Variant::Ricoh2A03 => DEMO.to_string(),
+                Variant::Cmos => DEMO.replace("JAM ", "STP "),
+            };
+            let result = assembler::assemble(&source, options.variant);
+            let program = try!(result.map_err(|e| {
+                format!("couldn't assemble the demo: {}", e)
+            }));
+            program.load_into(&mut memory);
+        }
+        Some(ref path) => {
+            let mut image = Vec::new();
+            let result = File::open(path)
+                             .and_then(|mut file| file.read_to_end(&mut image));
+            try!(result.map_err(|e| format!("couldn't read {}: {}", path, e)));
+
+            let load = options.load_address.to_u16() as usize;
+            if load + image.len() > 0x10000 {
+                return Err(format!("{} doesn't fit in memory at ${:04X}",
+                                   path, load));
+            }
+            memory.set_bytes(options.load_address, &image);
+        }
+    }
+
+    Ok(memory)
+}
+
+#[cfg(not(test))]
+fn load_state(machine: &mut Machine, path: &str) -> Result<(), String> {
+    let mut state = Vec::new();
+    let result = File::open(path).and_then(|mut file| {
+        file.read_to_end(&mut state)
+    });
+    try!(result.map_err(|e| format!("couldn't read {}: {}", path, e)));
+    machine.load_state(&state).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(not(test))]
+fn save_state(machine: &Machine, path: &str) -> Result<(), String> {
+    let result = File::create(path).and_then(|mut file| {
+        file.write_all(&machine.save_state())
+    });
+    result.map_err(|e| format!("couldn't write {}: {}", path, e))
+}
+
+#[cfg(not(test))]
+fn start_trace(machine: &mut Machine, path: &str) -> Result<(), String> {
+    let file = try!(File::create(path).map_err(|e| {
+        format!("couldn't write {}: {}", path, e)
+    }));
+
+    // Flushed when the machine is dropped
+    let mut out = BufWriter::new(file);
+    machine.set_tracer(move |line: &str| {
+        let _ = writeln!(out, "{}", line);
+    });
+    Ok(())
+}
+
+// How far a run has got through the reference log
+#[cfg(not(test))]
+struct LogDiff {
+    expected:   Vec<String>,
+    matched:    usize,
+
+    // The line that differed and what was different about it
+    divergence: Option<(String, String)>,
+}
+
+// Runs the machine against a reference log until a line differs, the log
+// runs out, the machine stops or a limit is reached, and describes how it
+// went along with the exit status for it. Unless `seed` is false (when
+// carrying on from a save state), the registers and cycle count are first set
+// from the log's first line, as logs start from wherever the emulator that
+// wrote them left the processor.
+#[cfg(not(test))]
+fn diff_log(machine: &mut Machine, options: &Options, path: &str, seed: bool)
+    -> Result<(String, i32), String>
+{
+    let mut text = String::new();
+    let result = File::open(path).and_then(|mut file| {
+        file.read_to_string(&mut text)
+    });
+    try!(result.map_err(|e| format!("couldn't read {}: {}", path, e)));
+
+    let expected: Vec<String> = text.lines()
+                                    .filter(|line| !line.trim().is_empty())
+                                    .map(|line| line.to_string())
+                                    .collect();
+    let first = match expected.first().map(|line| trace::parse_line(line)) {
+        Some(Some(first)) => first,
+        Some(None) => return Err(format!("{}:1: not a trace line", path)),
+        None => return Err(format!("{} is empty", path)),
+    };
+
+    if seed {
+        machine.registers.program_counter = Address(first.pc);
+        machine.registers.accumulator = first.a as i8;
+        machine.registers.index_x = first.x as i8;
+        machine.registers.index_y = first.y as i8;
+        machine.registers.status = Status::from_bits_truncate(first.p);
+        machine.registers.stack_pointer = StackPointer(first.sp);
+        if let Some(cycles) = first.cycles {
+            machine.cycles = cycles;
+        }
+    }
+
+    let total = expected.len();
+    let state = Rc::new(RefCell::new(LogDiff { expected:   expected,
+                                               matched:    0,
+                                               divergence: None }));
+    let tracer_state = state.clone();
+    machine.set_tracer(move |line: &str| {
+        let mut state = tracer_state.borrow_mut();
+        if state.divergence.is_some() || state.matched == state.expected.len()
+        {
+            return;
+        }
+        match trace::compare_lines(&state.expected[state.matched], line) {
+            Some(differences) =>
+                state.divergence = Some((line.to_string(), differences)),
+            None => state.matched += 1,
+        }
+    });
+
+    // The tracer sees each instruction before it runs, so this stops one
+    // instruction after the divergence
+    let mut instructions: u64 = 0;
+    let mut stop = None;
+    loop {
+        {
+            let state = state.borrow();
+            if state.divergence.is_some() || state.matched == total {
+                break;
+            }
+        }
+        if let Some(limit) = limit_reached(options, instructions,
+                                           machine.cycles) {
+            stop = Some((limit.to_string(), EXIT_LIMIT));
+            break;
+        }
+        if let Some(reason) = machine.step() {
+            stop = Some((reason.to_string(), EXIT_DIVERGED));
+            break;
+        }
+        instructions += 1;
+    }
+    machine.clear_tracer();
+
+    let state = state.borrow();
+    if let Some((ref actual, ref differences)) = state.divergence {
+        let report = format!("{}:{}: first difference: {}\n  expected: {}\n  \
+                              actual:   {}",
+                             path, state.matched + 1, differences,
+                             state.expected[state.matched], actual);
+        return Ok((report, EXIT_DIVERGED));
+    }
+    Ok(match stop {
+        Some((reason, status)) if state.matched < total =>
+            (format!("{}: {} of {} lines match, then stopped: {}",
+                     path, state.matched, total, reason), status),
+        _ => (format!("{}: all {} lines match", path, total), EXIT_STOPPED),
+    })
+}
+
+// Adds up a value fetched with each of ADC's addressing modes
+#[cfg(not(test))]
+static DEMO: &'static str = "
+data    = $8000
+
+        .org $0000
+        .byte $00
+zp:     .byte $02               ; ADC ZeroPage target
+        .byte $00
+        .byte $04               ; ADC ZeroPageX target
+        .byte $00, $00, $00, $00
+ind_x:  .word data + $10        ; ADC IndexedIndirectX address
+        .byte $00, $00, $00, $00, $00
+ind_y:  .word data + $08        ; ADC IndirectIndexedY address
+
+        .org $4000
+start:  LDA #$01
+        ADC #$07
+        ADC zp
+        LDX #$01
+        ADC zp + 1,X
+        ADC data + 1
+        LDX #$08
+        ADC data,X
+        LDY #$04
+        ADC data,Y
+        LDX #$05
+        ADC (ind_x - 5,X)
+        LDY #$10
+        ADC (ind_y),Y
+        NOP                     ; :)
+        JAM                     ; Stop the processor -- the end!
+
+        .org data
+        .byte $00, $09, $00, $00 ; ADC Absolute target
+        .byte $40, $00, $00, $00 ; ADC AbsoluteY target
+        .byte $11, $00, $00, $00 ; ADC AbsoluteX target
+        .byte $00, $00, $00, $00
+        .byte $12, $00, $00, $00 ; ADC IndexedIndirectX target
+        .byte $00, $00, $00, $00
+        .byte $06               ; ADC IndirectIndexedY target
+
+        .org $FFFC
+        .word start             ; reset vector
+";
+
+// Reads commands from stdin until end of input or Q
+#[cfg(not(test))]
+fn run_monitor(machine: Machine) {
+    let mut monitor = Monitor::new(machine);
+    let stdin = io::stdin();
+
+    println!("{}", monitor.command("R"));
+    loop {
+        print!("* ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        let command = line.trim();
+        if command == "q" || command == "Q" {
+            break;
+        }
+
+        let output = monitor.command(command);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+    }
+}
+
+// Which limit, if any, has been reached. They're checked before each
+// instruction, so a limit that's already been reached stops the machine
+// before it runs anything.
+#[cfg(not(test))]
+fn limit_reached(options: &Options, instructions: u64, cycles: u64)
+    -> Option<&'static str>
+{
+    if options.max_instructions.map_or(false, |max| instructions >= max) {
+        Some("instruction limit reached")
+    } else if options.max_cycles.map_or(false, |max| cycles >= max) {
+        Some("cycle limit reached")
+    } else {
+        None
+    }
+}
+
+// Reports an error on stderr, returning the exit status for it
+#[cfg(not(test))]
+fn error(message: &str) -> i32 {
+    let _ = writeln!(&mut io::stderr(), "emu6502: {}", message);
+    EXIT_ERROR
+}
+
+// Stopping at a limit is told apart from the program stopping itself, so
+// that scripts can tell a test ROM that finished from one that hung
+#[cfg(not(test))]
+fn exit_status(reason: StopReason) -> i32 {
+    match reason {
+        StopReason::Jam(_) | StopReason::Stp | StopReason::Brk(_) =>
+            EXIT_STOPPED,
+        StopReason::InvalidOpcode(..) => EXIT_INVALID_OPCODE,
+        StopReason::Breakpoint(_) | StopReason::BudgetExhausted |
+        StopReason::ConditionMet | StopReason::HistoryExhausted => EXIT_LIMIT,
+    }
+}
+
 #[cfg(not(test))]
 fn main() {
-    let mut machine = machine::Machine::new();
-
-    // "Load" a program
-
-    // JAM: FIXME: What's the syntax for specifying the array element type,
-    //             but not the length? (For a fixed-size array)
-
-    let zero_page_data: [u8; 17] = [
-        // ZeroPage data start
-        0x00,
-        0x02, // ADC ZeroPage target
-        0x00,
-        0x04, // ADC ZeroPageX target
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x10, // ADC IndexedIndirectX address
-        0x80, // ADC IndexedIndirectX address
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x08, // ADC IndirectIndexedY address
-        0x80, // ADC IndirectIndexedY address
-    ];
-
-    let program: [u8; 33] = [
-        // Code start
-        0xA9, // LDA Immediate
-        0x01, //     Immediate operand
-
-        0x69, // ADC Immediate
-        0x07, //     Immediate operand
-
-        0x65, // ADC ZeroPage
-        0x01, //     ZeroPage operand
-
-        0xA2, // LDX Immediate
-        0x01, //     Immediate operand
-
-        0x75, // ADC ZeroPageX
-        0x02, //     ZeroPageX operand
-
-        0x6D, // ADC Absolute
-        0x01, //     Absolute operand
-        0x80, //     Absolute operand
-
-        0xA2, // LDX immediate
-        0x08, //     Immediate operand
-
-        0x7D, // ADC AbsoluteX
-        0x00, //     AbsoluteX operand
-        0x80, //     AbsoluteX operand
-
-        0xA0, // LDY immediate
-        0x04, //     Immediate operand
-
-        0x79, // ADC AbsoluteY
-        0x00, //     AbsoluteY operand
-        0x80, //     AbsoluteY operand
-
-        0xA2, // LDX immediate
-        0x05, //     Immediate operand
-
-        0x61, // ADC IndexedIndirectX
-        0x03, //     IndexedIndirectX operand
-
-        0xA0, // LDY immediate
-        0x10, //     Immediate operand
-
-        0x71, // ADC IndirectIndexedY
-        0x0F, //     IndirectIndexedY operand
-
-        0xEA, // NOP :)
-
-        0xFF, // Something invalid -- the end!
-    ];
-
-    let data: [u8; 25] = [
-        0x00,
-        0x09, // ADC Absolute target
-        0x00,
-        0x00,
-        0x40, // ADC AbsoluteY target
-        0x00,
-        0x00,
-        0x00,
-        0x11, // ADC AbsoluteX target
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x12, // ADC IndexedIndirectX target
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x00,
-        0x06, // ADC IndirectIndexedY target
-    ];
-
-    machine.memory.set_bytes(Address(0x0000), &zero_page_data);
-    machine.memory.set_bytes(Address(0x4000), &program);
-    machine.memory.set_bytes(Address(0x8000), &data);
-
-    machine.registers.program_counter = Address(0x4000);
-
-    machine.run();
+    // Everything has been dropped (and flushed) by the time this is set
+    let status = run();
+    env::set_exit_status(status);
+}
+
+// Returns the exit status
+#[cfg(not(test))]
+fn run() -> i32 {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return EXIT_STOPPED;
+        }
+        Err(message) => {
+            let _ = writeln!(&mut io::stderr(), "emu6502: {}\n\n{}", message,
+                             USAGE);
+            return EXIT_ERROR;
+        }
+    };
+
+    let memory = match load_memory(&options) {
+        Ok(memory) => memory,
+        Err(message) => return error(&message),
+    };
 
+    // Going through the reset sequence picks up the reset vector from the
+    // loaded image.
+    let mut machine = Machine::with_variant(memory, options.variant);
+    machine.strict = options.strict;
+    machine.stop_on_brk = options.stop_on_brk;
+    if let Some(ref path) = options.load_state {
+        if let Err(message) = load_state(&mut machine, path) {
+            return error(&message);
+        }
+    }
+    if let Some(start) = options.start_address {
+        machine.registers.program_counter = start;
+    }
+
+    if let Some(ref path) = options.diff_log {
+        let seed = options.load_state.is_none();
+        return match diff_log(&mut machine, &options, path, seed) {
+            Ok((report, status)) => {
+                println!("{}", report);
+                status
+            }
+            Err(message) => error(&message),
+        };
+    }
+
+    if let Some(ref path) = options.trace {
+        if let Err(message) = start_trace(&mut machine, path) {
+            return error(&message);
+        }
+    }
+
+    if options.monitor {
+        run_monitor(machine);
+        return EXIT_STOPPED;
+    }
+
+    let mut instructions: u64 = 0;
+    let stop;
+    loop {
+        if let Some(limit) = limit_reached(&options, instructions,
+                                           machine.cycles) {
+            stop = (limit.to_string(), EXIT_LIMIT);
+            break;
+        }
+        if let Some(reason) = machine.step() {
+            stop = (reason.to_string(), exit_status(reason));
+            break;
+        }
+        instructions += 1;
+    }
+    let (reason, status) = stop;
+
+    println!("Stopped after {} instructions: {}\n", instructions, reason);
     println!("{:?}", machine);
-}
 
+    if let Some(ref path) = options.save_state {
+        if let Err(message) = save_state(&machine, path) {
+            return error(&message);
+        }
+    }
+
+    status
+}
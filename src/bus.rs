@@ -0,0 +1,112 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use address::{Address, AddressDiff, LongAddress};
+use code_cache::DecodeCache;
+use save_state::{StateReader, StateWriter};
+
+// Everything the CPU can see through its address and data lines. The machine
+// is generic over this, so that ROM, mirrored RAM and memory-mapped I/O can be
+// modelled by whatever decodes the addresses. `memory::Memory` is the plain
+// 64 KiB of RAM implementation.
+pub trait Bus {
+    // Reads the byte at the given address, as the CPU would. This may have
+    // side effects (for example, reading a device's status register might
+    // clear it).
+    fn get_byte(&mut self, address: Address) -> u8;
+
+    // Reads the byte at the given address without any side effects, for
+    // debuggers, disassemblers and the like.
+    fn peek_byte(&self, address: Address) -> u8;
+
+    // Sets the byte at the given address to the given value and returns the
+    // previous value at the address.
+    fn set_byte(&mut self, address: Address, value: u8) -> u8;
+
+    fn set_bytes(&mut self, start: Address, values: &[u8]) {
+        for (i, value) in values.iter().enumerate() {
+            self.set_byte(start + AddressDiff(i as i32), *value);
+        }
+    }
+
+    // Called by the machine after each instruction with the number of cycles
+    // it took (or by `CycleMachine` after every cycle), so that anything on
+    // the bus can keep time with the CPU.
+    fn tick(&mut self, _cycles: u64) {
+    }
+
+    // Whether anything on the bus is holding the IRQ line low.
+    fn irq_asserted(&self) -> bool {
+        false
+    }
+
+    // The cache of decoded instructions that comes along with the bus, if
+    // there is one (see `code_cache::CodeCache`). By default there isn't.
+    fn decode_cache(&mut self) -> Option<&mut DecodeCache> {
+        None
+    }
+
+    // Saves everything on the bus for a save state, and restores it again.
+    // By default this is the 64 KiB that `peek_byte` sees, written back with
+    // `set_byte`, which suits RAM and ROM. Buses with devices on them should
+    // save the devices' own state instead of what they show the CPU.
+    fn save_state(&self, out: &mut StateWriter) {
+        for i in 0..0x10000 {
+            out.byte(self.peek_byte(Address(i as u16)));
+        }
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), String> {
+        let bytes = try!(input.slice(0x10000));
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.set_byte(Address(i as u16), byte);
+        }
+        Ok(())
+    }
+}
+
+// The 65C816's equivalent of `Bus`, with 24-bit addresses.
+pub trait LongBus {
+    fn get_byte(&mut self, address: LongAddress) -> u8;
+
+    fn peek_byte(&self, address: LongAddress) -> u8;
+
+    fn set_byte(&mut self, address: LongAddress, value: u8) -> u8;
+
+    fn set_bytes(&mut self, start: LongAddress, values: &[u8]) {
+        for (i, value) in values.iter().enumerate() {
+            self.set_byte(start + AddressDiff(i as i32), *value);
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+    }
+
+    fn irq_asserted(&self) -> bool {
+        false
+    }
+}
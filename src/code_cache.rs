@@ -0,0 +1,310 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use instruction::{PredecodedInstr, Variant};
+use memory::Memory;
+use save_state::{StateReader, StateWriter};
+
+// Keeps hold of the machine's decoded instructions, so that code that runs
+// over and over is only decoded once. Whatever implements this has to forget
+// an instruction once any of its bytes are written to. Instructions decode
+// differently on each variant, so they're only handed back to the variant
+// that decoded them.
+pub trait DecodeCache {
+    fn get(&self, address: Address, variant: Variant)
+        -> Option<PredecodedInstr>;
+
+    fn insert(&mut self, address: Address, variant: Variant,
+              instr: PredecodedInstr);
+}
+
+// A bus that keeps hold of the machine's decoded instructions for whatever
+// bus it wraps, so that code that runs over and over is only decoded once:
+//
+//     let mut machine = Machine::with_bus(CodeCache::with_bus(memory));
+//
+// A cached instruction skips the opcode and operand reads, the opcode table
+// lookups and, for addressing modes that don't depend on the registers,
+// working out the operand. That takes about a quarter off the time each
+// instruction takes (see `benches/interpreter.rs`).
+//
+// Writes through the cache forget any instruction they overlap, so
+// self-modifying code still works. But a cached instruction is run without
+// reading its bytes from the bus again, so the cache can't see code change
+// any other way. Anything that does that (writing to `bus` directly, a bus
+// that switches banks) has to call `flush`, which is cheap. Code shouldn't
+// run from memory-mapped I/O at all: reading it has no side effects after
+// the first time, and the device's later values are never seen.
+pub struct CodeCache<B = Memory> {
+    pub bus: B,
+
+    // Indexed by the address of the opcode, along with the generation it
+    // was cached in and the variant that decoded it. Only entries from the
+    // current generation are valid.
+    entries:    Vec<Option<(u32, Variant, PredecodedInstr)>>,
+    generation: u32,
+}
+
+impl CodeCache {
+    pub fn new() -> CodeCache {
+        CodeCache::with_bus(Memory::new())
+    }
+}
+
+impl<B: Bus> CodeCache<B> {
+    pub fn with_bus(bus: B) -> CodeCache<B> {
+        CodeCache { bus:        bus,
+                    entries:    (0..0x10000).map(|_| None).collect(),
+                    generation: 0 }
+    }
+
+    // Forgets every instruction, by starting a new generation rather than
+    // clearing the entries
+    pub fn flush(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+
+        // Entries from the first generation would be valid again
+        if self.generation == 0 {
+            for entry in self.entries.iter_mut() {
+                *entry = None;
+            }
+        }
+    }
+
+    // Forgets any instruction that the byte at the given address is part of.
+    // Instructions are at most three bytes long, so only the two addresses
+    // before it need checking.
+    fn invalidate(&mut self, address: Address) {
+        for back in 0..3 {
+            let start = address + AddressDiff(-back);
+            let index = start.to_usize();
+            let overlaps = match self.entries[index] {
+                Some((generation, _, ref instr)) =>
+                    generation == self.generation
+                    && instr.len() > AddressDiff(back),
+                None => false,
+            };
+            if overlaps {
+                self.entries[index] = None;
+            }
+        }
+    }
+}
+
+impl<B: Bus> Bus for CodeCache<B> {
+    fn get_byte(&mut self, address: Address) -> u8 {
+        self.bus.get_byte(address)
+    }
+
+    fn peek_byte(&self, address: Address) -> u8 {
+        self.bus.peek_byte(address)
+    }
+
+    fn set_byte(&mut self, address: Address, value: u8) -> u8 {
+        self.invalidate(address);
+        self.bus.set_byte(address, value)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.bus.tick(cycles);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.bus.irq_asserted()
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        self.bus.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), String> {
+        self.flush();
+        self.bus.load_state(input)
+    }
+
+    fn decode_cache(&mut self) -> Option<&mut DecodeCache> {
+        Some(self)
+    }
+}
+
+impl<B: Bus> DecodeCache for CodeCache<B> {
+    fn get(&self, address: Address, variant: Variant)
+        -> Option<PredecodedInstr>
+    {
+        match self.entries[address.to_usize()] {
+            Some((generation, decoded_by, instr))
+                if generation == self.generation && decoded_by == variant =>
+                    Some(instr),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, address: Address, variant: Variant,
+              instr: PredecodedInstr) {
+        self.entries[address.to_usize()] =
+            Some((self.generation, variant, instr));
+    }
+}
+
+#[cfg(test)]
+use assembler;
+
+#[cfg(test)]
+use machine::{Machine, StopReason};
+
+#[test]
+fn self_modifying_code_test() {
+    let program = assembler::assemble("
+        .org $0200
+start:  LDX #$00
+loop:   LDA #$00                ; the STA below rewrites this operand
+        CLC
+        ADC #$01
+        STA loop + 1
+        INX
+        CPX #$03
+        BNE loop
+        JAM
+
+        .org $FFFC
+        .word start
+", Variant::Nmos).unwrap();
+
+    let mut cache = CodeCache::new();
+    program.load_into(&mut cache);
+    let mut machine = Machine::with_bus(cache);
+
+    // Each time round the LDA loads what the previous STA wrote
+    let mut values = Vec::new();
+    while machine.step().is_none() {
+        if machine.registers.program_counter == Address(0x0204) {
+            values.push(machine.registers.accumulator);
+        }
+    }
+    assert_eq!(values, vec![0, 1, 2]);
+    assert_eq!(machine.memory.peek_byte(Address(0x0203)), 0x03);
+
+    // Rewriting the opcode takes effect too
+    machine.registers.program_counter = Address(0x0202);
+    machine.memory.set_byte(Address(0x0202), 0xA0); // LDY #$03
+    machine.step();
+    assert_eq!(machine.registers.index_y, 0x03);
+}
+
+#[test]
+fn matches_machine_test() {
+    let program = assembler::assemble("
+        .org $0300
+start:  LDY #$00
+        LDX #$10
+copy:   LDA $0400,Y
+        STA next + 1            ; patch the immediate operand below
+next:   ADC #$00
+        STA ($F0),Y
+        INY
+        DEX
+        BNE copy
+        JSR sub
+        JAM
+
+sub:    LDA ($F0),Y
+        ROL A
+        RTS
+
+        .org $00F0
+        .word $0500
+
+        .org $0400
+        .byte $01, $02, $03, $04, $05, $06, $07, $08
+        .byte $F9, $FA, $FB, $FC, $FD, $FE, $FF, $80
+
+        .org $FFFC
+        .word start
+", Variant::Nmos).unwrap();
+
+    let mut memory = Memory::new();
+    program.load_into(&mut memory);
+    let mut plain = Machine::with_bus(memory);
+    let mut cached = Machine::with_bus(CodeCache::with_bus(memory));
+
+    let reason = plain.run();
+    match reason {
+        StopReason::Jam(_) => {}
+        _ => panic!("stopped with {}", reason),
+    }
+    assert_eq!(cached.run(), reason);
+    assert_eq!(cached.registers, plain.registers);
+    assert_eq!(cached.cycles, plain.cycles);
+    for i in 0..0x10000 {
+        let address = Address(i as u16);
+        assert_eq!(cached.memory.peek_byte(address),
+                   plain.memory.peek_byte(address));
+    }
+}
+
+#[test]
+fn variant_change_test() {
+    // $1A is a one-byte NOP on the NMOS 6502, and INC A on the 65C02
+    let mut cache = CodeCache::new();
+    cache.set_byte(Address(0x0200), 0x1A);
+    let mut machine = Machine::with_bus(cache);
+
+    machine.registers.program_counter = Address(0x0200);
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0);
+
+    machine.variant = Variant::Cmos;
+    machine.registers.program_counter = Address(0x0200);
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 1);
+}
+
+#[test]
+fn flush_test() {
+    let mut cache = CodeCache::new();
+    cache.set_byte(Address(0x0200), 0xE8); // INX
+    let mut machine = Machine::with_bus(cache);
+
+    machine.registers.program_counter = Address(0x0200);
+    machine.step();
+    assert_eq!(machine.registers.index_x, 1);
+
+    // Behind the cache's back, so the INX is still cached
+    machine.memory.bus.set_byte(Address(0x0200), 0xC8); // INY
+    machine.registers.program_counter = Address(0x0200);
+    machine.step();
+    assert_eq!(machine.registers.index_x, 2);
+    assert_eq!(machine.registers.index_y, 0);
+
+    machine.memory.flush();
+    machine.registers.program_counter = Address(0x0200);
+    machine.step();
+    assert_eq!(machine.registers.index_x, 2);
+    assert_eq!(machine.registers.index_y, 1);
+}
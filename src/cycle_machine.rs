@@ -0,0 +1,879 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// A cycle-stepped NMOS 6502. `Machine` runs a whole instruction at a time,
+// which is all most software needs, but some hardware (a video chip stealing
+// cycles, say, or a device with read side effects) has to see every bus cycle
+// as it happens. `CycleMachine::clock` advances by a single cycle and returns
+// the access made on it, including the dummy reads and the double writes of
+// read-modify-write instructions that the NMOS 6502 makes along the way.
+//
+// Only the bus activity is modelled here. The instructions' effects on the
+// registers are worked out by a `Machine` on a one-byte bus (`Latch`), so the
+// two cores can't disagree about what an instruction does.
+
+use std::fmt;
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use instruction::{AddressingMode, DecodedInstr, Instruction, OpInput, Variant};
+use machine::{Machine, StopReason};
+use memory::{Memory, IRQ_INTERRUPT_VECTOR_LO, NMI_INTERRUPT_VECTOR_LO,
+             RESET_VECTOR_LO};
+use registers::{Registers, StackPointer};
+use registers::{PS_DISABLE_INTERRUPTS, PS_BRK, PS_UNUSED};
+
+// One clock cycle's worth of bus activity
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub struct BusCycle {
+    pub address: Address,
+    pub data:    u8,
+    pub write:   bool,
+}
+
+// A bus holding a single byte, whatever the address. Executing an
+// instruction on it shows what the instruction would do with that byte, and
+// what it would store and where.
+struct Latch {
+    value:   u8,
+    address: Address,
+}
+
+impl Bus for Latch {
+    fn get_byte(&mut self, _address: Address) -> u8 {
+        self.value
+    }
+
+    fn peek_byte(&self, _address: Address) -> u8 {
+        self.value
+    }
+
+    fn set_byte(&mut self, address: Address, value: u8) -> u8 {
+        let old_value = self.value;
+        self.value = value;
+        self.address = address;
+        old_value
+    }
+}
+
+// Returned by `CycleMachine::with_variant` for a processor whose bus cycles
+// it doesn't model
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedVariant(pub Variant);
+
+impl fmt::Display for UnsupportedVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the cycle-stepped core doesn't support the {:?} variant",
+               self.0)
+    }
+}
+
+// How an instruction uses its memory operand, which decides its bus cycles
+#[derive(Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+fn access(instr: Instruction) -> Access {
+    match instr {
+        Instruction::STA | Instruction::STX | Instruction::STY |
+        Instruction::SAX | Instruction::SHA | Instruction::SHX |
+        Instruction::SHY | Instruction::TAS => Access::Write,
+
+        Instruction::ASL | Instruction::LSR | Instruction::ROL |
+        Instruction::ROR | Instruction::INC | Instruction::DEC |
+        Instruction::SLO | Instruction::RLA | Instruction::SRE |
+        Instruction::RRA | Instruction::DCP | Instruction::ISC =>
+            Access::ReadModifyWrite,
+
+        _ => Access::Read,
+    }
+}
+
+// What the processor is in the middle of
+#[derive(Copy)]
+enum Operation {
+    Instruction(Instruction, AddressingMode),
+
+    // A hardware interrupt, through the vector at the given address
+    Interrupt(Address),
+}
+
+pub struct CycleMachine<B = Memory> {
+    pub registers:   Registers,
+    pub memory:      B,
+
+    // Number of clock cycles executed so far
+    pub cycles:      u64,
+
+    // Interrupt inputs, as on `Machine`. They're only looked at between
+    // instructions.
+    pub irq_line:    bool,
+    pub nmi_pending: bool,
+
+    pub variant:     Variant,
+    pub jammed:      bool,
+
+    alu:             Machine<Latch>,
+
+    // None between instructions
+    operation:       Option<Operation>,
+
+    // The cycle of the current operation, counting the opcode fetch as 0
+    cycle:           u8,
+
+    // The effective address, the base address it was indexed from and a
+    // zero page pointer, as they're worked out
+    address:         u16,
+    base:            u16,
+    pointer:         u8,
+
+    // The last byte read, or the byte to be written back
+    data:            u8,
+
+    last_cycle:      BusCycle,
+}
+
+impl CycleMachine {
+    pub fn new() -> CycleMachine {
+        CycleMachine::with_bus(Memory::new())
+    }
+}
+
+impl<B: Bus> CycleMachine<B> {
+    pub fn with_bus(bus: B) -> CycleMachine<B> {
+        CycleMachine::build(bus, Variant::Nmos)
+    }
+
+    // The 65C02 does its dummy cycles differently, so only the NMOS variants
+    // are supported.
+    pub fn with_variant(bus: B, variant: Variant)
+        -> Result<CycleMachine<B>, UnsupportedVariant>
+    {
+        if variant == Variant::Cmos {
+            return Err(UnsupportedVariant(variant));
+        }
+        Ok(CycleMachine::build(bus, variant))
+    }
+
+    fn build(bus: B, variant: Variant) -> CycleMachine<B> {
+        let latch = Latch { value: 0, address: Address(0) };
+        let mut machine = CycleMachine {
+            registers:   Registers::new(),
+            memory:      bus,
+            cycles:      0,
+            irq_line:    false,
+            nmi_pending: false,
+            variant:     variant,
+            jammed:      false,
+            alu:         Machine::with_variant(latch, variant),
+            operation:   None,
+            cycle:       0,
+            address:     0,
+            base:        0,
+            pointer:     0,
+            data:        0,
+            last_cycle:  BusCycle { address: Address(0), data: 0,
+                                    write: false },
+        };
+        machine.reset();
+        machine
+    }
+
+    // Like `Machine::reset`. The reset sequence itself isn't clocked, but
+    // still counts seven cycles.
+    pub fn reset(&mut self) {
+        let StackPointer(sp) = self.registers.stack_pointer;
+        self.registers.stack_pointer = StackPointer(sp.wrapping_sub(3));
+        self.registers.status.or(PS_DISABLE_INTERRUPTS);
+        self.nmi_pending = false;
+        self.jammed = false;
+        self.operation = None;
+
+        let lo = self.memory.get_byte(RESET_VECTOR_LO) as u16;
+        let hi = self.memory.get_byte(RESET_VECTOR_LO + AddressDiff(1)) as u16;
+        self.registers.program_counter = Address((hi << 8) | lo);
+        self.cycles += 7;
+    }
+
+    // Whether the next clock starts a new instruction
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.operation.is_none()
+    }
+
+    // Runs a single clock cycle, returning the bus access made on it. A
+    // jammed processor just keeps reading $FFFF.
+    pub fn clock(&mut self) -> BusCycle {
+        if self.jammed {
+            self.read(0xFFFF);
+        } else {
+            match self.operation {
+                None => self.start_operation(),
+                Some(operation) => {
+                    let cycle = self.cycle;
+                    self.cycle += 1;
+                    match operation {
+                        Operation::Instruction(instr, am) =>
+                            self.instruction_cycle(cycle, instr, am),
+                        Operation::Interrupt(vector) =>
+                            self.interrupt_cycle(cycle, vector, false),
+                    }
+                }
+            }
+        }
+
+        self.cycles += 1;
+        self.memory.tick(1);
+        self.last_cycle
+    }
+
+    // Clocks to the end of the current instruction, or of the next one if
+    // it's between instructions. Stops like `Machine::step`, except that
+    // every opcode is valid on the NMOS 6502.
+    pub fn step(&mut self) -> Option<StopReason> {
+        loop {
+            self.clock();
+            if self.jammed {
+                return Some(StopReason::Jam(self.registers.program_counter));
+            }
+            if self.operation.is_none() {
+                return None;
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+        }
+    }
+
+    // The first cycle fetches the opcode, unless there's an interrupt to
+    // take, in which case the fetch is thrown away.
+    fn start_operation(&mut self) {
+        let pc = self.registers.program_counter;
+        let opcode = self.read(pc.to_u16());
+        self.cycle = 1;
+
+        let irq = (self.irq_line || self.memory.irq_asserted())
+               && !self.registers.status.contains(PS_DISABLE_INTERRUPTS);
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            let vector = NMI_INTERRUPT_VECTOR_LO;
+            self.operation = Some(Operation::Interrupt(vector));
+        } else if irq {
+            let vector = IRQ_INTERRUPT_VECTOR_LO;
+            self.operation = Some(Operation::Interrupt(vector));
+        } else {
+            let (instr, am) = match self.variant.opcodes()[opcode as usize] {
+                Some((instr, am, _)) => (instr, am),
+                None => (Instruction::JAM, AddressingMode::Implied),
+            };
+            self.registers.program_counter = pc + AddressDiff(1);
+            self.operation = Some(Operation::Instruction(instr, am));
+        }
+    }
+
+    fn finish(&mut self) {
+        self.operation = None;
+    }
+
+    fn instruction_cycle(&mut self, cycle: u8, instr: Instruction,
+                         am: AddressingMode) {
+        match (instr, am) {
+            (Instruction::BRK, _) =>
+                self.interrupt_cycle(cycle, IRQ_INTERRUPT_VECTOR_LO, true),
+            (Instruction::JAM, _) => {
+                // Left pointing at the JAM, as `Machine` does
+                self.read(0xFFFF);
+                let pc = self.registers.program_counter;
+                self.registers.program_counter = pc + AddressDiff(-1);
+                self.jammed = true;
+            }
+            (Instruction::JSR, _) => match cycle {
+                1 => self.data = self.fetch(),
+                2 => self.read_stack(),
+                3 | 4 => {
+                    let pc = self.registers.program_counter.to_u16();
+                    let val = if cycle == 3 { pc >> 8 } else { pc };
+                    self.push(val as u8);
+                }
+                _ => {
+                    let hi = self.fetch() as u16;
+                    self.registers.program_counter =
+                        Address((hi << 8) | self.data as u16);
+                    self.finish();
+                }
+            },
+            (Instruction::RTS, _) => match cycle {
+                1 => self.read_pc(),
+                2 => self.read_stack(),
+                3 => self.data = self.pull(),
+                4 => {
+                    let hi = self.pull() as u16;
+                    self.registers.program_counter =
+                        Address((hi << 8) | self.data as u16);
+                }
+                _ => {
+                    self.fetch();
+                    self.finish();
+                }
+            },
+            (Instruction::RTI, _) => match cycle {
+                1 => self.read_pc(),
+                2 => self.read_stack(),
+                3 => {
+                    self.pull_into_alu();
+                    self.operate((Instruction::PLP, OpInput::UseImplied));
+                }
+                4 => self.data = self.pull(),
+                _ => {
+                    let hi = self.pull() as u16;
+                    self.registers.program_counter =
+                        Address((hi << 8) | self.data as u16);
+                    self.finish();
+                }
+            },
+            (Instruction::PHA, _) | (Instruction::PHP, _) => match cycle {
+                1 => self.read_pc(),
+                _ => {
+                    let address = self.registers.stack_pointer.to_address();
+                    let val = self.operate((instr, OpInput::UseImplied));
+                    self.write(address.to_u16(), val);
+                    self.finish();
+                }
+            },
+            (Instruction::PLA, _) | (Instruction::PLP, _) => match cycle {
+                1 => self.read_pc(),
+                2 => self.read_stack(),
+                _ => {
+                    self.pull_into_alu();
+                    self.operate((instr, OpInput::UseImplied));
+                    self.finish();
+                }
+            },
+            (Instruction::JMP, AddressingMode::Absolute) => match cycle {
+                1 => self.address = self.fetch() as u16,
+                _ => {
+                    let hi = self.fetch() as u16;
+                    self.registers.program_counter =
+                        Address((hi << 8) | self.address);
+                    self.finish();
+                }
+            },
+            (Instruction::JMP, AddressingMode::Indirect) => match cycle {
+                1 => self.address = self.fetch() as u16,
+                2 => self.address |= (self.fetch() as u16) << 8,
+                3 => {
+                    let address = self.address;
+                    self.data = self.read(address);
+                }
+                _ => {
+                    // The NMOS 6502 doesn't carry into the high byte of the
+                    // pointer, so JMP ($12FF) reads $12FF and $1200
+                    let address = (self.address & 0xFF00)
+                                | (self.address.wrapping_add(1) & 0x00FF);
+                    let hi = self.read(address) as u16;
+                    self.registers.program_counter =
+                        Address((hi << 8) | self.data as u16);
+                    self.finish();
+                }
+            },
+            (_, AddressingMode::Relative) => self.branch_cycle(cycle, instr),
+            (_, AddressingMode::Implied) | (_, AddressingMode::Accumulator) => {
+                self.read_pc();
+                self.operate((instr, OpInput::UseImplied));
+                self.finish();
+            }
+            (_, AddressingMode::Immediate) => {
+                self.data = self.fetch();
+                let val = self.data;
+                self.operate((instr, OpInput::UseImmediate(val)));
+                self.finish();
+            }
+            (_, _) => self.memory_cycle(cycle, instr, am),
+        }
+    }
+
+    // Works out the effective address, a cycle at a time, and then hands
+    // over to `access_cycle`
+    fn memory_cycle(&mut self, cycle: u8, instr: Instruction,
+                    am: AddressingMode) {
+        let x = self.registers.index_x as u8;
+        let y = self.registers.index_y as u8;
+
+        match (am, cycle) {
+            (AddressingMode::ZeroPage, 1) =>
+                self.address = self.fetch() as u16,
+            (AddressingMode::ZeroPage, _) =>
+                self.access_cycle(cycle - 2, instr),
+
+            (AddressingMode::ZeroPageX, 1) |
+            (AddressingMode::ZeroPageY, 1) =>
+                self.address = self.fetch() as u16,
+            (AddressingMode::ZeroPageX, 2) |
+            (AddressingMode::ZeroPageY, 2) => {
+                // Reads the unindexed address while adding the index, which
+                // wraps around within the zero page
+                let address = self.address;
+                self.read(address);
+                let index = if am == AddressingMode::ZeroPageX { x } else { y };
+                self.address = (address as u8).wrapping_add(index) as u16;
+            }
+            (AddressingMode::ZeroPageX, _) |
+            (AddressingMode::ZeroPageY, _) =>
+                self.access_cycle(cycle - 3, instr),
+
+            (AddressingMode::Absolute, 1) =>
+                self.address = self.fetch() as u16,
+            (AddressingMode::Absolute, 2) =>
+                self.address |= (self.fetch() as u16) << 8,
+            (AddressingMode::Absolute, _) =>
+                self.access_cycle(cycle - 3, instr),
+
+            (AddressingMode::AbsoluteX, 1) |
+            (AddressingMode::AbsoluteY, 1) =>
+                self.base = self.fetch() as u16,
+            (AddressingMode::AbsoluteX, 2) |
+            (AddressingMode::AbsoluteY, 2) => {
+                self.base |= (self.fetch() as u16) << 8;
+                let index = if am == AddressingMode::AbsoluteX { x } else { y };
+                self.address = self.base.wrapping_add(index as u16);
+            }
+            (AddressingMode::AbsoluteX, 3) |
+            (AddressingMode::AbsoluteY, 3) => self.indexed_read(instr),
+            (AddressingMode::AbsoluteX, _) |
+            (AddressingMode::AbsoluteY, _) =>
+                self.access_cycle(cycle - 4, instr),
+
+            (AddressingMode::IndexedIndirectX, 1) =>
+                self.pointer = self.fetch(),
+            (AddressingMode::IndexedIndirectX, 2) => {
+                let pointer = self.pointer;
+                self.read(pointer as u16);
+                self.pointer = pointer.wrapping_add(x);
+            }
+            (AddressingMode::IndexedIndirectX, 3) => {
+                let pointer = self.pointer;
+                self.address = self.read(pointer as u16) as u16;
+            }
+            (AddressingMode::IndexedIndirectX, 4) => {
+                let pointer = self.pointer.wrapping_add(1);
+                self.address |= (self.read(pointer as u16) as u16) << 8;
+            }
+            (AddressingMode::IndexedIndirectX, _) =>
+                self.access_cycle(cycle - 5, instr),
+
+            (AddressingMode::IndirectIndexedY, 1) =>
+                self.pointer = self.fetch(),
+            (AddressingMode::IndirectIndexedY, 2) => {
+                let pointer = self.pointer;
+                self.base = self.read(pointer as u16) as u16;
+            }
+            (AddressingMode::IndirectIndexedY, 3) => {
+                let pointer = self.pointer.wrapping_add(1);
+                self.base |= (self.read(pointer as u16) as u16) << 8;
+                self.address = self.base.wrapping_add(y as u16);
+            }
+            (AddressingMode::IndirectIndexedY, 4) => self.indexed_read(instr),
+            (AddressingMode::IndirectIndexedY, _) =>
+                self.access_cycle(cycle - 5, instr),
+
+            _ => panic!("{:?} doesn't run on the NMOS 6502", am),
+        }
+    }
+
+    // The cycle after indexing reads from the address with the index added
+    // to its low byte only. If that was the right address after all (no
+    // page was crossed) and the instruction only reads, that's the operand
+    // and the instruction is done. Otherwise it was a dummy read.
+    fn indexed_read(&mut self, instr: Instruction) {
+        let unfixed = (self.base & 0xFF00) | (self.address & 0x00FF);
+        self.data = self.read(unfixed);
+        if unfixed == self.address && access(instr) == Access::Read {
+            let address = Address(unfixed);
+            self.operate((instr, OpInput::UseAddress(address)));
+            self.finish();
+        }
+    }
+
+    // The cycles that use the effective address
+    fn access_cycle(&mut self, n: u8, instr: Instruction) {
+        let address = self.address;
+        let decoded = (instr, OpInput::UseAddress(Address(address)));
+
+        match (access(instr), n) {
+            (Access::Read, _) => {
+                self.data = self.read(address);
+                self.operate(decoded);
+                self.finish();
+            }
+            (Access::Write, _) => {
+                // SHA and friends sometimes write somewhere else
+                let val = self.operate(decoded);
+                let target = self.alu.memory.address.to_u16();
+                self.write(target, val);
+                self.finish();
+            }
+            (Access::ReadModifyWrite, 0) => {
+                self.data = self.read(address);
+            }
+            (Access::ReadModifyWrite, 1) => {
+                // The NMOS 6502 writes the unmodified value back while it
+                // works out the new one
+                let val = self.data;
+                self.write(address, val);
+            }
+            (Access::ReadModifyWrite, _) => {
+                let val = self.operate(decoded);
+                self.write(address, val);
+                self.finish();
+            }
+        }
+    }
+
+    // A taken branch takes a cycle to add the offset to the low byte of the
+    // PC, and another to fix the high byte if that crossed a page.
+    fn branch_cycle(&mut self, cycle: u8, instr: Instruction) {
+        match cycle {
+            1 => {
+                self.data = self.fetch();
+                let pc = self.registers.program_counter;
+                let offset = self.data as i8;
+                self.operate((instr, OpInput::UseRelative(offset)));
+
+                let target = self.registers.program_counter;
+                self.registers.program_counter = pc;
+                self.address = target.to_u16();
+                if target == pc {
+                    self.finish();
+                }
+            }
+            2 => {
+                self.read_pc();
+                let pc = self.registers.program_counter.to_u16();
+                let unfixed = (pc & 0xFF00) | (self.address & 0x00FF);
+                self.registers.program_counter = Address(unfixed);
+                if unfixed == self.address {
+                    self.finish();
+                }
+            }
+            _ => {
+                self.read_pc();
+                self.registers.program_counter = Address(self.address);
+                self.finish();
+            }
+        }
+    }
+
+    // BRK and the hardware interrupts differ only in BRK skipping the byte
+    // after the opcode, and setting B in the pushed status.
+    fn interrupt_cycle(&mut self, cycle: u8, vector: Address, brk: bool) {
+        match cycle {
+            1 => if brk { self.fetch(); } else { self.read_pc(); },
+            2 | 3 => {
+                let pc = self.registers.program_counter.to_u16();
+                let val = if cycle == 2 { pc >> 8 } else { pc };
+                self.push(val as u8);
+            }
+            4 => {
+                let status = if brk {
+                    self.registers.status | PS_BRK | PS_UNUSED
+                } else {
+                    (self.registers.status - PS_BRK) | PS_UNUSED
+                };
+                self.push(status.bits());
+                self.registers.status.or(PS_DISABLE_INTERRUPTS);
+            }
+            5 => self.data = self.read(vector.to_u16()),
+            _ => {
+                let hi = self.read((vector + AddressDiff(1)).to_u16()) as u16;
+                self.registers.program_counter =
+                    Address((hi << 8) | self.data as u16);
+                self.finish();
+            }
+        }
+    }
+
+    // Runs the instruction on the latch, holding the last byte read, and
+    // returns what's in the latch afterwards
+    fn operate(&mut self, decoded_instr: DecodedInstr) -> u8 {
+        self.alu.registers = self.registers;
+        self.alu.memory.value = self.data;
+        self.alu.execute_instruction(decoded_instr);
+        self.registers = self.alu.registers;
+        self.alu.memory.value
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        let address = Address(address);
+        let data = self.memory.get_byte(address);
+        self.last_cycle = BusCycle { address: address, data: data,
+                                     write: false };
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        let address = Address(address);
+        self.memory.set_byte(address, data);
+        self.last_cycle = BusCycle { address: address, data: data,
+                                     write: true };
+    }
+
+    // Reads the byte at the PC and moves past it
+    fn fetch(&mut self) -> u8 {
+        let pc = self.registers.program_counter;
+        self.registers.program_counter = pc + AddressDiff(1);
+        self.read(pc.to_u16())
+    }
+
+    // A dummy read of the byte at the PC
+    fn read_pc(&mut self) {
+        let pc = self.registers.program_counter.to_u16();
+        self.read(pc);
+    }
+
+    // A dummy read of the top of the stack
+    fn read_stack(&mut self) {
+        let address = self.registers.stack_pointer.to_address().to_u16();
+        self.read(address);
+    }
+
+    fn push(&mut self, val: u8) {
+        let StackPointer(sp) = self.registers.stack_pointer;
+        self.write(0x0100 | sp as u16, val);
+        self.registers.stack_pointer = StackPointer(sp.wrapping_sub(1));
+    }
+
+    fn pull(&mut self) -> u8 {
+        let StackPointer(sp) = self.registers.stack_pointer;
+        let sp = sp.wrapping_add(1);
+        self.registers.stack_pointer = StackPointer(sp);
+        self.read(0x0100 | sp as u16)
+    }
+
+    // Reads the byte above the top of the stack for the ALU to pull, which
+    // moves the stack pointer itself
+    fn pull_into_alu(&mut self) {
+        let StackPointer(sp) = self.registers.stack_pointer;
+        self.data = self.read(0x0100 | sp.wrapping_add(1) as u16);
+    }
+}
+
+#[cfg(test)]
+use assembler;
+
+#[cfg(test)]
+use registers::PS_CARRY;
+
+#[cfg(test)]
+fn cycle_machine(program: &[u8]) -> CycleMachine {
+    let mut machine = CycleMachine::new();
+    machine.memory.set_bytes(Address(0x1000), program);
+    machine.registers.program_counter = Address(0x1000);
+    machine
+}
+
+#[cfg(test)]
+fn read_cycle(address: u16, data: u8) -> BusCycle {
+    BusCycle { address: Address(address), data: data, write: false }
+}
+
+#[cfg(test)]
+fn write_cycle(address: u16, data: u8) -> BusCycle {
+    BusCycle { address: Address(address), data: data, write: true }
+}
+
+#[test]
+fn with_variant_test() {
+    assert!(CycleMachine::with_variant(Memory::new(), Variant::Ricoh2A03)
+                .is_ok());
+    assert_eq!(CycleMachine::with_variant(Memory::new(), Variant::Cmos).err(),
+               Some(UnsupportedVariant(Variant::Cmos)));
+}
+
+#[test]
+fn read_modify_write_cycles_test() {
+    let mut machine = cycle_machine(&[
+        0xE6, 0x10,       // INC $10
+        0x1E, 0xF0, 0x12, // ASL $12F0,X
+    ]);
+    machine.memory.set_byte(Address(0x0010), 0x41);
+    machine.memory.set_byte(Address(0x1300), 0x81);
+    machine.registers.index_x = 0x10;
+
+    // The old value is written back before the new one
+    let cycles: Vec<BusCycle> = (0..5).map(|_| machine.clock()).collect();
+    assert_eq!(cycles, vec![
+        read_cycle(0x1000, 0xE6),
+        read_cycle(0x1001, 0x10),
+        read_cycle(0x0010, 0x41),
+        write_cycle(0x0010, 0x41),
+        write_cycle(0x0010, 0x42),
+    ]);
+    assert!(machine.at_instruction_boundary());
+
+    // Indexing always costs a dummy read, here from $1200
+    let cycles: Vec<BusCycle> = (0..7).map(|_| machine.clock()).collect();
+    assert_eq!(cycles, vec![
+        read_cycle(0x1002, 0x1E),
+        read_cycle(0x1003, 0xF0),
+        read_cycle(0x1004, 0x12),
+        read_cycle(0x1200, 0x00),
+        read_cycle(0x1300, 0x81),
+        write_cycle(0x1300, 0x81),
+        write_cycle(0x1300, 0x02),
+    ]);
+    assert!(machine.registers.status.contains(PS_CARRY));
+}
+
+#[test]
+fn indexed_read_cycles_test() {
+    let mut machine = cycle_machine(&[
+        0xBD, 0xF0, 0x10, // LDA $10F0,X
+        0xBD, 0xFF, 0x10, // LDA $10FF,X
+    ]);
+    machine.memory.set_byte(Address(0x1100), 0x42);
+    machine.registers.index_x = 0x01;
+
+    // Without a page crossing the first read is the real one
+    let cycles: Vec<BusCycle> = (0..4).map(|_| machine.clock()).collect();
+    assert_eq!(cycles[3], read_cycle(0x10F1, 0x00));
+    assert!(machine.at_instruction_boundary());
+
+    let cycles: Vec<BusCycle> = (0..5).map(|_| machine.clock()).collect();
+    assert_eq!(cycles[3], read_cycle(0x1000, 0xBD));
+    assert_eq!(cycles[4], read_cycle(0x1100, 0x42));
+    assert!(machine.at_instruction_boundary());
+    assert_eq!(machine.registers.accumulator, 0x42);
+}
+
+#[test]
+fn interrupt_cycles_test() {
+    let mut machine = cycle_machine(&[0xEA]);
+    machine.memory.set_bytes(Address(0xFFFA), &[0x00, 0x20]);
+    machine.registers.stack_pointer = StackPointer(0xFF);
+    machine.registers.status = PS_UNUSED;
+    machine.nmi_pending = true;
+
+    // The opcode fetch is thrown away, and B is clear in the pushed status
+    let cycles: Vec<BusCycle> = (0..7).map(|_| machine.clock()).collect();
+    assert_eq!(cycles, vec![
+        read_cycle(0x1000, 0xEA),
+        read_cycle(0x1000, 0xEA),
+        write_cycle(0x01FF, 0x10),
+        write_cycle(0x01FE, 0x00),
+        write_cycle(0x01FD, 0x20),
+        read_cycle(0xFFFA, 0x00),
+        read_cycle(0xFFFB, 0x20),
+    ]);
+    assert_eq!(machine.registers.program_counter, Address(0x2000));
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+}
+
+// Steps the cycle-stepped core and `Machine` through the same program,
+// checking that they agree on the registers and the cycle count after every
+// instruction, and on memory at the end
+#[test]
+fn matches_machine_test() {
+    let program = assembler::assemble("
+        .org $0040
+        .word $0600
+        .byte $00, $00, $00, $00, $00, $00, $00
+        .word $0700
+
+        .org $0200
+start:  LDX #$00
+        LDY #$10
+loop:   LDA data,X
+        STA $0300,X
+        ADC ($40),Y
+        STA ($40),Y
+        INC $50,X
+        ASL $0400,X
+        DCP $60
+        ROR $0401
+        LDA $02F8,Y
+        PHA
+        PHP
+        PLA
+        PLP
+        JSR sub
+        INX
+        CPX #$05
+        BNE loop
+        LDA ($44,X)
+        SLO $0500,Y
+        JMP (vector)
+back:   BRK
+        .byte $00
+        JAM
+
+sub:    LDA $20
+        EOR #$FF
+        BIT $20
+        RTS
+
+irq:    INC $70
+        RTI
+
+vector: .word back
+data:   .byte $01, $80, $FF, $7F, $00
+
+        .org $FFFC
+        .word start
+        .word irq
+    ", Variant::Nmos).unwrap();
+
+    let mut memory = Memory::new();
+    program.load_into(&mut memory);
+    let mut machine = Machine::with_bus(memory);
+    let mut cycle_machine = CycleMachine::with_bus(memory);
+
+    for _ in 0..1000 {
+        let reason = machine.step();
+        assert_eq!(cycle_machine.step(), reason);
+        assert_eq!(cycle_machine.registers, machine.registers);
+        assert_eq!(cycle_machine.cycles, machine.cycles);
+        if reason.is_some() {
+            break;
+        }
+    }
+
+    // The BRK went through the IRQ handler and came back to the JAM
+    assert!(cycle_machine.jammed);
+    assert_eq!(cycle_machine.memory.get_byte(Address(0x0070)), 0x01);
+    for address in 0..0x0800 {
+        let address = Address(address);
+        assert_eq!(cycle_machine.memory.get_byte(address),
+                   machine.memory.get_byte(address));
+    }
+}
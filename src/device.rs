@@ -0,0 +1,331 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use address::Address;
+use bus::Bus;
+use memory::Memory;
+use range_incl::AddressRangeIncl;
+use save_state::{StateReader, StateWriter};
+
+// A memory-mapped device, such as a UART, a timer or a framebuffer. Devices
+// are registered with a `DeviceBus` at a range of addresses, and only ever
+// see offsets from the start of that range, so the same device can be mapped
+// anywhere.
+pub trait Device {
+    // Called when the CPU reads from the device.
+    fn read(&mut self, offset: u16) -> u8;
+
+    // Like `read`, but without any side effects. Used by debuggers.
+    fn peek(&self, offset: u16) -> u8;
+
+    // Called when the CPU writes to the device.
+    fn write(&mut self, offset: u16, value: u8);
+
+    // Called after every instruction with the number of cycles it took.
+    fn tick(&mut self, _cycles: u64) {
+    }
+
+    // Whether the device is requesting an interrupt. IRQ is level-triggered,
+    // so the device should keep returning true until it's been acknowledged.
+    fn irq_asserted(&self) -> bool {
+        false
+    }
+
+    // Saves whatever the device needs to carry on where it left off after a
+    // save state is loaded, and restores it. Devices with no state of their
+    // own can leave these alone.
+    fn save_state(&self, _out: &mut StateWriter) {
+    }
+
+    fn load_state(&mut self, _input: &mut StateReader)
+        -> Result<(), String>
+    {
+        Ok(())
+    }
+}
+
+// A bus that routes accesses to registered devices, and everything else to a
+// backing bus (plain RAM by default).
+pub struct DeviceBus<B = Memory> {
+    pub backing: B,
+    devices:     Vec<(AddressRangeIncl, Box<Device>)>,
+}
+
+impl DeviceBus {
+    pub fn new() -> DeviceBus {
+        DeviceBus::with_backing(Memory::new())
+    }
+}
+
+impl<B: Bus> DeviceBus<B> {
+    pub fn with_backing(backing: B) -> DeviceBus<B> {
+        DeviceBus { backing: backing, devices: Vec::new() }
+    }
+
+    // Maps the device into the given range. Ranges must not overlap those of
+    // devices that are already registered.
+    pub fn register_device(&mut self, range: AddressRangeIncl,
+                           device: Box<Device>) {
+        for &(ref other, _) in self.devices.iter() {
+            if range.overlaps(other) {
+                panic!("device range {:?} overlaps {:?}", range, other);
+            }
+        }
+        self.devices.push((range, device));
+    }
+
+    fn find_device(&self, address: Address) -> Option<usize> {
+        self.devices.iter().position(|&(ref range, _)| range.contains(address))
+    }
+}
+
+impl<B: Bus> Bus for DeviceBus<B> {
+    fn get_byte(&mut self, address: Address) -> u8 {
+        match self.find_device(address) {
+            Some(i) => {
+                let (range, ref mut device) = self.devices[i];
+                device.read(range.offset_of(address))
+            }
+            None => self.backing.get_byte(address)
+        }
+    }
+
+    fn peek_byte(&self, address: Address) -> u8 {
+        match self.find_device(address) {
+            Some(i) => {
+                let (range, ref device) = self.devices[i];
+                device.peek(range.offset_of(address))
+            }
+            None => self.backing.peek_byte(address)
+        }
+    }
+
+    fn set_byte(&mut self, address: Address, value: u8) -> u8 {
+        match self.find_device(address) {
+            Some(i) => {
+                let (range, ref mut device) = self.devices[i];
+                let offset = range.offset_of(address);
+                let old_value = device.peek(offset);
+                device.write(offset, value);
+                old_value
+            }
+            None => self.backing.set_byte(address, value)
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.backing.tick(cycles);
+        for &mut (_, ref mut device) in self.devices.iter_mut() {
+            device.tick(cycles);
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.backing.irq_asserted()
+            || self.devices.iter().any(|&(_, ref device)| device.irq_asserted())
+    }
+
+    // The backing bus, and then each device in the order they were
+    // registered
+    fn save_state(&self, out: &mut StateWriter) {
+        self.backing.save_state(out);
+        out.u32(self.devices.len() as u32);
+        for &(_, ref device) in self.devices.iter() {
+            device.save_state(out);
+        }
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), String> {
+        try!(self.backing.load_state(input));
+        let count = try!(input.u32()) as usize;
+        if count != self.devices.len() {
+            return Err(format!("saved with {} devices, but {} are registered",
+                               count, self.devices.len()));
+        }
+        for &mut (_, ref mut device) in self.devices.iter_mut() {
+            try!(device.load_state(input));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::rc::Rc;
+#[cfg(test)]
+use machine::Machine;
+#[cfg(test)]
+use memory::{IRQ_INTERRUPT_VECTOR_LO, IRQ_INTERRUPT_VECTOR_HI};
+
+// A write-only serial port that remembers what was written to it, and
+// counts the cycles it has seen.
+#[cfg(test)]
+struct TestUart {
+    output: Rc<RefCell<Vec<u8>>>,
+    cycles: Rc<RefCell<u64>>,
+}
+
+#[cfg(test)]
+impl Device for TestUart {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        // Register 1 is the "transmitter ready" status register
+        if offset == 1 { 0x80 } else { 0 }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset == 0 {
+            self.output.borrow_mut().push(value);
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        *self.cycles.borrow_mut() += cycles;
+    }
+}
+
+#[test]
+fn device_bus_test() {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let cycles = Rc::new(RefCell::new(0));
+
+    let mut bus = DeviceBus::new();
+    bus.register_device(
+        AddressRangeIncl::new(Address(0xD000), Address(0xD001)),
+        Box::new(TestUart { output: output.clone(), cycles: cycles.clone() }));
+
+    let mut machine = Machine::with_bus(bus);
+    machine.cycles = 0;
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xAD, 0x01, 0xD0, // LDA $D001
+        0x8D, 0x00, 0xD0, // STA $D000
+        0xA9, 0x41,       // LDA #$41
+        0x8D, 0x00, 0xD0, // STA $D000
+        0x8D, 0x02, 0xD0, // STA $D002
+        0x02,             // JAM
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+    machine.run();
+
+    assert_eq!(*output.borrow(), vec![0x80, 0x41]);
+    assert_eq!(*cycles.borrow(), machine.cycles);
+
+    // $D002 is outside the device's range, so it's plain RAM
+    assert_eq!(machine.memory.peek_byte(Address(0xD002)), 0x41);
+    assert_eq!(machine.memory.backing.get_byte(Address(0xD000)), 0);
+}
+
+#[test]
+#[should_fail]
+fn overlapping_devices_test() {
+    let mut bus = DeviceBus::new();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let cycles = Rc::new(RefCell::new(0));
+
+    bus.register_device(
+        AddressRangeIncl::new(Address(0xD000), Address(0xD001)),
+        Box::new(TestUart { output: output.clone(), cycles: cycles.clone() }));
+    bus.register_device(
+        AddressRangeIncl::new(Address(0xD001), Address(0xD002)),
+        Box::new(TestUart { output: output.clone(), cycles: cycles.clone() }));
+}
+
+// A timer that raises an interrupt after a number of cycles. Reading it
+// acknowledges the interrupt.
+#[cfg(test)]
+struct TestTimer {
+    remaining: u64,
+    fired:     bool,
+}
+
+#[cfg(test)]
+impl Device for TestTimer {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.peek(offset);
+        self.fired = false;
+        value
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        if self.fired { 0x80 } else { 0 }
+    }
+
+    fn write(&mut self, _offset: u16, _value: u8) {
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if self.remaining > 0 && cycles >= self.remaining {
+            self.fired = true;
+        }
+        self.remaining -= ::std::cmp::min(cycles, self.remaining);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.fired
+    }
+}
+
+#[test]
+fn device_irq_test() {
+    let mut bus = DeviceBus::new();
+    bus.register_device(
+        AddressRangeIncl::new(Address(0xD100), Address(0xD100)),
+        Box::new(TestTimer { remaining: 10, fired: false }));
+
+    let mut machine = Machine::with_bus(bus);
+    machine.memory.set_bytes(Address(0x1000), &[
+        0x58,             // CLI
+        0xEA,             // NOP
+        0xEA,             // NOP
+        0xEA,             // NOP
+        0xEA,             // NOP
+        0xEA,             // NOP
+        0xEA,             // NOP
+        0x02,             // JAM
+    ]);
+    machine.memory.set_bytes(Address(0x2000), &[
+        0xAD, 0x00, 0xD1, // LDA $D100
+        0x02,             // JAM
+    ]);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_LO, 0x00);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_HI, 0x20);
+    machine.registers.program_counter = Address(0x1000);
+    machine.run();
+
+    // The interrupt was taken after ten cycles (CLI and four NOPs), and
+    // acknowledged by the handler
+    assert_eq!(machine.registers.accumulator as u8, 0x80);
+    assert_eq!(machine.registers.program_counter, Address(0x2003));
+    assert_eq!(machine.memory.get_byte(Address(0x01FC)), 0x10);
+    assert_eq!(machine.memory.get_byte(Address(0x01FB)), 0x05);
+    assert!(!machine.memory.irq_asserted());
+}
+
@@ -0,0 +1,246 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt;
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use instruction::{AddressingMode, Variant};
+
+// One disassembled instruction (or, for bytes that aren't a valid opcode, a
+// `.byte` directive).
+pub struct DisassembledInstr {
+    pub address: Address,
+    pub bytes:   Vec<u8>,
+    pub text:    String,
+}
+
+impl DisassembledInstr {
+    // Address of whatever follows this instruction
+    pub fn next_address(&self) -> Address {
+        self.address + AddressDiff(self.bytes.len() as i32)
+    }
+}
+
+// Formats as e.g. "C000  B1 10     LDA ($10),Y"
+impl fmt::Display for DisassembledInstr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter()
+                                           .map(|b| format!("{:02X}", b))
+                                           .collect();
+        write!(f, "{:04X}  {:<8}  {}",
+               self.address.to_u16(), bytes.connect(" "), self.text)
+    }
+}
+
+// Formats the operand of an instruction at `address` with the given operand
+// bytes, in the usual assembler syntax. Relative branch targets are resolved
+// to absolute addresses.
+pub fn format_operand(am: AddressingMode, address: Address, arr: &[u8])
+    -> String
+{
+    let byte = || arr[0];
+    let word = || (arr[0] as u16) | ((arr[1] as u16) << 8);
+
+    match am {
+        AddressingMode::Accumulator      => "A".to_string(),
+        AddressingMode::Implied          => String::new(),
+        AddressingMode::Immediate        => format!("#${:02X}", byte()),
+        AddressingMode::ZeroPage         => format!("${:02X}", byte()),
+        AddressingMode::ZeroPageX        => format!("${:02X},X", byte()),
+        AddressingMode::ZeroPageY        => format!("${:02X},Y", byte()),
+        AddressingMode::Relative         => {
+            // Relative to the address of the next instruction
+            let target = address + AddressDiff(2)
+                       + AddressDiff(byte() as i8 as i32);
+            format!("${:04X}", target.to_u16())
+        },
+        AddressingMode::Absolute         => format!("${:04X}", word()),
+        AddressingMode::AbsoluteX        => format!("${:04X},X", word()),
+        AddressingMode::AbsoluteY        => format!("${:04X},Y", word()),
+        AddressingMode::Indirect         => format!("(${:04X})", word()),
+        AddressingMode::IndexedIndirectX => format!("(${:02X},X)", byte()),
+        AddressingMode::IndirectIndexedY => format!("(${:02X}),Y", byte()),
+        AddressingMode::ZeroPageIndirect => format!("(${:02X})", byte()),
+        AddressingMode::AbsoluteIndexedIndirect =>
+            format!("(${:04X},X)", word()),
+        AddressingMode::ZeroPageRelative => {
+            let target = address + AddressDiff(3)
+                       + AddressDiff(arr[1] as i8 as i32);
+            format!("${:02X},${:04X}", byte(), target.to_u16())
+        },
+    }
+}
+
+// Disassembles the instruction at the given address, using the instruction
+// set of the given variant. This only peeks at the bus, so it's safe to use
+// on memory-mapped I/O.
+pub fn disassemble_instruction<B: Bus>(bus: &B, address: Address,
+                                       variant: Variant)
+    -> DisassembledInstr
+{
+    let opcode = bus.peek_byte(address);
+
+    match variant.opcodes()[opcode as usize] {
+        Some((instr, am, _)) => {
+            let AddressDiff(len) = am.extra_bytes();
+            let mut bytes = vec![opcode];
+            for i in 0..len {
+                bytes.push(bus.peek_byte(address + AddressDiff(1 + i)));
+            }
+
+            let operand = format_operand(am, address, &bytes[1..]);
+            let text = if operand.is_empty() {
+                format!("{:?}", instr)
+            } else {
+                format!("{:?} {}", instr, operand)
+            };
+
+            DisassembledInstr { address: address, bytes: bytes, text: text }
+        }
+        None => {
+            DisassembledInstr { address: address,
+                                bytes:   vec![opcode],
+                                text:    format!(".byte ${:02X}", opcode) }
+        }
+    }
+}
+
+// Disassembles every instruction starting in the inclusive range
+// `start`..`end`. The last instruction may extend past `end`.
+pub fn disassemble<B: Bus>(bus: &B, start: Address, end: Address,
+                           variant: Variant)
+    -> Vec<DisassembledInstr>
+{
+    let mut out = Vec::new();
+    let mut address = start.to_u16() as u32;
+
+    // Count in u32 so that a range ending at $FFFF doesn't wrap around.
+    while address <= end.to_u16() as u32 {
+        let instr = disassemble_instruction(bus, Address(address as u16),
+                                            variant);
+        address += instr.bytes.len() as u32;
+        out.push(instr);
+    }
+
+    out
+}
+
+#[cfg(test)]
+use memory::Memory;
+
+#[test]
+fn disassemble_test() {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0xC000), &[
+        0xA9, 0x01,       // LDA #$01
+        0x0A,             // ASL A
+        0xB1, 0x10,       // LDA ($10),Y
+        0x61, 0x20,       // ADC ($20,X)
+        0x6C, 0x34, 0x12, // JMP ($1234)
+        0x9D, 0x00, 0x80, // STA $8000,X
+        0xB6, 0x7F,       // LDX $7F,Y
+        0xD0, 0xF1,       // BNE $C002
+        0x60,             // RTS
+        0x02,             // JAM
+    ]);
+
+    let lines: Vec<String> =
+        disassemble(&memory, Address(0xC000), Address(0xC012), Variant::Nmos)
+            .iter()
+            .map(|instr| instr.to_string())
+            .collect();
+
+    assert_eq!(lines, vec![
+        "C000  A9 01     LDA #$01",
+        "C002  0A        ASL A",
+        "C003  B1 10     LDA ($10),Y",
+        "C005  61 20     ADC ($20,X)",
+        "C007  6C 34 12  JMP ($1234)",
+        "C00A  9D 00 80  STA $8000,X",
+        "C00D  B6 7F     LDX $7F,Y",
+        "C00F  D0 F1     BNE $C002",
+        "C011  60        RTS",
+        "C012  02        JAM",
+    ]);
+}
+
+#[test]
+fn disassemble_relative_test() {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0x10F0), &[0xF0, 0x7F, 0x90, 0x80]);
+
+    let beq = disassemble_instruction(&memory, Address(0x10F0), Variant::Nmos);
+    assert_eq!(beq.text, "BEQ $1171");
+    assert_eq!(beq.next_address(), Address(0x10F2));
+
+    let bcc = disassemble_instruction(&memory, Address(0x10F2), Variant::Nmos);
+    assert_eq!(bcc.text, "BCC $1074");
+}
+
+#[test]
+fn disassemble_end_of_memory_test() {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0xFFFE), &[0xEA, 0xEA]);
+
+    let instrs = disassemble(&memory, Address(0xFFFE), Address(0xFFFF),
+                             Variant::Nmos);
+    assert_eq!(instrs.len(), 2);
+    assert_eq!(instrs[1].text, "NOP");
+}
+
+#[test]
+fn disassemble_cmos_test() {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0x0200), &[
+        0xB2, 0x10,       // LDA ($10)
+        0x7C, 0x00, 0x30, // JMP ($3000,X)
+        0x8F, 0x20, 0xFD, // BBS0 $20,$0205
+        0x80, 0x02,       // BRA $020C
+        0x64, 0x30,       // STZ $30
+        0xCB,             // WAI
+    ]);
+
+    let lines: Vec<String> =
+        disassemble(&memory, Address(0x0200), Address(0x020C), Variant::Cmos)
+            .iter()
+            .map(|instr| instr.to_string())
+            .collect();
+
+    assert_eq!(lines, vec![
+        "0200  B2 10     LDA ($10)",
+        "0202  7C 00 30  JMP ($3000,X)",
+        "0205  8F 20 FD  BBS0 $20,$0205",
+        "0208  80 02     BRA $020C",
+        "020A  64 30     STZ $30",
+        "020C  CB        WAI",
+    ]);
+
+    // The same bytes mean something else to an NMOS 6502
+    let nmos = disassemble_instruction(&memory, Address(0x0200), Variant::Nmos);
+    assert_eq!(nmos.text, "JAM");
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// What `Machine` records so that steps can be undone. See
+// `Machine::record_history`.
+
+use std::collections::VecDeque;
+
+use address::Address;
+use registers::Registers;
+
+// What one step changed: the machine's state from before it, and the
+// previous value of each byte it wrote, in the order they were written
+pub struct Delta {
+    pub registers:   Registers,
+    pub cycles:      u64,
+    pub nmi_pending: bool,
+    pub waiting:     bool,
+    pub stopped:     bool,
+    pub writes:      Vec<(Address, u8)>,
+}
+
+// The deltas of the most recent steps, in a ring buffer that forgets the
+// oldest one once it's full
+pub struct History {
+    deltas:   VecDeque<Delta>,
+    capacity: usize,
+
+    // Steps taken since recording started, less any that were undone
+    steps:    u64,
+
+    // The step in progress
+    current:  Option<Delta>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History { deltas:   VecDeque::with_capacity(capacity),
+                  capacity: capacity,
+                  steps:    0,
+                  current:  None }
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // How far back the history goes, as a number of steps since recording
+    // started
+    pub fn earliest_step(&self) -> u64 {
+        self.steps - self.deltas.len() as u64
+    }
+
+    pub fn begin(&mut self, delta: Delta) {
+        self.current = Some(delta);
+    }
+
+    pub fn record_write(&mut self, address: Address, old_value: u8) {
+        if let Some(ref mut delta) = self.current {
+            delta.writes.push((address, old_value));
+        }
+    }
+
+    // Ends the step in progress. Steps that didn't change anything (such as
+    // stopping at an invalid opcode) aren't kept.
+    pub fn finish(&mut self, changed: bool) {
+        let delta = match self.current.take() {
+            Some(delta) => delta,
+            None => return,
+        };
+        if !changed || self.capacity == 0 {
+            return;
+        }
+
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+        self.steps += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<Delta> {
+        let delta = self.deltas.pop_back();
+        if delta.is_some() {
+            self.steps -= 1;
+        }
+        delta
+    }
+}
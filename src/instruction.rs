@@ -27,6 +27,7 @@
 
 use address::Address;
 use address::AddressDiff;
+use bus::Bus;
 use machine::Machine;
 
 // Abbreviations
@@ -111,6 +112,145 @@ pub enum Instruction
 , TXA // Transfer X to Accumulator..... | N. ...Z. A            = X
 , TXS // Transfer X to Stack pointer... | .. .....       S      = X
 , TYA // Transfer Y to Accumulator..... | N. ...Z. A            = Y
+
+      // Undocumented NMOS instructions. The ones marked (unstable) depend on
+      // the particular chip and its temperature; they're given their most
+      // common behaviour.
+, ALR // AND then LSR.................. | N. ...ZC A            = (A & M) / 2
+, ANC // AND, carry from bit 7......... | N. ...ZC A            = A & M
+, ANE // (unstable) AND X AND M........ | N. ...Z. A            = (A|K) & X & M
+, ARR // AND then ROR.................. | NV ...ZC A            = C (A & M) rot.
+, DCP // DEC then CMP.................. | N. ...ZC            M = M - 1, A - M
+, ISC // INC then SBC.................. | NV ...ZC A          M = M + 1, A - M
+, JAM // halt the processor............ | .. .....         PC   =
+, LAS // LDA/TSX with AND.............. | N. ...Z. A X   S      = M & S
+, LAX // LDA and LDX................... | N. ...Z. A X          = M
+, LXA // (unstable) LAX immediate...... | N. ...Z. A X          = (A|K) & M
+, RLA // ROL then AND.................. | N. ...ZC A          M = C M rotated
+, RRA // ROR then ADC.................. | NV ...ZC A          M = C M rotated
+, SAX // Store A AND X................. | .. .....            M = A & X
+, SBX // CMP and DEX................... | N. ...ZC   X          = (A & X) - M
+, SHA // (unstable) Store A AND X AND H | .. .....            M = A & X & H+1
+, SHX // (unstable) Store X AND H...... | .. .....            M = X & H+1
+, SHY // (unstable) Store Y AND H...... | .. .....            M = Y & H+1
+, SLO // ASL then ORA.................. | N. ...ZC A          M = M << 1
+, SRE // LSR then EOR.................. | N. ...ZC A          M = M / 2
+, TAS // (unstable) SHA and TXS........ | .. .....       S    M = A & X & H+1
+
+      // 65C02 instructions. INC and DEC also gain an accumulator mode.
+, BBR0 // Branch if Bit 0 Reset........ | .. .....         PC   = !M bit 0
+, BBR1 // Branch if Bit 1 Reset........ | .. .....         PC   = !M bit 1
+, BBR2 // Branch if Bit 2 Reset........ | .. .....         PC   = !M bit 2
+, BBR3 // Branch if Bit 3 Reset........ | .. .....         PC   = !M bit 3
+, BBR4 // Branch if Bit 4 Reset........ | .. .....         PC   = !M bit 4
+, BBR5 // Branch if Bit 5 Reset........ | .. .....         PC   = !M bit 5
+, BBR6 // Branch if Bit 6 Reset........ | .. .....         PC   = !M bit 6
+, BBR7 // Branch if Bit 7 Reset........ | .. .....         PC   = !M bit 7
+, BBS0 // Branch if Bit 0 Set.......... | .. .....         PC   = M bit 0
+, BBS1 // Branch if Bit 1 Set.......... | .. .....         PC   = M bit 1
+, BBS2 // Branch if Bit 2 Set.......... | .. .....         PC   = M bit 2
+, BBS3 // Branch if Bit 3 Set.......... | .. .....         PC   = M bit 3
+, BBS4 // Branch if Bit 4 Set.......... | .. .....         PC   = M bit 4
+, BBS5 // Branch if Bit 5 Set.......... | .. .....         PC   = M bit 5
+, BBS6 // Branch if Bit 6 Set.......... | .. .....         PC   = M bit 6
+, BBS7 // Branch if Bit 7 Set.......... | .. .....         PC   = M bit 7
+, BRA // BRanch Always................. | .. .....         PC   =
+, PHX // PusH X register............... | .. .....       S    M = X
+, PHY // PusH Y register............... | .. .....       S    M = Y
+, PLX // PuLl X register............... | N. ...Z.   X   S      = M (stack)
+, PLY // PuLl Y register............... | N. ...Z.     Y S      = M (stack)
+, RMB0 // Reset Memory Bit 0........... | .. .....            M = M & !(1 << 0)
+, RMB1 // Reset Memory Bit 1........... | .. .....            M = M & !(1 << 1)
+, RMB2 // Reset Memory Bit 2........... | .. .....            M = M & !(1 << 2)
+, RMB3 // Reset Memory Bit 3........... | .. .....            M = M & !(1 << 3)
+, RMB4 // Reset Memory Bit 4........... | .. .....            M = M & !(1 << 4)
+, RMB5 // Reset Memory Bit 5........... | .. .....            M = M & !(1 << 5)
+, RMB6 // Reset Memory Bit 6........... | .. .....            M = M & !(1 << 6)
+, RMB7 // Reset Memory Bit 7........... | .. .....            M = M & !(1 << 7)
+, SMB0 // Set Memory Bit 0............. | .. .....            M = M | (1 << 0)
+, SMB1 // Set Memory Bit 1............. | .. .....            M = M | (1 << 1)
+, SMB2 // Set Memory Bit 2............. | .. .....            M = M | (1 << 2)
+, SMB3 // Set Memory Bit 3............. | .. .....            M = M | (1 << 3)
+, SMB4 // Set Memory Bit 4............. | .. .....            M = M | (1 << 4)
+, SMB5 // Set Memory Bit 5............. | .. .....            M = M | (1 << 5)
+, SMB6 // Set Memory Bit 6............. | .. .....            M = M | (1 << 6)
+, SMB7 // Set Memory Bit 7............. | .. .....            M = M | (1 << 7)
+, STP // SToP the processor............ | .. .....              =
+, STZ // STore Zero.................... | .. .....            M = 0
+, TRB // Test and Reset Bits........... | .. ...Z.            M = M & !A
+, TSB // Test and Set Bits............. | .. ...Z.            M = M | A
+, WAI // WAit for Interrupt............ | .. .....              =
+}
+
+impl Instruction {
+    // Instructions that only read their operand take an extra cycle when
+    // indexing carries into the high byte of the address. Stores and
+    // read-modify-write instructions always spend that cycle, so it's already
+    // part of their base cycle count.
+    pub fn has_page_crossing_penalty(self) -> bool {
+        match self {
+            Instruction::ADC | Instruction::AND | Instruction::CMP |
+            Instruction::EOR | Instruction::LDA | Instruction::LDX |
+            Instruction::LDY | Instruction::ORA | Instruction::SBC |
+            Instruction::LAS | Instruction::LAX | Instruction::NOP |
+            Instruction::BIT => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_undocumented(self) -> bool {
+        match self {
+            Instruction::ALR | Instruction::ANC | Instruction::ANE |
+            Instruction::ARR | Instruction::DCP | Instruction::ISC |
+            Instruction::JAM | Instruction::LAS | Instruction::LAX |
+            Instruction::LXA | Instruction::RLA | Instruction::RRA |
+            Instruction::SAX | Instruction::SBX | Instruction::SHA |
+            Instruction::SHX | Instruction::SHY | Instruction::SLO |
+            Instruction::SRE | Instruction::TAS => true,
+            _ => false,
+        }
+    }
+
+    // For BBRn, BBSn, RMBn and SMBn: the bit number n, and whether the
+    // instruction is about the bit being set (BBS and SMB) rather than reset
+    // (BBR and RMB).
+    pub fn bit_operation(self) -> Option<(u8, bool)> {
+        match self {
+            Instruction::BBR0 => Some((0, false)),
+            Instruction::BBR1 => Some((1, false)),
+            Instruction::BBR2 => Some((2, false)),
+            Instruction::BBR3 => Some((3, false)),
+            Instruction::BBR4 => Some((4, false)),
+            Instruction::BBR5 => Some((5, false)),
+            Instruction::BBR6 => Some((6, false)),
+            Instruction::BBR7 => Some((7, false)),
+            Instruction::BBS0 => Some((0, true)),
+            Instruction::BBS1 => Some((1, true)),
+            Instruction::BBS2 => Some((2, true)),
+            Instruction::BBS3 => Some((3, true)),
+            Instruction::BBS4 => Some((4, true)),
+            Instruction::BBS5 => Some((5, true)),
+            Instruction::BBS6 => Some((6, true)),
+            Instruction::BBS7 => Some((7, true)),
+            Instruction::RMB0 => Some((0, false)),
+            Instruction::RMB1 => Some((1, false)),
+            Instruction::RMB2 => Some((2, false)),
+            Instruction::RMB3 => Some((3, false)),
+            Instruction::RMB4 => Some((4, false)),
+            Instruction::RMB5 => Some((5, false)),
+            Instruction::RMB6 => Some((6, false)),
+            Instruction::RMB7 => Some((7, false)),
+            Instruction::SMB0 => Some((0, true)),
+            Instruction::SMB1 => Some((1, true)),
+            Instruction::SMB2 => Some((2, true)),
+            Instruction::SMB3 => Some((3, true)),
+            Instruction::SMB4 => Some((4, true)),
+            Instruction::SMB5 => Some((5, true)),
+            Instruction::SMB6 => Some((6, true)),
+            Instruction::SMB7 => Some((7, true)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Copy)]
@@ -119,9 +259,12 @@ pub enum OpInput {
     UseImmediate(u8),
     UseRelative(i8),
     UseAddress(Address),
+
+    // The zero page address and branch offset of BBRn and BBSn
+    UseZeroPageRelative(Address, i8),
 }
 
-#[derive(Copy)]
+#[derive(Copy, Debug, PartialEq, Eq)]
 pub enum AddressingMode
 //                 length
 { Accumulator      // 1    LSR A        work directly on accumulator
@@ -139,6 +282,15 @@ pub enum AddressingMode
                    //                   zero page address plus X register)
 , IndirectIndexedY // 2    LDA ($10),Y  load from (address stored at constant
                    //                   zero page address) plus Y register
+
+// 65C02 addressing modes
+, ZeroPageIndirect // 2    LDA ($10)    load from address stored at constant
+                   //                   zero page address
+, AbsoluteIndexedIndirect
+                   // 3    JMP ($1000,X) jump to address stored at (constant
+                   //                   address plus X register)
+, ZeroPageRelative // 3    BBR0 $10,L   zero page address, then branch
+                   //                   target as signed relative offset
 }
 
 fn arr_to_addr(arr: &[u8]) -> Address {
@@ -148,6 +300,14 @@ fn arr_to_addr(arr: &[u8]) -> Address {
     Address(x)
 }
 
+// Reads a pointer from the zero page. A pointer at $FF takes its high byte
+// from $00, not $0100.
+fn read_zero_page_pointer<B: Bus>(memory: &mut B, start: u8) -> Address {
+    let slice = [memory.get_byte(Address(start as u16)),
+                 memory.get_byte(Address(start.wrapping_add(1) as u16))];
+    arr_to_addr(&slice)
+}
+
 impl AddressingMode {
     pub fn extra_bytes(self) -> AddressDiff {
         let x = match self {
@@ -164,19 +324,61 @@ impl AddressingMode {
             AddressingMode::Indirect => 2,
             AddressingMode::IndexedIndirectX => 1,
             AddressingMode::IndirectIndexedY => 1,
+            AddressingMode::ZeroPageIndirect => 1,
+            AddressingMode::AbsoluteIndexedIndirect => 2,
+            AddressingMode::ZeroPageRelative => 2,
         };
         AddressDiff(x)
     }
 
-    pub fn process(self, machine: &Machine, arr: &[u8]) -> OpInput {
+    // Whether `process` gives the same result every time for the same operand
+    // bytes, whatever is in the registers and memory
+    pub fn is_fixed(self) -> bool {
+        match self {
+            AddressingMode::Accumulator | AddressingMode::Implied
+                | AddressingMode::Immediate | AddressingMode::ZeroPage
+                | AddressingMode::Relative | AddressingMode::Absolute
+                | AddressingMode::ZeroPageRelative => true,
+            _ => false,
+        }
+    }
+
+    // Whether indexing moves the effective address onto a different page than
+    // the base address. Only meaningful for the indexed absolute modes.
+    // (This peeks at the bus, so that the pointer read by IndirectIndexedY
+    // only has side effects once, in `process`.)
+    pub fn crosses_page_boundary<B: Bus>(self, machine: &Machine<B>,
+                                         arr: &[u8]) -> bool {
+        let x = machine.registers.index_x as u8;
+        let y = machine.registers.index_y as u8;
+
+        let (base, index) = match self {
+            AddressingMode::AbsoluteX => (arr_to_addr(arr), x),
+            AddressingMode::AbsoluteY => (arr_to_addr(arr), y),
+            AddressingMode::IndirectIndexedY => {
+                let lo = machine.memory.peek_byte(Address(arr[0] as u16));
+                let hi = machine.memory.peek_byte(
+                    Address(arr[0].wrapping_add(1) as u16));
+                (arr_to_addr(&[lo, hi]), y)
+            },
+            _ => return false,
+        };
+
+        let indexed = base + AddressDiff(index as i32);
+        base.get_page_number() != indexed.get_page_number()
+    }
+
+    pub fn process<B: Bus>(self, machine: &mut Machine<B>,
+                           arr: &[u8]) -> OpInput {
 
         debug_assert!({let AddressDiff(x) = self.extra_bytes();
                        arr.len() == x as usize});
 
         let x = machine.registers.index_x as u8;
         let y = machine.registers.index_y as u8;
+        let variant = machine.variant;
 
-        let memory = &machine.memory;
+        let memory = &mut machine.memory;
 
         match self {
             AddressingMode::Accumulator | AddressingMode::Implied => {
@@ -197,13 +399,13 @@ impl AddressingMode {
                 // Use [u8, ..1] from instruction
                 // Add to X register (as u8 -- the final address is in 0-page)
                 // (Output: an 8-bit zero-page address)
-                OpInput::UseAddress(Address((arr[0] + x) as u16))
+                OpInput::UseAddress(Address(arr[0].wrapping_add(x) as u16))
             },
             AddressingMode::ZeroPageY => {
                 // Use [u8, ..1] from instruction
                 // Add to Y register (as u8 -- the final address is in 0-page)
                 // (Output: an 8-bit zero-page address)
-                OpInput::UseAddress(Address((arr[0] + y) as u16))
+                OpInput::UseAddress(Address(arr[0].wrapping_add(y) as u16))
             },
             AddressingMode::Relative => {
                 // Use [u8, ..1] from instruction
@@ -227,293 +429,681 @@ impl AddressingMode {
             },
             AddressingMode::Indirect => {
                 // Use [u8, ..2] from instruction as an address. Interpret the
-                // two bytes starting at that address as an address.
+                // two bytes starting at that address as an address. The NMOS
+                // 6502 doesn't carry into the high byte of the pointer, so
+                // JMP ($12FF) takes the high byte from $1200.
                 // (Output: a 16-bit address)
-                let slice = memory.get_slice(arr_to_addr(arr), AddressDiff(2));
-                OpInput::UseAddress(arr_to_addr(slice))
+                let start = arr_to_addr(arr);
+                let next = if variant.has_indirect_jump_bug() {
+                    Address((start.to_u16() & 0xff00)
+                            | (start.get_offset().wrapping_add(1) as u16))
+                } else {
+                    start + AddressDiff(1)
+                };
+                let slice = [memory.get_byte(start), memory.get_byte(next)];
+                OpInput::UseAddress(arr_to_addr(&slice))
             },
             AddressingMode::IndexedIndirectX => {
                 // Use [u8, ..1] from instruction
                 // Add to X register with 0-page wraparound, like ZeroPageX.
                 // This is where the absolute (16-bit) target address is stored.
                 // (Output: a 16-bit address)
-                let start = arr[0] + x;
-                let slice = memory.get_slice(Address(start as u16),
-                                             AddressDiff(2));
-                OpInput::UseAddress(arr_to_addr(slice))
+                let start = arr[0].wrapping_add(x);
+                OpInput::UseAddress(read_zero_page_pointer(memory, start))
             },
             AddressingMode::IndirectIndexedY => {
                 // Use [u8, ..1] from instruction
                 // This is where the absolute (16-bit) target address is stored.
                 // Add Y register to this address to get the final address
                 // (Output: a 16-bit address)
-                let start = arr[0];
-                let slice = memory.get_slice(Address(start as u16),
-                                             AddressDiff(2));
-                OpInput::UseAddress(arr_to_addr(slice) + AddressDiff(y as i32))
+                let pointer = read_zero_page_pointer(memory, arr[0]);
+                OpInput::UseAddress(pointer + AddressDiff(y as i32))
+            },
+            AddressingMode::ZeroPageIndirect => {
+                // Use [u8, ..1] from instruction
+                // This is where the absolute (16-bit) target address is
+                // stored.
+                // (Output: a 16-bit address)
+                OpInput::UseAddress(read_zero_page_pointer(memory, arr[0]))
+            },
+            AddressingMode::AbsoluteIndexedIndirect => {
+                // Use [u8, ..2] from instruction as address, add X. Interpret
+                // the two bytes starting at that address as an address.
+                // (Output: a 16-bit address)
+                let start = arr_to_addr(arr) + AddressDiff(x as i32);
+                let slice = [memory.get_byte(start),
+                             memory.get_byte(start + AddressDiff(1))];
+                OpInput::UseAddress(arr_to_addr(&slice))
+            },
+            AddressingMode::ZeroPageRelative => {
+                // Use [u8, ..2] from instruction: a zero page address, and a
+                // branch offset relative to the next instruction
+                OpInput::UseZeroPageRelative(Address(arr[0] as u16),
+                                             arr[1] as i8)
             },
         }
     }
 }
 
+
 pub type DecodedInstr = (Instruction, OpInput);
 
-pub static OPCODES: [Option<(Instruction, AddressingMode)>; 256] = [
-/*0x00*/ Some((Instruction::BRK, AddressingMode::Implied)),
-/*0x01*/ Some((Instruction::ORA, AddressingMode::IndexedIndirectX)),
-/*0x02*/ None,
-/*0x03*/ None,
-/*0x04*/ None,
-/*0x05*/ Some((Instruction::ORA, AddressingMode::ZeroPage)),
-/*0x06*/ Some((Instruction::ASL, AddressingMode::ZeroPage)),
-/*0x07*/ None,
-/*0x08*/ Some((Instruction::PHP, AddressingMode::Implied)),
-/*0x09*/ Some((Instruction::ORA, AddressingMode::Immediate)),
-/*0x0A*/ Some((Instruction::ASL, AddressingMode::Accumulator)),
-/*0x0B*/ None,
-/*0x0C*/ None,
-/*0x0D*/ Some((Instruction::ORA, AddressingMode::Absolute)),
-/*0x0E*/ Some((Instruction::ASL, AddressingMode::Absolute)),
-/*0x0F*/ None,
-/*0x10*/ Some((Instruction::BPL, AddressingMode::Relative)),
-/*0x11*/ Some((Instruction::ORA, AddressingMode::IndirectIndexedY)),
-/*0x12*/ None,
-/*0x13*/ None,
-/*0x14*/ None,
-/*0x15*/ Some((Instruction::ORA, AddressingMode::ZeroPageX)),
-/*0x16*/ Some((Instruction::ASL, AddressingMode::ZeroPageX)),
-/*0x17*/ None,
-/*0x18*/ Some((Instruction::CLC, AddressingMode::Implied)),
-/*0x19*/ Some((Instruction::ORA, AddressingMode::AbsoluteY)),
-/*0x1A*/ None,
-/*0x1B*/ None,
-/*0x1C*/ None,
-/*0x1D*/ Some((Instruction::ORA, AddressingMode::AbsoluteX)),
-/*0x1E*/ Some((Instruction::ASL, AddressingMode::AbsoluteX)),
-/*0x1F*/ None,
-/*0x20*/ Some((Instruction::JSR, AddressingMode::Absolute)),
-/*0x21*/ Some((Instruction::AND, AddressingMode::IndexedIndirectX)),
-/*0x22*/ None,
-/*0x23*/ None,
-/*0x24*/ Some((Instruction::BIT, AddressingMode::ZeroPage)),
-/*0x25*/ Some((Instruction::AND, AddressingMode::ZeroPage)),
-/*0x26*/ Some((Instruction::ROL, AddressingMode::ZeroPage)),
-/*0x27*/ None,
-/*0x28*/ Some((Instruction::PLP, AddressingMode::Implied)),
-/*0x29*/ Some((Instruction::AND, AddressingMode::Immediate)),
-/*0x2A*/ Some((Instruction::ROL, AddressingMode::Accumulator)),
-/*0x2B*/ None,
-/*0x2C*/ Some((Instruction::BIT, AddressingMode::Absolute)),
-/*0x2D*/ Some((Instruction::AND, AddressingMode::Absolute)),
-/*0x2E*/ Some((Instruction::ROL, AddressingMode::Absolute)),
-/*0x2F*/ None,
-/*0x30*/ Some((Instruction::BMI, AddressingMode::Relative)),
-/*0x31*/ Some((Instruction::AND, AddressingMode::IndirectIndexedY)),
-/*0x32*/ None,
-/*0x33*/ None,
-/*0x34*/ None,
-/*0x35*/ Some((Instruction::AND, AddressingMode::ZeroPageX)),
-/*0x36*/ Some((Instruction::ROL, AddressingMode::ZeroPageX)),
-/*0x37*/ None,
-/*0x38*/ Some((Instruction::SEC, AddressingMode::Implied)),
-/*0x39*/ Some((Instruction::AND, AddressingMode::AbsoluteY)),
-/*0x3A*/ None,
-/*0x3B*/ None,
-/*0x3C*/ None,
-/*0x3D*/ Some((Instruction::AND, AddressingMode::AbsoluteX)),
-/*0x3E*/ Some((Instruction::ROL, AddressingMode::AbsoluteX)),
-/*0x3F*/ None,
-/*0x40*/ Some((Instruction::RTI, AddressingMode::Implied)),
-/*0x41*/ Some((Instruction::EOR, AddressingMode::IndexedIndirectX)),
-/*0x42*/ None,
-/*0x43*/ None,
-/*0x44*/ None,
-/*0x45*/ Some((Instruction::EOR, AddressingMode::ZeroPage)),
-/*0x46*/ Some((Instruction::LSR, AddressingMode::ZeroPage)),
-/*0x47*/ None,
-/*0x48*/ Some((Instruction::PHA, AddressingMode::Implied)),
-/*0x49*/ Some((Instruction::EOR, AddressingMode::Immediate)),
-/*0x4A*/ Some((Instruction::LSR, AddressingMode::Accumulator)),
-/*0x4B*/ None,
-/*0x4C*/ Some((Instruction::JMP, AddressingMode::Absolute)),
-/*0x4D*/ Some((Instruction::EOR, AddressingMode::Absolute)),
-/*0x4E*/ Some((Instruction::LSR, AddressingMode::Absolute)),
-/*0x4F*/ None,
-/*0x50*/ Some((Instruction::BVC, AddressingMode::Relative)),
-/*0x51*/ Some((Instruction::EOR, AddressingMode::IndirectIndexedY)),
-/*0x52*/ None,
-/*0x53*/ None,
-/*0x54*/ None,
-/*0x55*/ Some((Instruction::EOR, AddressingMode::ZeroPageX)),
-/*0x56*/ Some((Instruction::LSR, AddressingMode::ZeroPageX)),
-/*0x57*/ None,
-/*0x58*/ None,
-/*0x59*/ Some((Instruction::EOR, AddressingMode::AbsoluteY)),
-/*0x5A*/ None,
-/*0x5B*/ None,
-/*0x5C*/ None,
-/*0x5D*/ Some((Instruction::EOR, AddressingMode::AbsoluteX)),
-/*0x5E*/ Some((Instruction::LSR, AddressingMode::AbsoluteX)),
-/*0x5F*/ None,
-/*0x60*/ Some((Instruction::RTS, AddressingMode::Implied)),
-/*0x61*/ Some((Instruction::ADC, AddressingMode::IndexedIndirectX)),
-/*0x62*/ None,
-/*0x63*/ None,
-/*0x64*/ None,
-/*0x65*/ Some((Instruction::ADC, AddressingMode::ZeroPage)),
-/*0x66*/ Some((Instruction::ROR, AddressingMode::ZeroPage)),
-/*0x67*/ None,
-/*0x68*/ Some((Instruction::PLA, AddressingMode::Implied)),
-/*0x69*/ Some((Instruction::ADC, AddressingMode::Immediate)),
-/*0x6A*/ Some((Instruction::ROR, AddressingMode::Accumulator)),
-/*0x6B*/ None,
-/*0x6C*/ Some((Instruction::JMP, AddressingMode::Indirect)),
-/*0x6D*/ Some((Instruction::ADC, AddressingMode::Absolute)),
-/*0x6E*/ Some((Instruction::ROR, AddressingMode::Absolute)),
-/*0x6F*/ None,
-/*0x70*/ Some((Instruction::BVS, AddressingMode::Relative)),
-/*0x71*/ Some((Instruction::ADC, AddressingMode::IndirectIndexedY)),
-/*0x72*/ None,
-/*0x73*/ None,
-/*0x74*/ None,
-/*0x75*/ Some((Instruction::ADC, AddressingMode::ZeroPageX)),
-/*0x76*/ Some((Instruction::ROR, AddressingMode::ZeroPageX)),
-/*0x77*/ None,
-/*0x78*/ Some((Instruction::SEI, AddressingMode::Implied)),
-/*0x79*/ Some((Instruction::ADC, AddressingMode::AbsoluteY)),
-/*0x7A*/ None,
-/*0x7B*/ None,
-/*0x7C*/ None,
-/*0x7D*/ Some((Instruction::ADC, AddressingMode::AbsoluteX)),
-/*0x7E*/ Some((Instruction::ROR, AddressingMode::AbsoluteX)),
-/*0x7F*/ None,
-/*0x80*/ None,
-/*0x81*/ Some((Instruction::STA, AddressingMode::IndexedIndirectX)),
-/*0x82*/ None,
-/*0x83*/ None,
-/*0x84*/ Some((Instruction::STY, AddressingMode::ZeroPage)),
-/*0x85*/ Some((Instruction::STA, AddressingMode::ZeroPage)),
-/*0x86*/ Some((Instruction::STX, AddressingMode::ZeroPage)),
-/*0x87*/ None,
-/*0x88*/ Some((Instruction::DEY, AddressingMode::Implied)),
-/*0x89*/ None,
-/*0x8A*/ Some((Instruction::TXA, AddressingMode::Implied)),
-/*0x8B*/ None,
-/*0x8C*/ Some((Instruction::STY, AddressingMode::Absolute)),
-/*0x8D*/ Some((Instruction::STA, AddressingMode::Absolute)),
-/*0x8E*/ Some((Instruction::STX, AddressingMode::Absolute)),
-/*0x8F*/ None,
-/*0x90*/ Some((Instruction::BCC, AddressingMode::Relative)),
-/*0x91*/ Some((Instruction::STA, AddressingMode::IndirectIndexedY)),
-/*0x92*/ None,
-/*0x93*/ None,
-/*0x94*/ Some((Instruction::STY, AddressingMode::ZeroPageX)),
-/*0x95*/ Some((Instruction::STA, AddressingMode::ZeroPageX)),
-/*0x96*/ Some((Instruction::STX, AddressingMode::ZeroPageY)),
-/*0x97*/ None,
-/*0x98*/ Some((Instruction::TYA, AddressingMode::Implied)),
-/*0x99*/ Some((Instruction::STA, AddressingMode::AbsoluteY)),
-/*0x9A*/ Some((Instruction::TXS, AddressingMode::Implied)),
-/*0x9B*/ None,
-/*0x9C*/ None,
-/*0x9D*/ Some((Instruction::STA, AddressingMode::AbsoluteX)),
-/*0x9E*/ None,
-/*0x9F*/ None,
-/*0xA0*/ Some((Instruction::LDY, AddressingMode::Immediate)),
-/*0xA1*/ Some((Instruction::LDA, AddressingMode::IndexedIndirectX)),
-/*0xA2*/ Some((Instruction::LDX, AddressingMode::Immediate)),
-/*0xA3*/ None,
-/*0xA4*/ Some((Instruction::LDY, AddressingMode::ZeroPage)),
-/*0xA5*/ Some((Instruction::LDA, AddressingMode::ZeroPage)),
-/*0xA6*/ Some((Instruction::LDX, AddressingMode::ZeroPage)),
-/*0xA7*/ None,
-/*0xA8*/ Some((Instruction::TAY, AddressingMode::Implied)),
-/*0xA9*/ Some((Instruction::LDA, AddressingMode::Immediate)),
-/*0xAA*/ Some((Instruction::TAX, AddressingMode::Implied)),
-/*0xAB*/ None,
-/*0xAC*/ Some((Instruction::LDY, AddressingMode::Absolute)),
-/*0xAD*/ Some((Instruction::LDA, AddressingMode::Absolute)),
-/*0xAE*/ Some((Instruction::LDX, AddressingMode::Absolute)),
-/*0xAF*/ None,
-/*0xB0*/ Some((Instruction::BCS, AddressingMode::Relative)),
-/*0xB1*/ Some((Instruction::LDA, AddressingMode::IndirectIndexedY)),
-/*0xB2*/ None,
-/*0xB3*/ None,
-/*0xB4*/ Some((Instruction::LDY, AddressingMode::ZeroPageX)),
-/*0xB5*/ Some((Instruction::LDA, AddressingMode::ZeroPageX)),
-/*0xB6*/ Some((Instruction::LDX, AddressingMode::ZeroPageY)),
-/*0xB7*/ None,
-/*0xB8*/ Some((Instruction::CLV, AddressingMode::Implied)),
-/*0xB9*/ Some((Instruction::LDA, AddressingMode::AbsoluteY)),
-/*0xBA*/ Some((Instruction::TSX, AddressingMode::Implied)),
-/*0xBB*/ None,
-/*0xBC*/ Some((Instruction::LDY, AddressingMode::AbsoluteX)),
-/*0xBD*/ Some((Instruction::LDA, AddressingMode::AbsoluteX)),
-/*0xBE*/ Some((Instruction::LDX, AddressingMode::AbsoluteY)),
-/*0xBF*/ None,
-/*0xC0*/ Some((Instruction::CPY, AddressingMode::Immediate)),
-/*0xC1*/ Some((Instruction::CMP, AddressingMode::IndexedIndirectX)),
-/*0xC2*/ None,
-/*0xC3*/ None,
-/*0xC4*/ Some((Instruction::CPY, AddressingMode::ZeroPage)),
-/*0xC5*/ Some((Instruction::CMP, AddressingMode::ZeroPage)),
-/*0xC6*/ Some((Instruction::DEC, AddressingMode::ZeroPage)),
-/*0xC7*/ None,
-/*0xC8*/ Some((Instruction::INY, AddressingMode::Implied)),
-/*0xC9*/ Some((Instruction::CMP, AddressingMode::Immediate)),
-/*0xCA*/ Some((Instruction::DEX, AddressingMode::Implied)),
-/*0xCB*/ None,
-/*0xCC*/ Some((Instruction::CPY, AddressingMode::Absolute)),
-/*0xCD*/ Some((Instruction::CMP, AddressingMode::Absolute)),
-/*0xCE*/ Some((Instruction::DEC, AddressingMode::Absolute)),
-/*0xCF*/ None,
-/*0xD0*/ Some((Instruction::BNE, AddressingMode::Relative)),
-/*0xD1*/ Some((Instruction::CMP, AddressingMode::IndirectIndexedY)),
-/*0xD2*/ None,
-/*0xD3*/ None,
-/*0xD4*/ None,
-/*0xD5*/ Some((Instruction::CMP, AddressingMode::ZeroPageX)),
-/*0xD6*/ Some((Instruction::DEC, AddressingMode::ZeroPageX)),
-/*0xD7*/ None,
-/*0xD8*/ Some((Instruction::CLD, AddressingMode::Implied)),
-/*0xD9*/ Some((Instruction::CMP, AddressingMode::AbsoluteY)),
-/*0xDA*/ None,
-/*0xDB*/ None,
-/*0xDC*/ None,
-/*0xDD*/ Some((Instruction::CMP, AddressingMode::AbsoluteX)),
-/*0xDE*/ Some((Instruction::DEC, AddressingMode::AbsoluteX)),
-/*0xDF*/ None,
-/*0xE0*/ Some((Instruction::CPX, AddressingMode::Immediate)),
-/*0xE1*/ Some((Instruction::SBC, AddressingMode::IndexedIndirectX)),
-/*0xE2*/ None,
-/*0xE3*/ None,
-/*0xE4*/ Some((Instruction::CPX, AddressingMode::ZeroPage)),
-/*0xE5*/ Some((Instruction::SBC, AddressingMode::ZeroPage)),
-/*0xE6*/ Some((Instruction::INC, AddressingMode::ZeroPage)),
-/*0xE7*/ None,
-/*0xE8*/ Some((Instruction::INX, AddressingMode::Implied)),
-/*0xE9*/ Some((Instruction::SBC, AddressingMode::Immediate)),
-/*0xEA*/ Some((Instruction::NOP, AddressingMode::Implied)),
-/*0xEB*/ None,
-/*0xEC*/ Some((Instruction::CPX, AddressingMode::Absolute)),
-/*0xED*/ Some((Instruction::SBC, AddressingMode::Absolute)),
-/*0xEE*/ Some((Instruction::INC, AddressingMode::Absolute)),
-/*0xEF*/ None,
-/*0xF0*/ Some((Instruction::BEQ, AddressingMode::Relative)),
-/*0xF1*/ Some((Instruction::SBC, AddressingMode::IndirectIndexedY)),
-/*0xF2*/ None,
-/*0xF3*/ None,
-/*0xF4*/ None,
-/*0xF5*/ Some((Instruction::SBC, AddressingMode::ZeroPageX)),
-/*0xF6*/ Some((Instruction::INC, AddressingMode::ZeroPageX)),
-/*0xF7*/ None,
-/*0xF8*/ Some((Instruction::SED, AddressingMode::Implied)),
-/*0xF9*/ Some((Instruction::SBC, AddressingMode::AbsoluteY)),
-/*0xFA*/ None,
-/*0xFB*/ None,
-/*0xFC*/ None,
-/*0xFD*/ Some((Instruction::SBC, AddressingMode::AbsoluteX)),
-/*0xFE*/ Some((Instruction::INC, AddressingMode::AbsoluteX)),
-/*0xFF*/ None,
+// An instruction that's been looked up in the opcode table and had its
+// operand bytes fetched, which is all of decoding that doesn't depend on the
+// registers or the rest of memory. `input` is the operand already worked out,
+// for the addressing modes that don't depend on those either.
+#[derive(Copy)]
+pub struct PredecodedInstr {
+    pub opcode:       u8,
+    pub instr:        Instruction,
+    pub am:           AddressingMode,
+    pub cycles:       u8,
+    pub operand:      [u8; 2],
+    pub input:        Option<OpInput>,
+
+    // What the variant says about the opcode, so that it isn't looked up
+    // again on every step: whether it's undocumented, and whether indexing
+    // across a page costs it an extra cycle
+    pub undocumented: bool,
+    pub page_penalty: bool,
+}
+
+impl PredecodedInstr {
+    pub fn operand_bytes(&self) -> &[u8] {
+        let AddressDiff(len) = self.am.extra_bytes();
+        &self.operand[..(len as usize)]
+    }
+
+    // Number of bytes the instruction takes up, including the opcode
+    pub fn len(&self) -> AddressDiff {
+        AddressDiff(1) + self.am.extra_bytes()
+    }
+}
+
+// The processors that can be emulated. They differ in which instructions
+// they decode, and in some details of how instructions behave.
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    // The original NMOS 6502, including its undocumented instructions
+    Nmos,
+
+    // The WDC/Rockwell 65C02
+    Cmos,
+
+    // The Ricoh 2A03 used in the NES: an NMOS 6502 whose decimal mode has
+    // been disconnected, so that ADC and SBC ignore the D flag
+    Ricoh2A03,
+}
+
+impl Variant {
+    pub fn opcodes(self)
+        -> &'static [Option<(Instruction, AddressingMode, u8)>; 256]
+    {
+        match self {
+            Variant::Nmos | Variant::Ricoh2A03 => &OPCODES,
+            Variant::Cmos => &CMOS_OPCODES,
+        }
+    }
+
+    // Whether JMP ($xxFF) takes the high byte of the target from $xx00
+    // instead of the next page, as on the NMOS 6502
+    pub fn has_indirect_jump_bug(self) -> bool {
+        self != Variant::Cmos
+    }
+
+    // Whether `instr` takes an extra cycle when indexing crosses a page. On
+    // the 65C02 the shifts and rotates only take it when they need it, too,
+    // though INC and DEC abs,X always take 7 cycles.
+    pub fn has_page_crossing_penalty(self, instr: Instruction) -> bool {
+        match instr {
+            Instruction::ASL | Instruction::LSR | Instruction::ROL |
+            Instruction::ROR => self == Variant::Cmos,
+            _ => instr.has_page_crossing_penalty(),
+        }
+    }
+
+    // Whether ADC and SBC do decimal arithmetic when D is set. D itself still
+    // works as a flag either way.
+    pub fn has_decimal_mode(self) -> bool {
+        self != Variant::Ricoh2A03
+    }
+
+    // Whether an opcode is one of the undocumented ones. On the NMOS 6502
+    // these are the undocumented instructions, all the NOPs other than $EA
+    // and a second SBC #imm at $EB, and the 2A03 has the same ones. On the
+    // 65C02 they're the NOPs other than $EA.
+    pub fn is_undocumented_opcode(self, opcode: u8) -> bool {
+        let nmos = self != Variant::Cmos;
+        match self.opcodes()[opcode as usize] {
+            Some((Instruction::NOP, _, _)) => opcode != 0xEA,
+            Some((Instruction::SBC, _, _)) if nmos => opcode == 0xEB,
+            Some((instr, _, _)) if nmos => instr.is_undocumented(),
+            _ => false,
+        }
+    }
+}
+
+// Each entry is the instruction, its addressing mode and its base cycle count.
+// Page crossings and taken branches cost extra cycles, which the machine adds
+// on top of the base count. The undocumented opcodes are included; see
+// `Variant::is_undocumented_opcode`.
+pub static OPCODES: [Option<(Instruction, AddressingMode, u8)>; 256] = [
+/*0x00*/ Some((Instruction::BRK, AddressingMode::Implied, 7)),
+/*0x01*/ Some((Instruction::ORA, AddressingMode::IndexedIndirectX, 6)),
+/*0x02*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x03*/ Some((Instruction::SLO, AddressingMode::IndexedIndirectX, 8)),
+/*0x04*/ Some((Instruction::NOP, AddressingMode::ZeroPage, 3)),
+/*0x05*/ Some((Instruction::ORA, AddressingMode::ZeroPage, 3)),
+/*0x06*/ Some((Instruction::ASL, AddressingMode::ZeroPage, 5)),
+/*0x07*/ Some((Instruction::SLO, AddressingMode::ZeroPage, 5)),
+/*0x08*/ Some((Instruction::PHP, AddressingMode::Implied, 3)),
+/*0x09*/ Some((Instruction::ORA, AddressingMode::Immediate, 2)),
+/*0x0A*/ Some((Instruction::ASL, AddressingMode::Accumulator, 2)),
+/*0x0B*/ Some((Instruction::ANC, AddressingMode::Immediate, 2)),
+/*0x0C*/ Some((Instruction::NOP, AddressingMode::Absolute, 4)),
+/*0x0D*/ Some((Instruction::ORA, AddressingMode::Absolute, 4)),
+/*0x0E*/ Some((Instruction::ASL, AddressingMode::Absolute, 6)),
+/*0x0F*/ Some((Instruction::SLO, AddressingMode::Absolute, 6)),
+/*0x10*/ Some((Instruction::BPL, AddressingMode::Relative, 2)),
+/*0x11*/ Some((Instruction::ORA, AddressingMode::IndirectIndexedY, 5)),
+/*0x12*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x13*/ Some((Instruction::SLO, AddressingMode::IndirectIndexedY, 8)),
+/*0x14*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0x15*/ Some((Instruction::ORA, AddressingMode::ZeroPageX, 4)),
+/*0x16*/ Some((Instruction::ASL, AddressingMode::ZeroPageX, 6)),
+/*0x17*/ Some((Instruction::SLO, AddressingMode::ZeroPageX, 6)),
+/*0x18*/ Some((Instruction::CLC, AddressingMode::Implied, 2)),
+/*0x19*/ Some((Instruction::ORA, AddressingMode::AbsoluteY, 4)),
+/*0x1A*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0x1B*/ Some((Instruction::SLO, AddressingMode::AbsoluteY, 7)),
+/*0x1C*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0x1D*/ Some((Instruction::ORA, AddressingMode::AbsoluteX, 4)),
+/*0x1E*/ Some((Instruction::ASL, AddressingMode::AbsoluteX, 7)),
+/*0x1F*/ Some((Instruction::SLO, AddressingMode::AbsoluteX, 7)),
+/*0x20*/ Some((Instruction::JSR, AddressingMode::Absolute, 6)),
+/*0x21*/ Some((Instruction::AND, AddressingMode::IndexedIndirectX, 6)),
+/*0x22*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x23*/ Some((Instruction::RLA, AddressingMode::IndexedIndirectX, 8)),
+/*0x24*/ Some((Instruction::BIT, AddressingMode::ZeroPage, 3)),
+/*0x25*/ Some((Instruction::AND, AddressingMode::ZeroPage, 3)),
+/*0x26*/ Some((Instruction::ROL, AddressingMode::ZeroPage, 5)),
+/*0x27*/ Some((Instruction::RLA, AddressingMode::ZeroPage, 5)),
+/*0x28*/ Some((Instruction::PLP, AddressingMode::Implied, 4)),
+/*0x29*/ Some((Instruction::AND, AddressingMode::Immediate, 2)),
+/*0x2A*/ Some((Instruction::ROL, AddressingMode::Accumulator, 2)),
+/*0x2B*/ Some((Instruction::ANC, AddressingMode::Immediate, 2)),
+/*0x2C*/ Some((Instruction::BIT, AddressingMode::Absolute, 4)),
+/*0x2D*/ Some((Instruction::AND, AddressingMode::Absolute, 4)),
+/*0x2E*/ Some((Instruction::ROL, AddressingMode::Absolute, 6)),
+/*0x2F*/ Some((Instruction::RLA, AddressingMode::Absolute, 6)),
+/*0x30*/ Some((Instruction::BMI, AddressingMode::Relative, 2)),
+/*0x31*/ Some((Instruction::AND, AddressingMode::IndirectIndexedY, 5)),
+/*0x32*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x33*/ Some((Instruction::RLA, AddressingMode::IndirectIndexedY, 8)),
+/*0x34*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0x35*/ Some((Instruction::AND, AddressingMode::ZeroPageX, 4)),
+/*0x36*/ Some((Instruction::ROL, AddressingMode::ZeroPageX, 6)),
+/*0x37*/ Some((Instruction::RLA, AddressingMode::ZeroPageX, 6)),
+/*0x38*/ Some((Instruction::SEC, AddressingMode::Implied, 2)),
+/*0x39*/ Some((Instruction::AND, AddressingMode::AbsoluteY, 4)),
+/*0x3A*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0x3B*/ Some((Instruction::RLA, AddressingMode::AbsoluteY, 7)),
+/*0x3C*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0x3D*/ Some((Instruction::AND, AddressingMode::AbsoluteX, 4)),
+/*0x3E*/ Some((Instruction::ROL, AddressingMode::AbsoluteX, 7)),
+/*0x3F*/ Some((Instruction::RLA, AddressingMode::AbsoluteX, 7)),
+/*0x40*/ Some((Instruction::RTI, AddressingMode::Implied, 6)),
+/*0x41*/ Some((Instruction::EOR, AddressingMode::IndexedIndirectX, 6)),
+/*0x42*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x43*/ Some((Instruction::SRE, AddressingMode::IndexedIndirectX, 8)),
+/*0x44*/ Some((Instruction::NOP, AddressingMode::ZeroPage, 3)),
+/*0x45*/ Some((Instruction::EOR, AddressingMode::ZeroPage, 3)),
+/*0x46*/ Some((Instruction::LSR, AddressingMode::ZeroPage, 5)),
+/*0x47*/ Some((Instruction::SRE, AddressingMode::ZeroPage, 5)),
+/*0x48*/ Some((Instruction::PHA, AddressingMode::Implied, 3)),
+/*0x49*/ Some((Instruction::EOR, AddressingMode::Immediate, 2)),
+/*0x4A*/ Some((Instruction::LSR, AddressingMode::Accumulator, 2)),
+/*0x4B*/ Some((Instruction::ALR, AddressingMode::Immediate, 2)),
+/*0x4C*/ Some((Instruction::JMP, AddressingMode::Absolute, 3)),
+/*0x4D*/ Some((Instruction::EOR, AddressingMode::Absolute, 4)),
+/*0x4E*/ Some((Instruction::LSR, AddressingMode::Absolute, 6)),
+/*0x4F*/ Some((Instruction::SRE, AddressingMode::Absolute, 6)),
+/*0x50*/ Some((Instruction::BVC, AddressingMode::Relative, 2)),
+/*0x51*/ Some((Instruction::EOR, AddressingMode::IndirectIndexedY, 5)),
+/*0x52*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x53*/ Some((Instruction::SRE, AddressingMode::IndirectIndexedY, 8)),
+/*0x54*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0x55*/ Some((Instruction::EOR, AddressingMode::ZeroPageX, 4)),
+/*0x56*/ Some((Instruction::LSR, AddressingMode::ZeroPageX, 6)),
+/*0x57*/ Some((Instruction::SRE, AddressingMode::ZeroPageX, 6)),
+/*0x58*/ Some((Instruction::CLI, AddressingMode::Implied, 2)),
+/*0x59*/ Some((Instruction::EOR, AddressingMode::AbsoluteY, 4)),
+/*0x5A*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0x5B*/ Some((Instruction::SRE, AddressingMode::AbsoluteY, 7)),
+/*0x5C*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0x5D*/ Some((Instruction::EOR, AddressingMode::AbsoluteX, 4)),
+/*0x5E*/ Some((Instruction::LSR, AddressingMode::AbsoluteX, 7)),
+/*0x5F*/ Some((Instruction::SRE, AddressingMode::AbsoluteX, 7)),
+/*0x60*/ Some((Instruction::RTS, AddressingMode::Implied, 6)),
+/*0x61*/ Some((Instruction::ADC, AddressingMode::IndexedIndirectX, 6)),
+/*0x62*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x63*/ Some((Instruction::RRA, AddressingMode::IndexedIndirectX, 8)),
+/*0x64*/ Some((Instruction::NOP, AddressingMode::ZeroPage, 3)),
+/*0x65*/ Some((Instruction::ADC, AddressingMode::ZeroPage, 3)),
+/*0x66*/ Some((Instruction::ROR, AddressingMode::ZeroPage, 5)),
+/*0x67*/ Some((Instruction::RRA, AddressingMode::ZeroPage, 5)),
+/*0x68*/ Some((Instruction::PLA, AddressingMode::Implied, 4)),
+/*0x69*/ Some((Instruction::ADC, AddressingMode::Immediate, 2)),
+/*0x6A*/ Some((Instruction::ROR, AddressingMode::Accumulator, 2)),
+/*0x6B*/ Some((Instruction::ARR, AddressingMode::Immediate, 2)),
+/*0x6C*/ Some((Instruction::JMP, AddressingMode::Indirect, 5)),
+/*0x6D*/ Some((Instruction::ADC, AddressingMode::Absolute, 4)),
+/*0x6E*/ Some((Instruction::ROR, AddressingMode::Absolute, 6)),
+/*0x6F*/ Some((Instruction::RRA, AddressingMode::Absolute, 6)),
+/*0x70*/ Some((Instruction::BVS, AddressingMode::Relative, 2)),
+/*0x71*/ Some((Instruction::ADC, AddressingMode::IndirectIndexedY, 5)),
+/*0x72*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x73*/ Some((Instruction::RRA, AddressingMode::IndirectIndexedY, 8)),
+/*0x74*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0x75*/ Some((Instruction::ADC, AddressingMode::ZeroPageX, 4)),
+/*0x76*/ Some((Instruction::ROR, AddressingMode::ZeroPageX, 6)),
+/*0x77*/ Some((Instruction::RRA, AddressingMode::ZeroPageX, 6)),
+/*0x78*/ Some((Instruction::SEI, AddressingMode::Implied, 2)),
+/*0x79*/ Some((Instruction::ADC, AddressingMode::AbsoluteY, 4)),
+/*0x7A*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0x7B*/ Some((Instruction::RRA, AddressingMode::AbsoluteY, 7)),
+/*0x7C*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0x7D*/ Some((Instruction::ADC, AddressingMode::AbsoluteX, 4)),
+/*0x7E*/ Some((Instruction::ROR, AddressingMode::AbsoluteX, 7)),
+/*0x7F*/ Some((Instruction::RRA, AddressingMode::AbsoluteX, 7)),
+/*0x80*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x81*/ Some((Instruction::STA, AddressingMode::IndexedIndirectX, 6)),
+/*0x82*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x83*/ Some((Instruction::SAX, AddressingMode::IndexedIndirectX, 6)),
+/*0x84*/ Some((Instruction::STY, AddressingMode::ZeroPage, 3)),
+/*0x85*/ Some((Instruction::STA, AddressingMode::ZeroPage, 3)),
+/*0x86*/ Some((Instruction::STX, AddressingMode::ZeroPage, 3)),
+/*0x87*/ Some((Instruction::SAX, AddressingMode::ZeroPage, 3)),
+/*0x88*/ Some((Instruction::DEY, AddressingMode::Implied, 2)),
+/*0x89*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x8A*/ Some((Instruction::TXA, AddressingMode::Implied, 2)),
+/*0x8B*/ Some((Instruction::ANE, AddressingMode::Immediate, 2)),
+/*0x8C*/ Some((Instruction::STY, AddressingMode::Absolute, 4)),
+/*0x8D*/ Some((Instruction::STA, AddressingMode::Absolute, 4)),
+/*0x8E*/ Some((Instruction::STX, AddressingMode::Absolute, 4)),
+/*0x8F*/ Some((Instruction::SAX, AddressingMode::Absolute, 4)),
+/*0x90*/ Some((Instruction::BCC, AddressingMode::Relative, 2)),
+/*0x91*/ Some((Instruction::STA, AddressingMode::IndirectIndexedY, 6)),
+/*0x92*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0x93*/ Some((Instruction::SHA, AddressingMode::IndirectIndexedY, 6)),
+/*0x94*/ Some((Instruction::STY, AddressingMode::ZeroPageX, 4)),
+/*0x95*/ Some((Instruction::STA, AddressingMode::ZeroPageX, 4)),
+/*0x96*/ Some((Instruction::STX, AddressingMode::ZeroPageY, 4)),
+/*0x97*/ Some((Instruction::SAX, AddressingMode::ZeroPageY, 4)),
+/*0x98*/ Some((Instruction::TYA, AddressingMode::Implied, 2)),
+/*0x99*/ Some((Instruction::STA, AddressingMode::AbsoluteY, 5)),
+/*0x9A*/ Some((Instruction::TXS, AddressingMode::Implied, 2)),
+/*0x9B*/ Some((Instruction::TAS, AddressingMode::AbsoluteY, 5)),
+/*0x9C*/ Some((Instruction::SHY, AddressingMode::AbsoluteX, 5)),
+/*0x9D*/ Some((Instruction::STA, AddressingMode::AbsoluteX, 5)),
+/*0x9E*/ Some((Instruction::SHX, AddressingMode::AbsoluteY, 5)),
+/*0x9F*/ Some((Instruction::SHA, AddressingMode::AbsoluteY, 5)),
+/*0xA0*/ Some((Instruction::LDY, AddressingMode::Immediate, 2)),
+/*0xA1*/ Some((Instruction::LDA, AddressingMode::IndexedIndirectX, 6)),
+/*0xA2*/ Some((Instruction::LDX, AddressingMode::Immediate, 2)),
+/*0xA3*/ Some((Instruction::LAX, AddressingMode::IndexedIndirectX, 6)),
+/*0xA4*/ Some((Instruction::LDY, AddressingMode::ZeroPage, 3)),
+/*0xA5*/ Some((Instruction::LDA, AddressingMode::ZeroPage, 3)),
+/*0xA6*/ Some((Instruction::LDX, AddressingMode::ZeroPage, 3)),
+/*0xA7*/ Some((Instruction::LAX, AddressingMode::ZeroPage, 3)),
+/*0xA8*/ Some((Instruction::TAY, AddressingMode::Implied, 2)),
+/*0xA9*/ Some((Instruction::LDA, AddressingMode::Immediate, 2)),
+/*0xAA*/ Some((Instruction::TAX, AddressingMode::Implied, 2)),
+/*0xAB*/ Some((Instruction::LXA, AddressingMode::Immediate, 2)),
+/*0xAC*/ Some((Instruction::LDY, AddressingMode::Absolute, 4)),
+/*0xAD*/ Some((Instruction::LDA, AddressingMode::Absolute, 4)),
+/*0xAE*/ Some((Instruction::LDX, AddressingMode::Absolute, 4)),
+/*0xAF*/ Some((Instruction::LAX, AddressingMode::Absolute, 4)),
+/*0xB0*/ Some((Instruction::BCS, AddressingMode::Relative, 2)),
+/*0xB1*/ Some((Instruction::LDA, AddressingMode::IndirectIndexedY, 5)),
+/*0xB2*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0xB3*/ Some((Instruction::LAX, AddressingMode::IndirectIndexedY, 5)),
+/*0xB4*/ Some((Instruction::LDY, AddressingMode::ZeroPageX, 4)),
+/*0xB5*/ Some((Instruction::LDA, AddressingMode::ZeroPageX, 4)),
+/*0xB6*/ Some((Instruction::LDX, AddressingMode::ZeroPageY, 4)),
+/*0xB7*/ Some((Instruction::LAX, AddressingMode::ZeroPageY, 4)),
+/*0xB8*/ Some((Instruction::CLV, AddressingMode::Implied, 2)),
+/*0xB9*/ Some((Instruction::LDA, AddressingMode::AbsoluteY, 4)),
+/*0xBA*/ Some((Instruction::TSX, AddressingMode::Implied, 2)),
+/*0xBB*/ Some((Instruction::LAS, AddressingMode::AbsoluteY, 4)),
+/*0xBC*/ Some((Instruction::LDY, AddressingMode::AbsoluteX, 4)),
+/*0xBD*/ Some((Instruction::LDA, AddressingMode::AbsoluteX, 4)),
+/*0xBE*/ Some((Instruction::LDX, AddressingMode::AbsoluteY, 4)),
+/*0xBF*/ Some((Instruction::LAX, AddressingMode::AbsoluteY, 4)),
+/*0xC0*/ Some((Instruction::CPY, AddressingMode::Immediate, 2)),
+/*0xC1*/ Some((Instruction::CMP, AddressingMode::IndexedIndirectX, 6)),
+/*0xC2*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0xC3*/ Some((Instruction::DCP, AddressingMode::IndexedIndirectX, 8)),
+/*0xC4*/ Some((Instruction::CPY, AddressingMode::ZeroPage, 3)),
+/*0xC5*/ Some((Instruction::CMP, AddressingMode::ZeroPage, 3)),
+/*0xC6*/ Some((Instruction::DEC, AddressingMode::ZeroPage, 5)),
+/*0xC7*/ Some((Instruction::DCP, AddressingMode::ZeroPage, 5)),
+/*0xC8*/ Some((Instruction::INY, AddressingMode::Implied, 2)),
+/*0xC9*/ Some((Instruction::CMP, AddressingMode::Immediate, 2)),
+/*0xCA*/ Some((Instruction::DEX, AddressingMode::Implied, 2)),
+/*0xCB*/ Some((Instruction::SBX, AddressingMode::Immediate, 2)),
+/*0xCC*/ Some((Instruction::CPY, AddressingMode::Absolute, 4)),
+/*0xCD*/ Some((Instruction::CMP, AddressingMode::Absolute, 4)),
+/*0xCE*/ Some((Instruction::DEC, AddressingMode::Absolute, 6)),
+/*0xCF*/ Some((Instruction::DCP, AddressingMode::Absolute, 6)),
+/*0xD0*/ Some((Instruction::BNE, AddressingMode::Relative, 2)),
+/*0xD1*/ Some((Instruction::CMP, AddressingMode::IndirectIndexedY, 5)),
+/*0xD2*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0xD3*/ Some((Instruction::DCP, AddressingMode::IndirectIndexedY, 8)),
+/*0xD4*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0xD5*/ Some((Instruction::CMP, AddressingMode::ZeroPageX, 4)),
+/*0xD6*/ Some((Instruction::DEC, AddressingMode::ZeroPageX, 6)),
+/*0xD7*/ Some((Instruction::DCP, AddressingMode::ZeroPageX, 6)),
+/*0xD8*/ Some((Instruction::CLD, AddressingMode::Implied, 2)),
+/*0xD9*/ Some((Instruction::CMP, AddressingMode::AbsoluteY, 4)),
+/*0xDA*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0xDB*/ Some((Instruction::DCP, AddressingMode::AbsoluteY, 7)),
+/*0xDC*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0xDD*/ Some((Instruction::CMP, AddressingMode::AbsoluteX, 4)),
+/*0xDE*/ Some((Instruction::DEC, AddressingMode::AbsoluteX, 7)),
+/*0xDF*/ Some((Instruction::DCP, AddressingMode::AbsoluteX, 7)),
+/*0xE0*/ Some((Instruction::CPX, AddressingMode::Immediate, 2)),
+/*0xE1*/ Some((Instruction::SBC, AddressingMode::IndexedIndirectX, 6)),
+/*0xE2*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0xE3*/ Some((Instruction::ISC, AddressingMode::IndexedIndirectX, 8)),
+/*0xE4*/ Some((Instruction::CPX, AddressingMode::ZeroPage, 3)),
+/*0xE5*/ Some((Instruction::SBC, AddressingMode::ZeroPage, 3)),
+/*0xE6*/ Some((Instruction::INC, AddressingMode::ZeroPage, 5)),
+/*0xE7*/ Some((Instruction::ISC, AddressingMode::ZeroPage, 5)),
+/*0xE8*/ Some((Instruction::INX, AddressingMode::Implied, 2)),
+/*0xE9*/ Some((Instruction::SBC, AddressingMode::Immediate, 2)),
+/*0xEA*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0xEB*/ Some((Instruction::SBC, AddressingMode::Immediate, 2)),
+/*0xEC*/ Some((Instruction::CPX, AddressingMode::Absolute, 4)),
+/*0xED*/ Some((Instruction::SBC, AddressingMode::Absolute, 4)),
+/*0xEE*/ Some((Instruction::INC, AddressingMode::Absolute, 6)),
+/*0xEF*/ Some((Instruction::ISC, AddressingMode::Absolute, 6)),
+/*0xF0*/ Some((Instruction::BEQ, AddressingMode::Relative, 2)),
+/*0xF1*/ Some((Instruction::SBC, AddressingMode::IndirectIndexedY, 5)),
+/*0xF2*/ Some((Instruction::JAM, AddressingMode::Implied, 2)),
+/*0xF3*/ Some((Instruction::ISC, AddressingMode::IndirectIndexedY, 8)),
+/*0xF4*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0xF5*/ Some((Instruction::SBC, AddressingMode::ZeroPageX, 4)),
+/*0xF6*/ Some((Instruction::INC, AddressingMode::ZeroPageX, 6)),
+/*0xF7*/ Some((Instruction::ISC, AddressingMode::ZeroPageX, 6)),
+/*0xF8*/ Some((Instruction::SED, AddressingMode::Implied, 2)),
+/*0xF9*/ Some((Instruction::SBC, AddressingMode::AbsoluteY, 4)),
+/*0xFA*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0xFB*/ Some((Instruction::ISC, AddressingMode::AbsoluteY, 7)),
+/*0xFC*/ Some((Instruction::NOP, AddressingMode::AbsoluteX, 4)),
+/*0xFD*/ Some((Instruction::SBC, AddressingMode::AbsoluteX, 4)),
+/*0xFE*/ Some((Instruction::INC, AddressingMode::AbsoluteX, 7)),
+/*0xFF*/ Some((Instruction::ISC, AddressingMode::AbsoluteX, 7)),
 ];
 
+// The 65C02 adds some instructions and addressing modes, and turns all the
+// opcodes that are undefined on the NMOS 6502 into NOPs of various lengths.
+// JMP ($xxxx) takes an extra cycle to fix the NMOS page wrapping bug.
+pub static CMOS_OPCODES: [Option<(Instruction, AddressingMode, u8)>; 256] = [
+/*0x00*/ Some((Instruction::BRK, AddressingMode::Implied, 7)),
+/*0x01*/ Some((Instruction::ORA, AddressingMode::IndexedIndirectX, 6)),
+/*0x02*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x03*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x04*/ Some((Instruction::TSB, AddressingMode::ZeroPage, 5)),
+/*0x05*/ Some((Instruction::ORA, AddressingMode::ZeroPage, 3)),
+/*0x06*/ Some((Instruction::ASL, AddressingMode::ZeroPage, 5)),
+/*0x07*/ Some((Instruction::RMB0, AddressingMode::ZeroPage, 5)),
+/*0x08*/ Some((Instruction::PHP, AddressingMode::Implied, 3)),
+/*0x09*/ Some((Instruction::ORA, AddressingMode::Immediate, 2)),
+/*0x0A*/ Some((Instruction::ASL, AddressingMode::Accumulator, 2)),
+/*0x0B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x0C*/ Some((Instruction::TSB, AddressingMode::Absolute, 6)),
+/*0x0D*/ Some((Instruction::ORA, AddressingMode::Absolute, 4)),
+/*0x0E*/ Some((Instruction::ASL, AddressingMode::Absolute, 6)),
+/*0x0F*/ Some((Instruction::BBR0, AddressingMode::ZeroPageRelative, 5)),
+/*0x10*/ Some((Instruction::BPL, AddressingMode::Relative, 2)),
+/*0x11*/ Some((Instruction::ORA, AddressingMode::IndirectIndexedY, 5)),
+/*0x12*/ Some((Instruction::ORA, AddressingMode::ZeroPageIndirect, 5)),
+/*0x13*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x14*/ Some((Instruction::TRB, AddressingMode::ZeroPage, 5)),
+/*0x15*/ Some((Instruction::ORA, AddressingMode::ZeroPageX, 4)),
+/*0x16*/ Some((Instruction::ASL, AddressingMode::ZeroPageX, 6)),
+/*0x17*/ Some((Instruction::RMB1, AddressingMode::ZeroPage, 5)),
+/*0x18*/ Some((Instruction::CLC, AddressingMode::Implied, 2)),
+/*0x19*/ Some((Instruction::ORA, AddressingMode::AbsoluteY, 4)),
+/*0x1A*/ Some((Instruction::INC, AddressingMode::Accumulator, 2)),
+/*0x1B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x1C*/ Some((Instruction::TRB, AddressingMode::Absolute, 6)),
+/*0x1D*/ Some((Instruction::ORA, AddressingMode::AbsoluteX, 4)),
+/*0x1E*/ Some((Instruction::ASL, AddressingMode::AbsoluteX, 6)),
+/*0x1F*/ Some((Instruction::BBR1, AddressingMode::ZeroPageRelative, 5)),
+/*0x20*/ Some((Instruction::JSR, AddressingMode::Absolute, 6)),
+/*0x21*/ Some((Instruction::AND, AddressingMode::IndexedIndirectX, 6)),
+/*0x22*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x23*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x24*/ Some((Instruction::BIT, AddressingMode::ZeroPage, 3)),
+/*0x25*/ Some((Instruction::AND, AddressingMode::ZeroPage, 3)),
+/*0x26*/ Some((Instruction::ROL, AddressingMode::ZeroPage, 5)),
+/*0x27*/ Some((Instruction::RMB2, AddressingMode::ZeroPage, 5)),
+/*0x28*/ Some((Instruction::PLP, AddressingMode::Implied, 4)),
+/*0x29*/ Some((Instruction::AND, AddressingMode::Immediate, 2)),
+/*0x2A*/ Some((Instruction::ROL, AddressingMode::Accumulator, 2)),
+/*0x2B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x2C*/ Some((Instruction::BIT, AddressingMode::Absolute, 4)),
+/*0x2D*/ Some((Instruction::AND, AddressingMode::Absolute, 4)),
+/*0x2E*/ Some((Instruction::ROL, AddressingMode::Absolute, 6)),
+/*0x2F*/ Some((Instruction::BBR2, AddressingMode::ZeroPageRelative, 5)),
+/*0x30*/ Some((Instruction::BMI, AddressingMode::Relative, 2)),
+/*0x31*/ Some((Instruction::AND, AddressingMode::IndirectIndexedY, 5)),
+/*0x32*/ Some((Instruction::AND, AddressingMode::ZeroPageIndirect, 5)),
+/*0x33*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x34*/ Some((Instruction::BIT, AddressingMode::ZeroPageX, 4)),
+/*0x35*/ Some((Instruction::AND, AddressingMode::ZeroPageX, 4)),
+/*0x36*/ Some((Instruction::ROL, AddressingMode::ZeroPageX, 6)),
+/*0x37*/ Some((Instruction::RMB3, AddressingMode::ZeroPage, 5)),
+/*0x38*/ Some((Instruction::SEC, AddressingMode::Implied, 2)),
+/*0x39*/ Some((Instruction::AND, AddressingMode::AbsoluteY, 4)),
+/*0x3A*/ Some((Instruction::DEC, AddressingMode::Accumulator, 2)),
+/*0x3B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x3C*/ Some((Instruction::BIT, AddressingMode::AbsoluteX, 4)),
+/*0x3D*/ Some((Instruction::AND, AddressingMode::AbsoluteX, 4)),
+/*0x3E*/ Some((Instruction::ROL, AddressingMode::AbsoluteX, 6)),
+/*0x3F*/ Some((Instruction::BBR3, AddressingMode::ZeroPageRelative, 5)),
+/*0x40*/ Some((Instruction::RTI, AddressingMode::Implied, 6)),
+/*0x41*/ Some((Instruction::EOR, AddressingMode::IndexedIndirectX, 6)),
+/*0x42*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x43*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x44*/ Some((Instruction::NOP, AddressingMode::ZeroPage, 3)),
+/*0x45*/ Some((Instruction::EOR, AddressingMode::ZeroPage, 3)),
+/*0x46*/ Some((Instruction::LSR, AddressingMode::ZeroPage, 5)),
+/*0x47*/ Some((Instruction::RMB4, AddressingMode::ZeroPage, 5)),
+/*0x48*/ Some((Instruction::PHA, AddressingMode::Implied, 3)),
+/*0x49*/ Some((Instruction::EOR, AddressingMode::Immediate, 2)),
+/*0x4A*/ Some((Instruction::LSR, AddressingMode::Accumulator, 2)),
+/*0x4B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x4C*/ Some((Instruction::JMP, AddressingMode::Absolute, 3)),
+/*0x4D*/ Some((Instruction::EOR, AddressingMode::Absolute, 4)),
+/*0x4E*/ Some((Instruction::LSR, AddressingMode::Absolute, 6)),
+/*0x4F*/ Some((Instruction::BBR4, AddressingMode::ZeroPageRelative, 5)),
+/*0x50*/ Some((Instruction::BVC, AddressingMode::Relative, 2)),
+/*0x51*/ Some((Instruction::EOR, AddressingMode::IndirectIndexedY, 5)),
+/*0x52*/ Some((Instruction::EOR, AddressingMode::ZeroPageIndirect, 5)),
+/*0x53*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x54*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0x55*/ Some((Instruction::EOR, AddressingMode::ZeroPageX, 4)),
+/*0x56*/ Some((Instruction::LSR, AddressingMode::ZeroPageX, 6)),
+/*0x57*/ Some((Instruction::RMB5, AddressingMode::ZeroPage, 5)),
+/*0x58*/ Some((Instruction::CLI, AddressingMode::Implied, 2)),
+/*0x59*/ Some((Instruction::EOR, AddressingMode::AbsoluteY, 4)),
+/*0x5A*/ Some((Instruction::PHY, AddressingMode::Implied, 3)),
+/*0x5B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x5C*/ Some((Instruction::NOP, AddressingMode::Absolute, 8)),
+/*0x5D*/ Some((Instruction::EOR, AddressingMode::AbsoluteX, 4)),
+/*0x5E*/ Some((Instruction::LSR, AddressingMode::AbsoluteX, 6)),
+/*0x5F*/ Some((Instruction::BBR5, AddressingMode::ZeroPageRelative, 5)),
+/*0x60*/ Some((Instruction::RTS, AddressingMode::Implied, 6)),
+/*0x61*/ Some((Instruction::ADC, AddressingMode::IndexedIndirectX, 6)),
+/*0x62*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x63*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x64*/ Some((Instruction::STZ, AddressingMode::ZeroPage, 3)),
+/*0x65*/ Some((Instruction::ADC, AddressingMode::ZeroPage, 3)),
+/*0x66*/ Some((Instruction::ROR, AddressingMode::ZeroPage, 5)),
+/*0x67*/ Some((Instruction::RMB6, AddressingMode::ZeroPage, 5)),
+/*0x68*/ Some((Instruction::PLA, AddressingMode::Implied, 4)),
+/*0x69*/ Some((Instruction::ADC, AddressingMode::Immediate, 2)),
+/*0x6A*/ Some((Instruction::ROR, AddressingMode::Accumulator, 2)),
+/*0x6B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x6C*/ Some((Instruction::JMP, AddressingMode::Indirect, 6)),
+/*0x6D*/ Some((Instruction::ADC, AddressingMode::Absolute, 4)),
+/*0x6E*/ Some((Instruction::ROR, AddressingMode::Absolute, 6)),
+/*0x6F*/ Some((Instruction::BBR6, AddressingMode::ZeroPageRelative, 5)),
+/*0x70*/ Some((Instruction::BVS, AddressingMode::Relative, 2)),
+/*0x71*/ Some((Instruction::ADC, AddressingMode::IndirectIndexedY, 5)),
+/*0x72*/ Some((Instruction::ADC, AddressingMode::ZeroPageIndirect, 5)),
+/*0x73*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x74*/ Some((Instruction::STZ, AddressingMode::ZeroPageX, 4)),
+/*0x75*/ Some((Instruction::ADC, AddressingMode::ZeroPageX, 4)),
+/*0x76*/ Some((Instruction::ROR, AddressingMode::ZeroPageX, 6)),
+/*0x77*/ Some((Instruction::RMB7, AddressingMode::ZeroPage, 5)),
+/*0x78*/ Some((Instruction::SEI, AddressingMode::Implied, 2)),
+/*0x79*/ Some((Instruction::ADC, AddressingMode::AbsoluteY, 4)),
+/*0x7A*/ Some((Instruction::PLY, AddressingMode::Implied, 4)),
+/*0x7B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x7C*/ Some((Instruction::JMP, AddressingMode::AbsoluteIndexedIndirect, 6)),
+/*0x7D*/ Some((Instruction::ADC, AddressingMode::AbsoluteX, 4)),
+/*0x7E*/ Some((Instruction::ROR, AddressingMode::AbsoluteX, 6)),
+/*0x7F*/ Some((Instruction::BBR7, AddressingMode::ZeroPageRelative, 5)),
+/*0x80*/ Some((Instruction::BRA, AddressingMode::Relative, 2)),
+/*0x81*/ Some((Instruction::STA, AddressingMode::IndexedIndirectX, 6)),
+/*0x82*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0x83*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x84*/ Some((Instruction::STY, AddressingMode::ZeroPage, 3)),
+/*0x85*/ Some((Instruction::STA, AddressingMode::ZeroPage, 3)),
+/*0x86*/ Some((Instruction::STX, AddressingMode::ZeroPage, 3)),
+/*0x87*/ Some((Instruction::SMB0, AddressingMode::ZeroPage, 5)),
+/*0x88*/ Some((Instruction::DEY, AddressingMode::Implied, 2)),
+/*0x89*/ Some((Instruction::BIT, AddressingMode::Immediate, 2)),
+/*0x8A*/ Some((Instruction::TXA, AddressingMode::Implied, 2)),
+/*0x8B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x8C*/ Some((Instruction::STY, AddressingMode::Absolute, 4)),
+/*0x8D*/ Some((Instruction::STA, AddressingMode::Absolute, 4)),
+/*0x8E*/ Some((Instruction::STX, AddressingMode::Absolute, 4)),
+/*0x8F*/ Some((Instruction::BBS0, AddressingMode::ZeroPageRelative, 5)),
+/*0x90*/ Some((Instruction::BCC, AddressingMode::Relative, 2)),
+/*0x91*/ Some((Instruction::STA, AddressingMode::IndirectIndexedY, 6)),
+/*0x92*/ Some((Instruction::STA, AddressingMode::ZeroPageIndirect, 5)),
+/*0x93*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x94*/ Some((Instruction::STY, AddressingMode::ZeroPageX, 4)),
+/*0x95*/ Some((Instruction::STA, AddressingMode::ZeroPageX, 4)),
+/*0x96*/ Some((Instruction::STX, AddressingMode::ZeroPageY, 4)),
+/*0x97*/ Some((Instruction::SMB1, AddressingMode::ZeroPage, 5)),
+/*0x98*/ Some((Instruction::TYA, AddressingMode::Implied, 2)),
+/*0x99*/ Some((Instruction::STA, AddressingMode::AbsoluteY, 5)),
+/*0x9A*/ Some((Instruction::TXS, AddressingMode::Implied, 2)),
+/*0x9B*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0x9C*/ Some((Instruction::STZ, AddressingMode::Absolute, 4)),
+/*0x9D*/ Some((Instruction::STA, AddressingMode::AbsoluteX, 5)),
+/*0x9E*/ Some((Instruction::STZ, AddressingMode::AbsoluteX, 5)),
+/*0x9F*/ Some((Instruction::BBS1, AddressingMode::ZeroPageRelative, 5)),
+/*0xA0*/ Some((Instruction::LDY, AddressingMode::Immediate, 2)),
+/*0xA1*/ Some((Instruction::LDA, AddressingMode::IndexedIndirectX, 6)),
+/*0xA2*/ Some((Instruction::LDX, AddressingMode::Immediate, 2)),
+/*0xA3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xA4*/ Some((Instruction::LDY, AddressingMode::ZeroPage, 3)),
+/*0xA5*/ Some((Instruction::LDA, AddressingMode::ZeroPage, 3)),
+/*0xA6*/ Some((Instruction::LDX, AddressingMode::ZeroPage, 3)),
+/*0xA7*/ Some((Instruction::SMB2, AddressingMode::ZeroPage, 5)),
+/*0xA8*/ Some((Instruction::TAY, AddressingMode::Implied, 2)),
+/*0xA9*/ Some((Instruction::LDA, AddressingMode::Immediate, 2)),
+/*0xAA*/ Some((Instruction::TAX, AddressingMode::Implied, 2)),
+/*0xAB*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xAC*/ Some((Instruction::LDY, AddressingMode::Absolute, 4)),
+/*0xAD*/ Some((Instruction::LDA, AddressingMode::Absolute, 4)),
+/*0xAE*/ Some((Instruction::LDX, AddressingMode::Absolute, 4)),
+/*0xAF*/ Some((Instruction::BBS2, AddressingMode::ZeroPageRelative, 5)),
+/*0xB0*/ Some((Instruction::BCS, AddressingMode::Relative, 2)),
+/*0xB1*/ Some((Instruction::LDA, AddressingMode::IndirectIndexedY, 5)),
+/*0xB2*/ Some((Instruction::LDA, AddressingMode::ZeroPageIndirect, 5)),
+/*0xB3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xB4*/ Some((Instruction::LDY, AddressingMode::ZeroPageX, 4)),
+/*0xB5*/ Some((Instruction::LDA, AddressingMode::ZeroPageX, 4)),
+/*0xB6*/ Some((Instruction::LDX, AddressingMode::ZeroPageY, 4)),
+/*0xB7*/ Some((Instruction::SMB3, AddressingMode::ZeroPage, 5)),
+/*0xB8*/ Some((Instruction::CLV, AddressingMode::Implied, 2)),
+/*0xB9*/ Some((Instruction::LDA, AddressingMode::AbsoluteY, 4)),
+/*0xBA*/ Some((Instruction::TSX, AddressingMode::Implied, 2)),
+/*0xBB*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xBC*/ Some((Instruction::LDY, AddressingMode::AbsoluteX, 4)),
+/*0xBD*/ Some((Instruction::LDA, AddressingMode::AbsoluteX, 4)),
+/*0xBE*/ Some((Instruction::LDX, AddressingMode::AbsoluteY, 4)),
+/*0xBF*/ Some((Instruction::BBS3, AddressingMode::ZeroPageRelative, 5)),
+/*0xC0*/ Some((Instruction::CPY, AddressingMode::Immediate, 2)),
+/*0xC1*/ Some((Instruction::CMP, AddressingMode::IndexedIndirectX, 6)),
+/*0xC2*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0xC3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xC4*/ Some((Instruction::CPY, AddressingMode::ZeroPage, 3)),
+/*0xC5*/ Some((Instruction::CMP, AddressingMode::ZeroPage, 3)),
+/*0xC6*/ Some((Instruction::DEC, AddressingMode::ZeroPage, 5)),
+/*0xC7*/ Some((Instruction::SMB4, AddressingMode::ZeroPage, 5)),
+/*0xC8*/ Some((Instruction::INY, AddressingMode::Implied, 2)),
+/*0xC9*/ Some((Instruction::CMP, AddressingMode::Immediate, 2)),
+/*0xCA*/ Some((Instruction::DEX, AddressingMode::Implied, 2)),
+/*0xCB*/ Some((Instruction::WAI, AddressingMode::Implied, 3)),
+/*0xCC*/ Some((Instruction::CPY, AddressingMode::Absolute, 4)),
+/*0xCD*/ Some((Instruction::CMP, AddressingMode::Absolute, 4)),
+/*0xCE*/ Some((Instruction::DEC, AddressingMode::Absolute, 6)),
+/*0xCF*/ Some((Instruction::BBS4, AddressingMode::ZeroPageRelative, 5)),
+/*0xD0*/ Some((Instruction::BNE, AddressingMode::Relative, 2)),
+/*0xD1*/ Some((Instruction::CMP, AddressingMode::IndirectIndexedY, 5)),
+/*0xD2*/ Some((Instruction::CMP, AddressingMode::ZeroPageIndirect, 5)),
+/*0xD3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xD4*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0xD5*/ Some((Instruction::CMP, AddressingMode::ZeroPageX, 4)),
+/*0xD6*/ Some((Instruction::DEC, AddressingMode::ZeroPageX, 6)),
+/*0xD7*/ Some((Instruction::SMB5, AddressingMode::ZeroPage, 5)),
+/*0xD8*/ Some((Instruction::CLD, AddressingMode::Implied, 2)),
+/*0xD9*/ Some((Instruction::CMP, AddressingMode::AbsoluteY, 4)),
+/*0xDA*/ Some((Instruction::PHX, AddressingMode::Implied, 3)),
+/*0xDB*/ Some((Instruction::STP, AddressingMode::Implied, 3)),
+/*0xDC*/ Some((Instruction::NOP, AddressingMode::Absolute, 4)),
+/*0xDD*/ Some((Instruction::CMP, AddressingMode::AbsoluteX, 4)),
+/*0xDE*/ Some((Instruction::DEC, AddressingMode::AbsoluteX, 7)),
+/*0xDF*/ Some((Instruction::BBS5, AddressingMode::ZeroPageRelative, 5)),
+/*0xE0*/ Some((Instruction::CPX, AddressingMode::Immediate, 2)),
+/*0xE1*/ Some((Instruction::SBC, AddressingMode::IndexedIndirectX, 6)),
+/*0xE2*/ Some((Instruction::NOP, AddressingMode::Immediate, 2)),
+/*0xE3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xE4*/ Some((Instruction::CPX, AddressingMode::ZeroPage, 3)),
+/*0xE5*/ Some((Instruction::SBC, AddressingMode::ZeroPage, 3)),
+/*0xE6*/ Some((Instruction::INC, AddressingMode::ZeroPage, 5)),
+/*0xE7*/ Some((Instruction::SMB6, AddressingMode::ZeroPage, 5)),
+/*0xE8*/ Some((Instruction::INX, AddressingMode::Implied, 2)),
+/*0xE9*/ Some((Instruction::SBC, AddressingMode::Immediate, 2)),
+/*0xEA*/ Some((Instruction::NOP, AddressingMode::Implied, 2)),
+/*0xEB*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xEC*/ Some((Instruction::CPX, AddressingMode::Absolute, 4)),
+/*0xED*/ Some((Instruction::SBC, AddressingMode::Absolute, 4)),
+/*0xEE*/ Some((Instruction::INC, AddressingMode::Absolute, 6)),
+/*0xEF*/ Some((Instruction::BBS6, AddressingMode::ZeroPageRelative, 5)),
+/*0xF0*/ Some((Instruction::BEQ, AddressingMode::Relative, 2)),
+/*0xF1*/ Some((Instruction::SBC, AddressingMode::IndirectIndexedY, 5)),
+/*0xF2*/ Some((Instruction::SBC, AddressingMode::ZeroPageIndirect, 5)),
+/*0xF3*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xF4*/ Some((Instruction::NOP, AddressingMode::ZeroPageX, 4)),
+/*0xF5*/ Some((Instruction::SBC, AddressingMode::ZeroPageX, 4)),
+/*0xF6*/ Some((Instruction::INC, AddressingMode::ZeroPageX, 6)),
+/*0xF7*/ Some((Instruction::SMB7, AddressingMode::ZeroPage, 5)),
+/*0xF8*/ Some((Instruction::SED, AddressingMode::Implied, 2)),
+/*0xF9*/ Some((Instruction::SBC, AddressingMode::AbsoluteY, 4)),
+/*0xFA*/ Some((Instruction::PLX, AddressingMode::Implied, 4)),
+/*0xFB*/ Some((Instruction::NOP, AddressingMode::Implied, 1)),
+/*0xFC*/ Some((Instruction::NOP, AddressingMode::Absolute, 4)),
+/*0xFD*/ Some((Instruction::SBC, AddressingMode::AbsoluteX, 4)),
+/*0xFE*/ Some((Instruction::INC, AddressingMode::AbsoluteX, 7)),
+/*0xFF*/ Some((Instruction::BBS7, AddressingMode::ZeroPageRelative, 5)),
+];
@@ -0,0 +1,708 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// The 65C816's instructions, addressing modes and opcode table. The decoder
+// works the same way as the 6502's: look the opcode up in `OPCODES_816`, read
+// the operand bytes and turn them into an `OpInput816` with `process`.
+
+use address::{Address, AddressDiff, LongAddress};
+use bus::LongBus;
+use machine816::Machine816;
+
+// Abbreviations as in `instruction`, plus
+//
+//        C | the whole 16-bit accumulator
+//        D | direct page register
+//      DBR | data bank register
+//      PBR | program bank register
+//        E | emulation mode flag
+//
+// Registers are 16 bits wide when the M and X flags are clear.
+
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub enum Instruction816
+      //                                  i/o vars should be listed as follows:
+      //                                  NV BDIZC A X Y S PC M
+      //
+      //                                | outputs               | inputs
+{ ADC // ADd with Carry................ | NV ...ZC A            = A + M + C
+, AND // logical AND (bitwise)......... | N. ...Z. A            = A && M
+, ASL // Arithmetic Shift Left......... | N. ...ZC A            = M << 1
+, BCC // Branch if Carry Clear......... | .. .....         PC   = !C
+, BCS // Branch if Carry Set........... | .. .....         PC   = C
+, BEQ // Branch if Equal (to zero?).... | .. .....         PC   = Z
+, BIT // BIT test...................... | NV ...Z.              = A & M
+, BMI // Branch if Minus............... | .. .....         PC   = N
+, BNE // Branch if Not Equal........... | .. .....         PC   = !Z
+, BPL // Branch if Positive............ | .. .....         PC   = Z
+, BRK // BReaK......................... | .. B....       S PC   =
+, BVC // Branch if oVerflow Clear...... | .. .....         PC   = !V
+, BVS // Branch if oVerflow Set........ | .. .....         PC   = V
+, CLC // CLear Carry flag.............. | .. ....C              = 0
+, CLD // Clear Decimal Mode............ | .. .D...              = 0
+, CLI // Clear Interrupt Disable....... | .. ..I..              = 0
+, CLV // Clear oVerflow flag........... | .V .....              = 0
+, CMP // Compare....................... | N. ...ZC              = A - M
+, CPX // Compare X register............ | N. ...ZC              = X - M
+, CPY // Compare Y register............ | N. ...ZC              = Y - M
+, DEC // DECrement memory.............. | N. ...Z.            M = M - 1
+, DEX // DEcrement X register.......... | N. ...Z.   X          = X - 1
+, DEY // DEcrement Y register.......... | N. ...Z.     Y        = Y - 1
+, EOR // Exclusive OR (bitwise)........ | N. ...Z. A            = A ^ M
+, INC // INCrement memory.............. | N. ...Z.            M = M + 1
+, INX // INcrement X register.......... | N. ...Z.   X          = X + 1
+, INY // INcrement Y register.......... | N. ...Z.     Y        = Y + 1
+, JMP // JuMP.......................... | .. .....       S PC   =
+, JSR // Jump to SubRoutine............ | .. .....       S PC   =
+, LDA // LoaD Accumulator.............. | N. ...Z. A            = M
+, LDX // LoaD X register............... | N. ...Z.   X          = M
+, LDY // LoaD Y register............... | N. ...Z.     Y        = M
+, LSR // Logical Shift Right........... | N. ...ZC A            = A/2
+, NOP // No OPeration.................. | .. .....              =
+, ORA // inclusive OR (bitwise)........ | N. ...Z. A            = A | M
+, PHA // PusH Accumulator.............. | .. .....       S    M = A
+, PHP // PusH Processor status......... | .. .....       S    M = F
+, PLA // PuLl Accumulator.............. | N. ...Z. A     S      = M (stack)
+, PLP // PuLl Processor status......... | NV BDIZC       S      = M (stack)
+, ROL // ROtate Left................... | N. ...ZC A            = C A rotated
+, ROR // ROtate Right.................. | N. ...ZC A            = C A rotated
+, RTI // ReTurn from Interrupt......... | NV BDIZC         PC   = M (stack)
+, RTS // ReTurn from Subroutine........ | .. .....         PC   = M (stack)
+, SBC // SuBtract with Carry........... | NV ...ZC A            = A-M-(1-C)
+, SEC // SEt Carry flag................ | .. ....C              = 1
+, SED // SEt Decimal flag.............. | .. .D...              = 1
+, SEI // SEt Interrupt disable......... | .. ..I..              = 1
+, STA // STore Accumulator............. | .. .....            M = A
+, STX // STore X register.............. | .. .....            M = X
+, STY // STore Y register.............. | .. .....            M = Y
+, TAX // Transfer Accumulator to X..... | N. ...Z.   X          = A
+, TAY // Transfer Accumulator to Y..... | N. ...Z.     Y        = A
+, TSX // Transfer Stack pointer to X... | N. ...Z.   X          = S
+, TXA // Transfer X to Accumulator..... | N. ...Z. A            = X
+, TXS // Transfer X to Stack pointer... | .. .....       S      = X
+, TYA // Transfer Y to Accumulator..... | N. ...Z. A            = Y
+
+      // Also on the 65C02
+, BRA // BRanch Always................. | .. .....         PC   =
+, PHX // PusH X register............... | .. .....       S    M = X
+, PHY // PusH Y register............... | .. .....       S    M = Y
+, PLX // PuLl X register............... | N. ...Z.   X   S      = M (stack)
+, PLY // PuLl Y register............... | N. ...Z.     Y S      = M (stack)
+, STP // SToP the processor............ | .. .....              =
+, STZ // STore Zero.................... | .. .....            M = 0
+, TRB // Test and Reset Bits........... | .. ...Z.            M = M & !A
+, TSB // Test and Set Bits............. | .. ...Z.            M = M | A
+, WAI // WAit for Interrupt............ | .. .....              =
+
+      // Only on the 65C816
+, BRL // BRanch Long................... | .. .....         PC   =
+, COP // COProcessor interrupt......... | .. .DI..       S PC   =
+, JML // JuMp Long..................... | .. .....         PC   = PBR M
+, JSL // Jump to Subroutine Long....... | .. .....       S PC   = PBR M
+, MVN // MoVe block, Next.............. | .. ..... A X Y      M = M, DBR
+, MVP // MoVe block, Previous.......... | .. ..... A X Y      M = M, DBR
+, PEA // Push Effective Address........ | .. .....       S    M = M
+, PEI // Push Effective Indirect....... | .. .....       S    M = M
+, PER // Push Effective Relative....... | .. .....       S    M = PC
+, PHB // PusH data Bank................ | .. .....       S    M = DBR
+, PHD // PusH Direct page.............. | .. .....       S    M = D
+, PHK // PusH program banK............. | .. .....       S    M = PBR
+, PLB // PuLl data Bank................ | N. ...Z.       S      = M (stack)
+, PLD // PuLl Direct page.............. | N. ...Z.       S      = M (stack)
+, REP // REset Processor status bits... | NV BDIZC              = F & !M
+, RTL // ReTurn from subroutine Long... | .. .....       S PC   = PBR M (stack)
+, SEP // SEt Processor status bits..... | NV BDIZC              = F | M
+, TCD // Transfer C to D............... | N. ...Z.              = C
+, TCS // Transfer C to S............... | .. .....       S      = C
+, TDC // Transfer D to C............... | N. ...Z. A            = D
+, TSC // Transfer S to C............... | N. ...Z. A            = S
+, TXY // Transfer X to Y............... | N. ...Z.     Y        = X
+, TYX // Transfer Y to X............... | N. ...Z.   X          = Y
+, WDM // (reserved) two byte NOP....... | .. .....              =
+, XBA // eXchange B and A.............. | N. ...Z. A            = A
+, XCE // eXchange Carry and Emulation.. | .. ....C              = E
+}
+
+impl Instruction816 {
+    // As on the 6502, instructions that only read their operand can take an
+    // extra cycle for indexing (see `AddressingMode816::has_index_penalty`).
+    pub fn has_page_crossing_penalty(self) -> bool {
+        match self {
+            Instruction816::ADC | Instruction816::AND | Instruction816::BIT |
+            Instruction816::CMP | Instruction816::EOR | Instruction816::LDA |
+            Instruction816::LDX | Instruction816::LDY | Instruction816::ORA |
+            Instruction816::SBC => true,
+            _ => false,
+        }
+    }
+}
+
+// Where an operand is in memory. Direct page and stack relative operands
+// wrap around within bank 0, while everything else carries on into the next
+// bank.
+#[derive(Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    BankZero(Address),
+    Long(LongAddress),
+}
+
+impl Location {
+    // The address of the given byte of a multi-byte operand
+    pub fn byte(self, n: u16) -> LongAddress {
+        match self {
+            Location::BankZero(Address(address)) =>
+                LongAddress(address.wrapping_add(n) as u32),
+            Location::Long(address) => address + AddressDiff(n as i32),
+        }
+    }
+
+    pub fn get_address(self) -> Address {
+        self.byte(0).get_address()
+    }
+}
+
+#[derive(Copy)]
+pub enum OpInput816 {
+    UseImplied,
+    UseImmediate(u16),
+    UseRelative(i16),
+    UseAddress(Location),
+
+    // The destination and source banks of MVN and MVP
+    UseBlockMove(u8, u8),
+}
+
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub enum AddressingMode816
+//                        length
+{ Accumulator             // 1    ASL A
+, Implied                 // 1    CLC
+, ImmediateM              // 2/3  LDA #$10       16-bit if the M flag is clear
+, ImmediateX              // 2/3  LDX #$10       16-bit if the X flag is clear
+, Immediate               // 2    REP #$30       always 8-bit
+, Direct                  // 2    LDA $10        direct page register + $10
+, DirectX                 // 2    LDA $10,X
+, DirectY                 // 2    LDX $10,Y
+, DirectIndirect          // 2    LDA ($10)      load from address stored in
+                          //                     the direct page, in the data
+                          //                     bank
+, DirectIndirectLong      // 2    LDA [$10]      ... a 24-bit address
+, DirectXIndirect         // 2    LDA ($10,X)
+, DirectIndirectY         // 2    LDA ($10),Y
+, DirectIndirectLongY     // 2    LDA [$10],Y
+, Absolute                // 3    LDA $1000      in the data bank (the program
+                          //                     bank for JMP and JSR)
+, AbsoluteX               // 3    LDA $1000,X
+, AbsoluteY               // 3    LDA $1000,Y
+, AbsoluteLong            // 4    LDA $123456    full 24-bit address
+, AbsoluteLongX           // 4    LDA $123456,X
+, AbsoluteIndirect        // 3    JMP ($1000)    pointer in bank 0
+, AbsoluteIndirectLong    // 3    JML [$1000]    24-bit pointer in bank 0
+, AbsoluteXIndirect       // 3    JMP ($1000,X)  pointer in the program bank
+, StackRelative           // 2    LDA $03,S      stack pointer + $03
+, StackRelativeIndirectY  // 2    LDA ($03,S),Y
+, Relative                // 2    BRA LABEL
+, RelativeLong            // 3    BRL LABEL      16-bit offset
+, BlockMove               // 3    MVN $01,$02    destination and source banks
+}
+
+fn arr_to_u16(arr: &[u8]) -> u16 {
+    (arr[0] as u16) | ((arr[1] as u16) << 8)
+}
+
+fn arr_to_long(arr: &[u8]) -> LongAddress {
+    LongAddress((arr[0] as u32) | ((arr[1] as u32) << 8)
+                | ((arr[2] as u32) << 16))
+}
+
+fn read_pointer<B: LongBus>(memory: &mut B, location: Location) -> u16 {
+    let lo = memory.get_byte(location.byte(0));
+    let hi = memory.get_byte(location.byte(1));
+    arr_to_u16(&[lo, hi])
+}
+
+fn read_long_pointer<B: LongBus>(memory: &mut B, location: Location)
+    -> LongAddress
+{
+    let bytes = [memory.get_byte(location.byte(0)),
+                 memory.get_byte(location.byte(1)),
+                 memory.get_byte(location.byte(2))];
+    arr_to_long(&bytes)
+}
+
+impl AddressingMode816 {
+    // Unlike on the 6502, the length of immediate operands depends on the
+    // M and X flags.
+    pub fn extra_bytes(self, wide_memory: bool, wide_index: bool)
+        -> AddressDiff
+    {
+        let x = match self {
+            AddressingMode816::Accumulator => 0,
+            AddressingMode816::Implied => 0,
+            AddressingMode816::ImmediateM => if wide_memory { 2 } else { 1 },
+            AddressingMode816::ImmediateX => if wide_index { 2 } else { 1 },
+            AddressingMode816::Immediate => 1,
+            AddressingMode816::Direct => 1,
+            AddressingMode816::DirectX => 1,
+            AddressingMode816::DirectY => 1,
+            AddressingMode816::DirectIndirect => 1,
+            AddressingMode816::DirectIndirectLong => 1,
+            AddressingMode816::DirectXIndirect => 1,
+            AddressingMode816::DirectIndirectY => 1,
+            AddressingMode816::DirectIndirectLongY => 1,
+            AddressingMode816::Absolute => 2,
+            AddressingMode816::AbsoluteX => 2,
+            AddressingMode816::AbsoluteY => 2,
+            AddressingMode816::AbsoluteLong => 3,
+            AddressingMode816::AbsoluteLongX => 3,
+            AddressingMode816::AbsoluteIndirect => 2,
+            AddressingMode816::AbsoluteIndirectLong => 2,
+            AddressingMode816::AbsoluteXIndirect => 2,
+            AddressingMode816::StackRelative => 1,
+            AddressingMode816::StackRelativeIndirectY => 1,
+            AddressingMode816::Relative => 1,
+            AddressingMode816::RelativeLong => 2,
+            AddressingMode816::BlockMove => 2,
+        };
+        AddressDiff(x)
+    }
+
+    pub fn is_direct_page(self) -> bool {
+        match self {
+            AddressingMode816::Direct | AddressingMode816::DirectX |
+            AddressingMode816::DirectY | AddressingMode816::DirectIndirect |
+            AddressingMode816::DirectIndirectLong |
+            AddressingMode816::DirectXIndirect |
+            AddressingMode816::DirectIndirectY |
+            AddressingMode816::DirectIndirectLongY => true,
+            _ => false,
+        }
+    }
+
+    // Whether an indexed read takes an extra cycle. As on the 6502, it does
+    // when indexing crosses a page boundary, but with 16-bit index registers
+    // it always does.
+    pub fn has_index_penalty<B: LongBus>(self, machine: &Machine816<B>,
+                                         arr: &[u8]) -> bool {
+        let registers = &machine.registers;
+        let (base, index) = match self {
+            AddressingMode816::AbsoluteX =>
+                (arr_to_u16(arr), registers.index_x),
+            AddressingMode816::AbsoluteY =>
+                (arr_to_u16(arr), registers.index_y),
+            AddressingMode816::DirectIndirectY => {
+                let start = machine.direct_address(arr[0], 0);
+                let lo = machine.memory.peek_byte(LongAddress(
+                             start.to_u16() as u32));
+                let hi = machine.memory.peek_byte(LongAddress(
+                             start.to_u16().wrapping_add(1) as u32));
+                (arr_to_u16(&[lo, hi]), registers.index_y)
+            }
+            _ => return false,
+        };
+
+        machine.wide_index()
+            || (base & 0xFF00) != (base.wrapping_add(index) & 0xFF00)
+    }
+
+    pub fn process<B: LongBus>(self, machine: &mut Machine816<B>,
+                               arr: &[u8]) -> OpInput816 {
+        let x = machine.registers.index_x;
+        let y = machine.registers.index_y;
+        let data_bank = machine.registers.data_bank;
+        let program_bank = machine.registers.program_bank;
+        let stack_pointer = machine.registers.stack_pointer;
+
+        // The direct page and stack relative locations, for the modes that
+        // have a one byte offset
+        let offset = if arr.is_empty() { 0 } else { arr[0] };
+        let direct = Location::BankZero(machine.direct_address(offset, 0));
+        let direct_x = Location::BankZero(machine.direct_address(offset, x));
+        let direct_y = Location::BankZero(machine.direct_address(offset, y));
+        let stack_relative = Location::BankZero(
+            Address(stack_pointer.wrapping_add(offset as u16)));
+        let in_data_bank = |address: u16| {
+            LongAddress::new(data_bank, Address(address))
+        };
+
+        let memory = &mut machine.memory;
+
+        let location = match self {
+            AddressingMode816::Accumulator | AddressingMode816::Implied => {
+                return OpInput816::UseImplied
+            }
+            AddressingMode816::ImmediateM | AddressingMode816::ImmediateX |
+            AddressingMode816::Immediate => {
+                let hi = if arr.len() > 1 { arr[1] } else { 0 };
+                return OpInput816::UseImmediate(arr_to_u16(&[arr[0], hi]))
+            }
+            AddressingMode816::Relative => {
+                return OpInput816::UseRelative(arr[0] as i8 as i16)
+            }
+            AddressingMode816::RelativeLong => {
+                return OpInput816::UseRelative(arr_to_u16(arr) as i16)
+            }
+            AddressingMode816::BlockMove => {
+                return OpInput816::UseBlockMove(arr[0], arr[1])
+            }
+
+            AddressingMode816::Direct => direct,
+            AddressingMode816::DirectX => direct_x,
+            AddressingMode816::DirectY => direct_y,
+            AddressingMode816::DirectIndirect => {
+                let pointer = read_pointer(memory, direct);
+                Location::Long(in_data_bank(pointer))
+            }
+            AddressingMode816::DirectIndirectLong => {
+                Location::Long(read_long_pointer(memory, direct))
+            }
+            AddressingMode816::DirectXIndirect => {
+                let pointer = read_pointer(memory, direct_x);
+                Location::Long(in_data_bank(pointer))
+            }
+            AddressingMode816::DirectIndirectY => {
+                let pointer = read_pointer(memory, direct);
+                Location::Long(in_data_bank(pointer) + AddressDiff(y as i32))
+            }
+            AddressingMode816::DirectIndirectLongY => {
+                let pointer = read_long_pointer(memory, direct);
+                Location::Long(pointer + AddressDiff(y as i32))
+            }
+
+            AddressingMode816::Absolute => {
+                Location::Long(in_data_bank(arr_to_u16(arr)))
+            }
+            AddressingMode816::AbsoluteX => {
+                Location::Long(in_data_bank(arr_to_u16(arr))
+                               + AddressDiff(x as i32))
+            }
+            AddressingMode816::AbsoluteY => {
+                Location::Long(in_data_bank(arr_to_u16(arr))
+                               + AddressDiff(y as i32))
+            }
+            AddressingMode816::AbsoluteLong => {
+                Location::Long(arr_to_long(arr))
+            }
+            AddressingMode816::AbsoluteLongX => {
+                Location::Long(arr_to_long(arr) + AddressDiff(x as i32))
+            }
+            AddressingMode816::AbsoluteIndirect => {
+                let start = Location::BankZero(Address(arr_to_u16(arr)));
+                let pointer = read_pointer(memory, start);
+                Location::Long(LongAddress::new(program_bank,
+                                                Address(pointer)))
+            }
+            AddressingMode816::AbsoluteIndirectLong => {
+                let start = Location::BankZero(Address(arr_to_u16(arr)));
+                Location::Long(read_long_pointer(memory, start))
+            }
+            AddressingMode816::AbsoluteXIndirect => {
+                // The pointer wraps around within the program bank
+                let start = arr_to_u16(arr).wrapping_add(x);
+                let lo = memory.get_byte(LongAddress::new(
+                             program_bank, Address(start)));
+                let hi = memory.get_byte(LongAddress::new(
+                             program_bank, Address(start.wrapping_add(1))));
+                Location::Long(LongAddress::new(program_bank,
+                                                Address(arr_to_u16(&[lo, hi]))))
+            }
+
+            AddressingMode816::StackRelative => stack_relative,
+            AddressingMode816::StackRelativeIndirectY => {
+                let pointer = read_pointer(memory, stack_relative);
+                Location::Long(in_data_bank(pointer) + AddressDiff(y as i32))
+            }
+        };
+
+        OpInput816::UseAddress(location)
+    }
+}
+
+pub type DecodedInstr816 = (Instruction816, OpInput816);
+
+// Every opcode is defined on the 65C816. As in `instruction::OPCODES`, the
+// cycle counts are the base ones; the machine adds cycles for 16-bit
+// operands, a direct page register that isn't page aligned, page crossings
+// and taken branches.
+pub static OPCODES_816: [(Instruction816, AddressingMode816, u8); 256] = [
+/*0x00*/ (Instruction816::BRK, AddressingMode816::Immediate, 7),
+/*0x01*/ (Instruction816::ORA, AddressingMode816::DirectXIndirect, 6),
+/*0x02*/ (Instruction816::COP, AddressingMode816::Immediate, 7),
+/*0x03*/ (Instruction816::ORA, AddressingMode816::StackRelative, 4),
+/*0x04*/ (Instruction816::TSB, AddressingMode816::Direct, 5),
+/*0x05*/ (Instruction816::ORA, AddressingMode816::Direct, 3),
+/*0x06*/ (Instruction816::ASL, AddressingMode816::Direct, 5),
+/*0x07*/ (Instruction816::ORA, AddressingMode816::DirectIndirectLong, 6),
+/*0x08*/ (Instruction816::PHP, AddressingMode816::Implied, 3),
+/*0x09*/ (Instruction816::ORA, AddressingMode816::ImmediateM, 2),
+/*0x0A*/ (Instruction816::ASL, AddressingMode816::Accumulator, 2),
+/*0x0B*/ (Instruction816::PHD, AddressingMode816::Implied, 4),
+/*0x0C*/ (Instruction816::TSB, AddressingMode816::Absolute, 6),
+/*0x0D*/ (Instruction816::ORA, AddressingMode816::Absolute, 4),
+/*0x0E*/ (Instruction816::ASL, AddressingMode816::Absolute, 6),
+/*0x0F*/ (Instruction816::ORA, AddressingMode816::AbsoluteLong, 5),
+/*0x10*/ (Instruction816::BPL, AddressingMode816::Relative, 2),
+/*0x11*/ (Instruction816::ORA, AddressingMode816::DirectIndirectY, 5),
+/*0x12*/ (Instruction816::ORA, AddressingMode816::DirectIndirect, 5),
+/*0x13*/ (Instruction816::ORA, AddressingMode816::StackRelativeIndirectY, 7),
+/*0x14*/ (Instruction816::TRB, AddressingMode816::Direct, 5),
+/*0x15*/ (Instruction816::ORA, AddressingMode816::DirectX, 4),
+/*0x16*/ (Instruction816::ASL, AddressingMode816::DirectX, 6),
+/*0x17*/ (Instruction816::ORA, AddressingMode816::DirectIndirectLongY, 6),
+/*0x18*/ (Instruction816::CLC, AddressingMode816::Implied, 2),
+/*0x19*/ (Instruction816::ORA, AddressingMode816::AbsoluteY, 4),
+/*0x1A*/ (Instruction816::INC, AddressingMode816::Accumulator, 2),
+/*0x1B*/ (Instruction816::TCS, AddressingMode816::Implied, 2),
+/*0x1C*/ (Instruction816::TRB, AddressingMode816::Absolute, 6),
+/*0x1D*/ (Instruction816::ORA, AddressingMode816::AbsoluteX, 4),
+/*0x1E*/ (Instruction816::ASL, AddressingMode816::AbsoluteX, 7),
+/*0x1F*/ (Instruction816::ORA, AddressingMode816::AbsoluteLongX, 5),
+/*0x20*/ (Instruction816::JSR, AddressingMode816::Absolute, 6),
+/*0x21*/ (Instruction816::AND, AddressingMode816::DirectXIndirect, 6),
+/*0x22*/ (Instruction816::JSL, AddressingMode816::AbsoluteLong, 8),
+/*0x23*/ (Instruction816::AND, AddressingMode816::StackRelative, 4),
+/*0x24*/ (Instruction816::BIT, AddressingMode816::Direct, 3),
+/*0x25*/ (Instruction816::AND, AddressingMode816::Direct, 3),
+/*0x26*/ (Instruction816::ROL, AddressingMode816::Direct, 5),
+/*0x27*/ (Instruction816::AND, AddressingMode816::DirectIndirectLong, 6),
+/*0x28*/ (Instruction816::PLP, AddressingMode816::Implied, 4),
+/*0x29*/ (Instruction816::AND, AddressingMode816::ImmediateM, 2),
+/*0x2A*/ (Instruction816::ROL, AddressingMode816::Accumulator, 2),
+/*0x2B*/ (Instruction816::PLD, AddressingMode816::Implied, 5),
+/*0x2C*/ (Instruction816::BIT, AddressingMode816::Absolute, 4),
+/*0x2D*/ (Instruction816::AND, AddressingMode816::Absolute, 4),
+/*0x2E*/ (Instruction816::ROL, AddressingMode816::Absolute, 6),
+/*0x2F*/ (Instruction816::AND, AddressingMode816::AbsoluteLong, 5),
+/*0x30*/ (Instruction816::BMI, AddressingMode816::Relative, 2),
+/*0x31*/ (Instruction816::AND, AddressingMode816::DirectIndirectY, 5),
+/*0x32*/ (Instruction816::AND, AddressingMode816::DirectIndirect, 5),
+/*0x33*/ (Instruction816::AND, AddressingMode816::StackRelativeIndirectY, 7),
+/*0x34*/ (Instruction816::BIT, AddressingMode816::DirectX, 4),
+/*0x35*/ (Instruction816::AND, AddressingMode816::DirectX, 4),
+/*0x36*/ (Instruction816::ROL, AddressingMode816::DirectX, 6),
+/*0x37*/ (Instruction816::AND, AddressingMode816::DirectIndirectLongY, 6),
+/*0x38*/ (Instruction816::SEC, AddressingMode816::Implied, 2),
+/*0x39*/ (Instruction816::AND, AddressingMode816::AbsoluteY, 4),
+/*0x3A*/ (Instruction816::DEC, AddressingMode816::Accumulator, 2),
+/*0x3B*/ (Instruction816::TSC, AddressingMode816::Implied, 2),
+/*0x3C*/ (Instruction816::BIT, AddressingMode816::AbsoluteX, 4),
+/*0x3D*/ (Instruction816::AND, AddressingMode816::AbsoluteX, 4),
+/*0x3E*/ (Instruction816::ROL, AddressingMode816::AbsoluteX, 7),
+/*0x3F*/ (Instruction816::AND, AddressingMode816::AbsoluteLongX, 5),
+/*0x40*/ (Instruction816::RTI, AddressingMode816::Implied, 6),
+/*0x41*/ (Instruction816::EOR, AddressingMode816::DirectXIndirect, 6),
+/*0x42*/ (Instruction816::WDM, AddressingMode816::Immediate, 2),
+/*0x43*/ (Instruction816::EOR, AddressingMode816::StackRelative, 4),
+/*0x44*/ (Instruction816::MVP, AddressingMode816::BlockMove, 7),
+/*0x45*/ (Instruction816::EOR, AddressingMode816::Direct, 3),
+/*0x46*/ (Instruction816::LSR, AddressingMode816::Direct, 5),
+/*0x47*/ (Instruction816::EOR, AddressingMode816::DirectIndirectLong, 6),
+/*0x48*/ (Instruction816::PHA, AddressingMode816::Implied, 3),
+/*0x49*/ (Instruction816::EOR, AddressingMode816::ImmediateM, 2),
+/*0x4A*/ (Instruction816::LSR, AddressingMode816::Accumulator, 2),
+/*0x4B*/ (Instruction816::PHK, AddressingMode816::Implied, 3),
+/*0x4C*/ (Instruction816::JMP, AddressingMode816::Absolute, 3),
+/*0x4D*/ (Instruction816::EOR, AddressingMode816::Absolute, 4),
+/*0x4E*/ (Instruction816::LSR, AddressingMode816::Absolute, 6),
+/*0x4F*/ (Instruction816::EOR, AddressingMode816::AbsoluteLong, 5),
+/*0x50*/ (Instruction816::BVC, AddressingMode816::Relative, 2),
+/*0x51*/ (Instruction816::EOR, AddressingMode816::DirectIndirectY, 5),
+/*0x52*/ (Instruction816::EOR, AddressingMode816::DirectIndirect, 5),
+/*0x53*/ (Instruction816::EOR, AddressingMode816::StackRelativeIndirectY, 7),
+/*0x54*/ (Instruction816::MVN, AddressingMode816::BlockMove, 7),
+/*0x55*/ (Instruction816::EOR, AddressingMode816::DirectX, 4),
+/*0x56*/ (Instruction816::LSR, AddressingMode816::DirectX, 6),
+/*0x57*/ (Instruction816::EOR, AddressingMode816::DirectIndirectLongY, 6),
+/*0x58*/ (Instruction816::CLI, AddressingMode816::Implied, 2),
+/*0x59*/ (Instruction816::EOR, AddressingMode816::AbsoluteY, 4),
+/*0x5A*/ (Instruction816::PHY, AddressingMode816::Implied, 3),
+/*0x5B*/ (Instruction816::TCD, AddressingMode816::Implied, 2),
+/*0x5C*/ (Instruction816::JML, AddressingMode816::AbsoluteLong, 4),
+/*0x5D*/ (Instruction816::EOR, AddressingMode816::AbsoluteX, 4),
+/*0x5E*/ (Instruction816::LSR, AddressingMode816::AbsoluteX, 7),
+/*0x5F*/ (Instruction816::EOR, AddressingMode816::AbsoluteLongX, 5),
+/*0x60*/ (Instruction816::RTS, AddressingMode816::Implied, 6),
+/*0x61*/ (Instruction816::ADC, AddressingMode816::DirectXIndirect, 6),
+/*0x62*/ (Instruction816::PER, AddressingMode816::RelativeLong, 6),
+/*0x63*/ (Instruction816::ADC, AddressingMode816::StackRelative, 4),
+/*0x64*/ (Instruction816::STZ, AddressingMode816::Direct, 3),
+/*0x65*/ (Instruction816::ADC, AddressingMode816::Direct, 3),
+/*0x66*/ (Instruction816::ROR, AddressingMode816::Direct, 5),
+/*0x67*/ (Instruction816::ADC, AddressingMode816::DirectIndirectLong, 6),
+/*0x68*/ (Instruction816::PLA, AddressingMode816::Implied, 4),
+/*0x69*/ (Instruction816::ADC, AddressingMode816::ImmediateM, 2),
+/*0x6A*/ (Instruction816::ROR, AddressingMode816::Accumulator, 2),
+/*0x6B*/ (Instruction816::RTL, AddressingMode816::Implied, 6),
+/*0x6C*/ (Instruction816::JMP, AddressingMode816::AbsoluteIndirect, 5),
+/*0x6D*/ (Instruction816::ADC, AddressingMode816::Absolute, 4),
+/*0x6E*/ (Instruction816::ROR, AddressingMode816::Absolute, 6),
+/*0x6F*/ (Instruction816::ADC, AddressingMode816::AbsoluteLong, 5),
+/*0x70*/ (Instruction816::BVS, AddressingMode816::Relative, 2),
+/*0x71*/ (Instruction816::ADC, AddressingMode816::DirectIndirectY, 5),
+/*0x72*/ (Instruction816::ADC, AddressingMode816::DirectIndirect, 5),
+/*0x73*/ (Instruction816::ADC, AddressingMode816::StackRelativeIndirectY, 7),
+/*0x74*/ (Instruction816::STZ, AddressingMode816::DirectX, 4),
+/*0x75*/ (Instruction816::ADC, AddressingMode816::DirectX, 4),
+/*0x76*/ (Instruction816::ROR, AddressingMode816::DirectX, 6),
+/*0x77*/ (Instruction816::ADC, AddressingMode816::DirectIndirectLongY, 6),
+/*0x78*/ (Instruction816::SEI, AddressingMode816::Implied, 2),
+/*0x79*/ (Instruction816::ADC, AddressingMode816::AbsoluteY, 4),
+/*0x7A*/ (Instruction816::PLY, AddressingMode816::Implied, 4),
+/*0x7B*/ (Instruction816::TDC, AddressingMode816::Implied, 2),
+/*0x7C*/ (Instruction816::JMP, AddressingMode816::AbsoluteXIndirect, 6),
+/*0x7D*/ (Instruction816::ADC, AddressingMode816::AbsoluteX, 4),
+/*0x7E*/ (Instruction816::ROR, AddressingMode816::AbsoluteX, 7),
+/*0x7F*/ (Instruction816::ADC, AddressingMode816::AbsoluteLongX, 5),
+/*0x80*/ (Instruction816::BRA, AddressingMode816::Relative, 3),
+/*0x81*/ (Instruction816::STA, AddressingMode816::DirectXIndirect, 6),
+/*0x82*/ (Instruction816::BRL, AddressingMode816::RelativeLong, 4),
+/*0x83*/ (Instruction816::STA, AddressingMode816::StackRelative, 4),
+/*0x84*/ (Instruction816::STY, AddressingMode816::Direct, 3),
+/*0x85*/ (Instruction816::STA, AddressingMode816::Direct, 3),
+/*0x86*/ (Instruction816::STX, AddressingMode816::Direct, 3),
+/*0x87*/ (Instruction816::STA, AddressingMode816::DirectIndirectLong, 6),
+/*0x88*/ (Instruction816::DEY, AddressingMode816::Implied, 2),
+/*0x89*/ (Instruction816::BIT, AddressingMode816::ImmediateM, 2),
+/*0x8A*/ (Instruction816::TXA, AddressingMode816::Implied, 2),
+/*0x8B*/ (Instruction816::PHB, AddressingMode816::Implied, 3),
+/*0x8C*/ (Instruction816::STY, AddressingMode816::Absolute, 4),
+/*0x8D*/ (Instruction816::STA, AddressingMode816::Absolute, 4),
+/*0x8E*/ (Instruction816::STX, AddressingMode816::Absolute, 4),
+/*0x8F*/ (Instruction816::STA, AddressingMode816::AbsoluteLong, 5),
+/*0x90*/ (Instruction816::BCC, AddressingMode816::Relative, 2),
+/*0x91*/ (Instruction816::STA, AddressingMode816::DirectIndirectY, 6),
+/*0x92*/ (Instruction816::STA, AddressingMode816::DirectIndirect, 5),
+/*0x93*/ (Instruction816::STA, AddressingMode816::StackRelativeIndirectY, 7),
+/*0x94*/ (Instruction816::STY, AddressingMode816::DirectX, 4),
+/*0x95*/ (Instruction816::STA, AddressingMode816::DirectX, 4),
+/*0x96*/ (Instruction816::STX, AddressingMode816::DirectY, 4),
+/*0x97*/ (Instruction816::STA, AddressingMode816::DirectIndirectLongY, 6),
+/*0x98*/ (Instruction816::TYA, AddressingMode816::Implied, 2),
+/*0x99*/ (Instruction816::STA, AddressingMode816::AbsoluteY, 5),
+/*0x9A*/ (Instruction816::TXS, AddressingMode816::Implied, 2),
+/*0x9B*/ (Instruction816::TXY, AddressingMode816::Implied, 2),
+/*0x9C*/ (Instruction816::STZ, AddressingMode816::Absolute, 4),
+/*0x9D*/ (Instruction816::STA, AddressingMode816::AbsoluteX, 5),
+/*0x9E*/ (Instruction816::STZ, AddressingMode816::AbsoluteX, 5),
+/*0x9F*/ (Instruction816::STA, AddressingMode816::AbsoluteLongX, 5),
+/*0xA0*/ (Instruction816::LDY, AddressingMode816::ImmediateX, 2),
+/*0xA1*/ (Instruction816::LDA, AddressingMode816::DirectXIndirect, 6),
+/*0xA2*/ (Instruction816::LDX, AddressingMode816::ImmediateX, 2),
+/*0xA3*/ (Instruction816::LDA, AddressingMode816::StackRelative, 4),
+/*0xA4*/ (Instruction816::LDY, AddressingMode816::Direct, 3),
+/*0xA5*/ (Instruction816::LDA, AddressingMode816::Direct, 3),
+/*0xA6*/ (Instruction816::LDX, AddressingMode816::Direct, 3),
+/*0xA7*/ (Instruction816::LDA, AddressingMode816::DirectIndirectLong, 6),
+/*0xA8*/ (Instruction816::TAY, AddressingMode816::Implied, 2),
+/*0xA9*/ (Instruction816::LDA, AddressingMode816::ImmediateM, 2),
+/*0xAA*/ (Instruction816::TAX, AddressingMode816::Implied, 2),
+/*0xAB*/ (Instruction816::PLB, AddressingMode816::Implied, 4),
+/*0xAC*/ (Instruction816::LDY, AddressingMode816::Absolute, 4),
+/*0xAD*/ (Instruction816::LDA, AddressingMode816::Absolute, 4),
+/*0xAE*/ (Instruction816::LDX, AddressingMode816::Absolute, 4),
+/*0xAF*/ (Instruction816::LDA, AddressingMode816::AbsoluteLong, 5),
+/*0xB0*/ (Instruction816::BCS, AddressingMode816::Relative, 2),
+/*0xB1*/ (Instruction816::LDA, AddressingMode816::DirectIndirectY, 5),
+/*0xB2*/ (Instruction816::LDA, AddressingMode816::DirectIndirect, 5),
+/*0xB3*/ (Instruction816::LDA, AddressingMode816::StackRelativeIndirectY, 7),
+/*0xB4*/ (Instruction816::LDY, AddressingMode816::DirectX, 4),
+/*0xB5*/ (Instruction816::LDA, AddressingMode816::DirectX, 4),
+/*0xB6*/ (Instruction816::LDX, AddressingMode816::DirectY, 4),
+/*0xB7*/ (Instruction816::LDA, AddressingMode816::DirectIndirectLongY, 6),
+/*0xB8*/ (Instruction816::CLV, AddressingMode816::Implied, 2),
+/*0xB9*/ (Instruction816::LDA, AddressingMode816::AbsoluteY, 4),
+/*0xBA*/ (Instruction816::TSX, AddressingMode816::Implied, 2),
+/*0xBB*/ (Instruction816::TYX, AddressingMode816::Implied, 2),
+/*0xBC*/ (Instruction816::LDY, AddressingMode816::AbsoluteX, 4),
+/*0xBD*/ (Instruction816::LDA, AddressingMode816::AbsoluteX, 4),
+/*0xBE*/ (Instruction816::LDX, AddressingMode816::AbsoluteY, 4),
+/*0xBF*/ (Instruction816::LDA, AddressingMode816::AbsoluteLongX, 5),
+/*0xC0*/ (Instruction816::CPY, AddressingMode816::ImmediateX, 2),
+/*0xC1*/ (Instruction816::CMP, AddressingMode816::DirectXIndirect, 6),
+/*0xC2*/ (Instruction816::REP, AddressingMode816::Immediate, 3),
+/*0xC3*/ (Instruction816::CMP, AddressingMode816::StackRelative, 4),
+/*0xC4*/ (Instruction816::CPY, AddressingMode816::Direct, 3),
+/*0xC5*/ (Instruction816::CMP, AddressingMode816::Direct, 3),
+/*0xC6*/ (Instruction816::DEC, AddressingMode816::Direct, 5),
+/*0xC7*/ (Instruction816::CMP, AddressingMode816::DirectIndirectLong, 6),
+/*0xC8*/ (Instruction816::INY, AddressingMode816::Implied, 2),
+/*0xC9*/ (Instruction816::CMP, AddressingMode816::ImmediateM, 2),
+/*0xCA*/ (Instruction816::DEX, AddressingMode816::Implied, 2),
+/*0xCB*/ (Instruction816::WAI, AddressingMode816::Implied, 3),
+/*0xCC*/ (Instruction816::CPY, AddressingMode816::Absolute, 4),
+/*0xCD*/ (Instruction816::CMP, AddressingMode816::Absolute, 4),
+/*0xCE*/ (Instruction816::DEC, AddressingMode816::Absolute, 6),
+/*0xCF*/ (Instruction816::CMP, AddressingMode816::AbsoluteLong, 5),
+/*0xD0*/ (Instruction816::BNE, AddressingMode816::Relative, 2),
+/*0xD1*/ (Instruction816::CMP, AddressingMode816::DirectIndirectY, 5),
+/*0xD2*/ (Instruction816::CMP, AddressingMode816::DirectIndirect, 5),
+/*0xD3*/ (Instruction816::CMP, AddressingMode816::StackRelativeIndirectY, 7),
+/*0xD4*/ (Instruction816::PEI, AddressingMode816::DirectIndirect, 6),
+/*0xD5*/ (Instruction816::CMP, AddressingMode816::DirectX, 4),
+/*0xD6*/ (Instruction816::DEC, AddressingMode816::DirectX, 6),
+/*0xD7*/ (Instruction816::CMP, AddressingMode816::DirectIndirectLongY, 6),
+/*0xD8*/ (Instruction816::CLD, AddressingMode816::Implied, 2),
+/*0xD9*/ (Instruction816::CMP, AddressingMode816::AbsoluteY, 4),
+/*0xDA*/ (Instruction816::PHX, AddressingMode816::Implied, 3),
+/*0xDB*/ (Instruction816::STP, AddressingMode816::Implied, 3),
+/*0xDC*/ (Instruction816::JML, AddressingMode816::AbsoluteIndirectLong, 6),
+/*0xDD*/ (Instruction816::CMP, AddressingMode816::AbsoluteX, 4),
+/*0xDE*/ (Instruction816::DEC, AddressingMode816::AbsoluteX, 7),
+/*0xDF*/ (Instruction816::CMP, AddressingMode816::AbsoluteLongX, 5),
+/*0xE0*/ (Instruction816::CPX, AddressingMode816::ImmediateX, 2),
+/*0xE1*/ (Instruction816::SBC, AddressingMode816::DirectXIndirect, 6),
+/*0xE2*/ (Instruction816::SEP, AddressingMode816::Immediate, 3),
+/*0xE3*/ (Instruction816::SBC, AddressingMode816::StackRelative, 4),
+/*0xE4*/ (Instruction816::CPX, AddressingMode816::Direct, 3),
+/*0xE5*/ (Instruction816::SBC, AddressingMode816::Direct, 3),
+/*0xE6*/ (Instruction816::INC, AddressingMode816::Direct, 5),
+/*0xE7*/ (Instruction816::SBC, AddressingMode816::DirectIndirectLong, 6),
+/*0xE8*/ (Instruction816::INX, AddressingMode816::Implied, 2),
+/*0xE9*/ (Instruction816::SBC, AddressingMode816::ImmediateM, 2),
+/*0xEA*/ (Instruction816::NOP, AddressingMode816::Implied, 2),
+/*0xEB*/ (Instruction816::XBA, AddressingMode816::Implied, 3),
+/*0xEC*/ (Instruction816::CPX, AddressingMode816::Absolute, 4),
+/*0xED*/ (Instruction816::SBC, AddressingMode816::Absolute, 4),
+/*0xEE*/ (Instruction816::INC, AddressingMode816::Absolute, 6),
+/*0xEF*/ (Instruction816::SBC, AddressingMode816::AbsoluteLong, 5),
+/*0xF0*/ (Instruction816::BEQ, AddressingMode816::Relative, 2),
+/*0xF1*/ (Instruction816::SBC, AddressingMode816::DirectIndirectY, 5),
+/*0xF2*/ (Instruction816::SBC, AddressingMode816::DirectIndirect, 5),
+/*0xF3*/ (Instruction816::SBC, AddressingMode816::StackRelativeIndirectY, 7),
+/*0xF4*/ (Instruction816::PEA, AddressingMode816::Absolute, 5),
+/*0xF5*/ (Instruction816::SBC, AddressingMode816::DirectX, 4),
+/*0xF6*/ (Instruction816::INC, AddressingMode816::DirectX, 6),
+/*0xF7*/ (Instruction816::SBC, AddressingMode816::DirectIndirectLongY, 6),
+/*0xF8*/ (Instruction816::SED, AddressingMode816::Implied, 2),
+/*0xF9*/ (Instruction816::SBC, AddressingMode816::AbsoluteY, 4),
+/*0xFA*/ (Instruction816::PLX, AddressingMode816::Implied, 4),
+/*0xFB*/ (Instruction816::XCE, AddressingMode816::Implied, 2),
+/*0xFC*/ (Instruction816::JSR, AddressingMode816::AbsoluteXIndirect, 8),
+/*0xFD*/ (Instruction816::SBC, AddressingMode816::AbsoluteX, 4),
+/*0xFE*/ (Instruction816::INC, AddressingMode816::AbsoluteX, 7),
+/*0xFF*/ (Instruction816::SBC, AddressingMode816::AbsoluteLongX, 5),
+];
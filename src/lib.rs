@@ -37,8 +37,20 @@ extern crate log;
 extern crate rustc_bitflags;
 
 pub mod address;
+pub mod assembler;
+pub mod bus;
+pub mod code_cache;
+pub mod cycle_machine;
+pub mod device;
+pub mod disassembler;
+pub mod history;
 pub mod instruction;
+pub mod instruction816;
 pub mod machine;
+pub mod machine816;
 pub mod memory;
+pub mod monitor;
 pub mod range_incl;
 pub mod registers;
+pub mod save_state;
+pub mod trace;
@@ -28,57 +28,394 @@
 use std;
 
 use address::{Address, AddressDiff};
-use instruction;
-use instruction::{DecodedInstr, Instruction, OpInput};
-use memory::Memory;
+use bus::Bus;
+use history::{Delta, History};
+use instruction::{DecodedInstr, Instruction, OpInput, PredecodedInstr,
+                  Variant};
+use memory::{Memory, IRQ_INTERRUPT_VECTOR_LO, IRQ_INTERRUPT_VECTOR_HI,
+             NMI_INTERRUPT_VECTOR_LO, NMI_INTERRUPT_VECTOR_HI,
+             RESET_VECTOR_LO, RESET_VECTOR_HI};
 use range_incl::range_incl;
 use registers::{ Registers, StackPointer, Status, StatusArgs };
 use registers::{ PS_NEGATIVE, PS_DECIMAL_MODE, PS_OVERFLOW, PS_ZERO, PS_CARRY,
-                 PS_DISABLE_INTERRUPTS };
+                 PS_DISABLE_INTERRUPTS, PS_BRK, PS_UNUSED };
+use trace;
 
-#[derive(Copy)]
-pub struct Machine {
+// ANE and LXA OR the accumulator with a constant that depends on the chip.
+// $EE is the most common value.
+const UNSTABLE_CONSTANT: u8 = 0xEE;
+
+// Why `step` or `run` stopped
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // The opcode at this address isn't one the variant has (or, in strict
+    // mode, is an undocumented one). Nothing was run, so the PC is still
+    // there.
+    InvalidOpcode(Address, u8),
+
+    // A BRK at this address, with `stop_on_brk` set. It hasn't been run.
+    Brk(Address),
+
+    // `run` reached a breakpoint. The instruction there hasn't been run.
+    Breakpoint(Address),
+
+    // A cycle or instruction limit ran out
+    BudgetExhausted,
+
+    // The condition given to `run_until` became true
+    ConditionMet,
+
+    // A JAM at this address has locked up the processor
+    Jam(Address),
+
+    // The 65C02 executed STP, and is stopped until the next reset
+    Stp,
+
+    // `run_backward` undid every step in the history
+    HistoryExhausted,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StopReason::InvalidOpcode(address, opcode) =>
+                write!(f, "invalid opcode ${:02X} at ${:04X}", opcode,
+                       address.to_u16()),
+            StopReason::Brk(address) =>
+                write!(f, "BRK at ${:04X}", address.to_u16()),
+            StopReason::Breakpoint(address) =>
+                write!(f, "breakpoint at ${:04X}", address.to_u16()),
+            StopReason::BudgetExhausted => write!(f, "budget exhausted"),
+            StopReason::ConditionMet => write!(f, "condition met"),
+            StopReason::Jam(address) =>
+                write!(f, "JAM at ${:04X}", address.to_u16()),
+            StopReason::Stp => write!(f, "stopped by STP"),
+            StopReason::HistoryExhausted => write!(f, "history exhausted"),
+        }
+    }
+}
+
+// How far one of the bounded `run_*` methods got, and why it stopped
+#[derive(Copy, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    pub instructions: u64,
+    pub cycles:       u64,
+    pub reason:       StopReason,
+}
+
+// `memory` can be anything implementing `Bus`; by default it's 64 KiB of
+// plain RAM.
+pub struct Machine<B = Memory> {
     pub registers: Registers,
-    pub memory:    Memory
+    pub memory:    B,
+
+    // Number of clock cycles executed so far
+    pub cycles:    u64,
+
+    // Interrupt inputs. IRQ is level-triggered, so it stays asserted until
+    // whoever asserted it deasserts it. NMI is edge-triggered, so a triggered
+    // NMI stays pending until it's been serviced.
+    pub irq_line:    bool,
+    pub nmi_pending: bool,
+
+    // In strict mode the undocumented opcodes are treated as invalid
+    pub strict:      bool,
+
+    pub variant:     Variant,
+
+    // Set by the 65C02's WAI until an interrupt comes along, and by its STP
+    // until the next reset
+    pub waiting:     bool,
+    pub stopped:     bool,
+
+    // Whether `step` stops at a BRK instead of running it, for programs that
+    // use BRK to say they've finished
+    pub stop_on_brk: bool,
+
+    // Recent steps, if they're being recorded so they can be undone
+    history:         Option<History>,
+
+    // Given a line of trace before each instruction runs
+    tracer:          Option<Box<FnMut(&str)>>
 }
 
 impl Machine {
     pub fn new() -> Machine {
-    	Machine{
-    	    registers: Registers::new(),
-    	    memory:    Memory::new()
-    	}
+        Machine::with_bus(Memory::new())
+    }
+}
+
+impl<B: Bus> Machine<B> {
+    // Creates an NMOS 6502 machine and puts it through the reset sequence, so
+    // the bus should already hold the reset vector.
+    pub fn with_bus(bus: B) -> Machine<B> {
+        Machine::with_variant(bus, Variant::Nmos)
     }
 
+    pub fn with_variant(bus: B, variant: Variant) -> Machine<B> {
+    	let mut machine = Machine{
+    	    registers:   Registers::new(),
+    	    memory:      bus,
+    	    cycles:      0,
+    	    irq_line:    false,
+    	    nmi_pending: false,
+    	    strict:      false,
+    	    variant:     variant,
+    	    waiting:     false,
+    	    stopped:     false,
+    	    stop_on_brk: false,
+    	    history:     None,
+    	    tracer:      None
+    	};
+    	machine.reset();
+    	machine
+    }
+
+    // The reset sequence. The CPU goes through the motions of an interrupt,
+    // except that the stack writes are turned into reads, and then jumps
+    // through the reset vector. Whatever is on the bus is left alone.
     pub fn reset(&mut self) {
-    	*self = Machine::new();
+        for _ in 0..3 {
+            self.registers.stack_pointer.decrement();
+        }
+        self.registers.status.or(PS_DISABLE_INTERRUPTS);
+        if self.variant == Variant::Cmos {
+            self.registers.status.remove(PS_DECIMAL_MODE);
+        }
+        self.nmi_pending = false;
+        self.waiting = false;
+        self.stopped = false;
+
+        let addr = self.read_vector(RESET_VECTOR_LO, RESET_VECTOR_HI);
+        self.jump(addr);
+        self.cycles += 7;
     }
 
-    pub fn fetch_next_and_decode(&mut self) -> Option<DecodedInstr> {
-        let x: u8 = self.memory.get_byte(self.registers.program_counter);
+    // Starts recording what each step changes, so that the last `capacity`
+    // steps can be undone with `step_back`, `run_backward` and `rewind_to`.
+    // Anything already recorded is thrown away. Memory is put back as it
+    // was, but devices aren't rewound: they just see the old values written
+    // back.
+    pub fn record_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn stop_recording_history(&mut self) {
+        self.history = None;
+    }
 
-        match instruction::OPCODES[x as usize] {
-            Some((instr, am)) => {
-                let extra_bytes = am.extra_bytes();
-                let num_bytes = AddressDiff(1) + extra_bytes;
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
 
-                let data_start = self.registers.program_counter
-                               + AddressDiff(1);
+    // Undoes the most recent step. Returns false if there's no history left.
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.as_mut().and_then(|h| h.pop()) {
+            Some(delta) => delta,
+            None => return false,
+        };
 
-                let slice = self.memory.get_slice(data_start, extra_bytes);
-                let am_out = am.process(self, slice);
+        for &(address, value) in delta.writes.iter().rev() {
+            self.memory.set_byte(address, value);
+        }
+        self.registers = delta.registers;
+        self.cycles = delta.cycles;
+        self.nmi_pending = delta.nmi_pending;
+        self.waiting = delta.waiting;
+        self.stopped = delta.stopped;
+        true
+    }
 
-                // Increment program counter
-                self.registers.program_counter =
-                    self.registers.program_counter + num_bytes;
+    // Steps backwards until the PC is at one of `breakpoints`, or the
+    // history runs out. Like `run_to_breakpoint`, it always takes at least
+    // one step, so that running backwards again from a breakpoint doesn't
+    // stop straight away.
+    pub fn run_backward(&mut self, breakpoints: &[Address]) -> StopReason {
+        loop {
+            if !self.step_back() {
+                return StopReason::HistoryExhausted;
+            }
+            let pc = self.registers.program_counter;
+            if !self.waiting && breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+        }
+    }
+
+    // Steps backwards to the point where `step` steps had been taken since
+    // recording started. Returns false, without changing anything, if that
+    // isn't in the history.
+    pub fn rewind_to(&mut self, step: u64) -> bool {
+        match self.history {
+            Some(ref history) if step >= history.earliest_step()
+                                 && step <= history.steps() => {}
+            _ => return false,
+        }
 
-                Some((instr, am_out))
+        while self.history.as_ref().map_or(false, |h| h.steps() > step) {
+            self.step_back();
+        }
+        true
+    }
+
+    // Calls `tracer` before each instruction with a line describing it and
+    // the registers, in the nestest log layout (see `trace::format_line`)
+    pub fn set_tracer<F>(&mut self, tracer: F)
+        where F: FnMut(&str) + 'static
+    {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn assert_irq(&mut self) {
+        self.irq_line = true;
+    }
+
+    pub fn deassert_irq(&mut self) {
+        self.irq_line = false;
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Checks the interrupt inputs between instructions, and starts the
+    // interrupt sequence if one needs servicing. NMI takes priority, and
+    // can't be masked. Returns whether an interrupt was taken.
+    pub fn service_interrupts(&mut self) -> bool {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(NMI_INTERRUPT_VECTOR_LO, NMI_INTERRUPT_VECTOR_HI);
+            true
+        } else if (self.irq_line || self.memory.irq_asserted())
+               && !self.registers.status.contains(PS_DISABLE_INTERRUPTS) {
+            self.interrupt(IRQ_INTERRUPT_VECTOR_LO, IRQ_INTERRUPT_VECTOR_HI);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Hardware interrupts push the address of the instruction that would
+    // have run next, and a copy of the status with B clear.
+    fn interrupt(&mut self, vector_lo: Address, vector_hi: Address) {
+        let return_addr = self.registers.program_counter;
+        self.push_address_on_stack(return_addr);
+
+        let status = (self.registers.status - PS_BRK) | PS_UNUSED;
+        self.push_on_stack(status.bits());
+        self.registers.status.or(PS_DISABLE_INTERRUPTS);
+        self.clear_decimal_on_interrupt();
+
+        let addr = self.read_vector(vector_lo, vector_hi);
+        self.jump(addr);
+        self.cycles += 7;
+    }
+
+    // The 65C02 clears the decimal flag when it takes an interrupt, so that
+    // handlers don't have to.
+    fn clear_decimal_on_interrupt(&mut self) {
+        if self.variant == Variant::Cmos {
+            self.registers.status.remove(PS_DECIMAL_MODE);
+        }
+    }
+
+    fn read_vector(&mut self, vector_lo: Address, vector_hi: Address)
+        -> Address
+    {
+        let lo = self.memory.get_byte(vector_lo) as u16;
+        let hi = self.memory.get_byte(vector_hi) as u16;
+        Address((hi << 8) | lo)
+    }
+
+    pub fn fetch_next_and_decode(&mut self) -> Option<DecodedInstr> {
+        let pc = self.registers.program_counter;
+        let predecoded = match self.predecode(pc) {
+            Some(predecoded) => predecoded,
+            None => return None,
+        };
+
+        if self.strict && predecoded.undocumented {
+            return None;
+        }
+
+        // The fixed addressing modes are never indexed, so there's no page
+        // to cross
+        let (am_out, page_crossed) = match predecoded.input {
+            Some(input) => (input, false),
+            None => {
+                let am = predecoded.am;
+                let slice = predecoded.operand_bytes();
+                let page_crossed = predecoded.page_penalty
+                                && am.crosses_page_boundary(self, slice);
+                (am.process(self, slice), page_crossed)
             }
-            _ => None
+        };
+        self.cycles += predecoded.cycles as u64;
+        if page_crossed {
+            self.cycles += 1;
+        }
+
+        // Increment program counter
+        self.registers.program_counter = pc + predecoded.len();
+
+        Some((predecoded.instr, am_out))
+    }
+
+    // Looks up the opcode at the given address and fetches its operand bytes,
+    // unless the bus's decode cache has kept the result from last time.
+    fn predecode(&mut self, pc: Address) -> Option<PredecodedInstr> {
+        let variant = self.variant;
+        let cached = match self.memory.decode_cache() {
+            Some(cache) => cache.get(pc, variant),
+            None => None,
+        };
+        if cached.is_some() {
+            return cached;
+        }
+
+        let opcode = self.memory.get_byte(pc);
+        let (instr, am, cycles) = match variant.opcodes()[opcode as usize] {
+            Some(entry) => entry,
+            None => return None,
+        };
+
+        let AddressDiff(len) = am.extra_bytes();
+        let mut operand = [0u8; 2];
+        for i in 0..(len as usize) {
+            operand[i] = self.memory.get_byte(pc + AddressDiff(1 + i as i32));
+        }
+        let input = if am.is_fixed() {
+            Some(am.process(self, &operand[..(len as usize)]))
+        } else {
+            None
+        };
+
+        let predecoded = PredecodedInstr {
+            opcode:       opcode,
+            instr:        instr,
+            am:           am,
+            cycles:       cycles,
+            operand:      operand,
+            input:        input,
+            undocumented: variant.is_undocumented_opcode(opcode),
+            page_penalty: variant.has_page_crossing_penalty(instr),
+        };
+        if let Some(cache) = self.memory.decode_cache() {
+            cache.insert(pc, variant, predecoded);
         }
+        Some(predecoded)
     }
 
-    pub fn execute_instruction(&mut self, decoded_instr: DecodedInstr) {
+    // Returns false if the instruction can't be run with that addressing
+    // mode. The opcode tables never decode to such a combination, so this
+    // only happens when the tables and this disagree.
+    pub fn execute_instruction(&mut self, decoded_instr: DecodedInstr)
+        -> bool
+    {
         match decoded_instr {
             (Instruction::ADC, OpInput::UseImmediate(val)) => {
                 debug!("add with carry immediate: {}", val);
@@ -101,15 +438,16 @@ impl Machine {
             (Instruction::ASL, OpInput::UseImplied) => {
                 // Accumulator mode
                 let mut val = self.registers.accumulator as u8;
-                Machine::shift_left_with_flags(&mut val,
-                                               &mut self.registers.status);
+                Machine::<B>::shift_left_with_flags(
+                    &mut val, &mut self.registers.status);
                 self.registers.accumulator = val as i8;
 
             }
             (Instruction::ASL, OpInput::UseAddress(addr)) => {
-                Machine::shift_left_with_flags(
-                    self.memory.get_byte_mut_ref(addr),
-                    &mut self.registers.status);
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::shift_left_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
             }
 
             (Instruction::BCC, OpInput::UseRelative(rel)) => {
@@ -139,10 +477,10 @@ impl Machine {
                 let is_zero = 0 == res;
 
                 // The N flag is set to bit 7 of the byte from memory.
-                let bit7 = 0 != (0x80 & res);
+                let bit7 = 0 != (0x80 & m);
 
                 // The V flag is set to bit 6 of the byte from memory.
-                let bit6 = 0 != (0x40 & res);
+                let bit6 = 0 != (0x40 & m);
 
                 self.registers.status.set_with_mask(
                     PS_ZERO | PS_NEGATIVE | PS_OVERFLOW,
@@ -152,6 +490,16 @@ impl Machine {
                                              ..StatusArgs::none() } ));
             }
 
+            (Instruction::BIT, OpInput::UseImmediate(val)) => {
+                // BIT #imm only sets Z, since N and V would just be copies of
+                // bits of the operand.
+                let is_zero = (self.registers.accumulator as u8 & val) == 0;
+                self.registers.status.set_with_mask(
+                    PS_ZERO,
+                    Status::new(StatusArgs { zero: is_zero,
+                                             ..StatusArgs::none() } ));
+            }
+
             (Instruction::BMI, OpInput::UseRelative(rel)) => {
                 let addr = self.registers.program_counter
                          + AddressDiff(rel as i32);
@@ -159,12 +507,37 @@ impl Machine {
                 self.branch_if_minus(addr);
             }
 
+            (Instruction::BNE, OpInput::UseRelative(rel)) => {
+                let addr = self.registers.program_counter
+                         + AddressDiff(rel as i32);
+                self.branch_if_not_equal(addr);
+            }
+
             (Instruction::BPL, OpInput::UseRelative(rel)) => {
                 let addr = self.registers.program_counter
                          + AddressDiff(rel as i32);
                 self.branch_if_positive(addr);
             }
 
+            (Instruction::BRK, OpInput::UseImplied) => {
+                // The byte after BRK is a padding (or "signature") byte, so
+                // the return address pushed is the BRK address plus two.
+                let return_addr = self.registers.program_counter
+                                + AddressDiff(1);
+                self.push_address_on_stack(return_addr);
+
+                // B is only ever set in the copy of the status that is pushed
+                // on the stack.
+                let status = self.registers.status | PS_BRK | PS_UNUSED;
+                self.push_on_stack(status.bits());
+                self.registers.status.or(PS_DISABLE_INTERRUPTS);
+                self.clear_decimal_on_interrupt();
+
+                let addr = self.read_vector(IRQ_INTERRUPT_VECTOR_LO,
+                                            IRQ_INTERRUPT_VECTOR_HI);
+                self.jump(addr);
+            }
+
             (Instruction::BVC, OpInput::UseRelative(rel)) => {
                 let addr = self.registers.program_counter
                          + AddressDiff(rel as i32);
@@ -214,6 +587,11 @@ impl Machine {
                 self.compare_with_y_register(val);
             }
 
+            (Instruction::DEC, OpInput::UseImplied) => {
+                // Accumulator mode
+                let val = (self.registers.accumulator as u8).wrapping_sub(1);
+                self.load_accumulator(val as i8);
+            }
             (Instruction::DEC, OpInput::UseAddress(addr)) => {
                 self.decrement_memory(addr)
             }
@@ -221,6 +599,9 @@ impl Machine {
             (Instruction::DEX, OpInput::UseImplied) => {
                 self.dec_x();
             }
+            (Instruction::DEY, OpInput::UseImplied) => {
+                self.dec_y();
+            }
 
             (Instruction::EOR, OpInput::UseImmediate(val)) => {
                 self.exclusive_or(val);
@@ -230,25 +611,34 @@ impl Machine {
                 self.exclusive_or(val);
             }
 
+            (Instruction::INC, OpInput::UseImplied) => {
+                // Accumulator mode
+                let val = (self.registers.accumulator as u8).wrapping_add(1);
+                self.load_accumulator(val as i8);
+            }
             (Instruction::INC, OpInput::UseAddress(addr)) => {
                  let m = self.memory.get_byte(addr);
-                 let m = m + 1;
-                 self.memory.set_byte(addr, m);
+                 let m = m.wrapping_add(1);
+                 self.write_byte(addr, m);
                  let i = m as i8;
-                 Machine::set_flags_from_i8(&mut self.registers.status, i);
+                 Machine::<B>::set_flags_from_i8(&mut self.registers.status,
+                                                 i);
             }
             (Instruction::INX, OpInput::UseImplied) => {
-                let x = self.registers.index_x + 1;
+                let x = self.registers.index_x.wrapping_add(1);
                 self.load_x_register(x);
             }
             (Instruction::INY, OpInput::UseImplied) => {
-                let y = self.registers.index_y + 1;
+                let y = self.registers.index_y.wrapping_add(1);
                 self.load_y_register(y);
             }
 
             (Instruction::JMP, OpInput::UseAddress(addr)) => {
                 self.jump(addr)
             }
+            (Instruction::JSR, OpInput::UseAddress(addr)) => {
+                self.jump_to_subroutine(addr)
+            }
 
             (Instruction::LDA, OpInput::UseImmediate(val)) => {
                 debug!("load A immediate: {}", val);
@@ -283,14 +673,15 @@ impl Machine {
             (Instruction::LSR, OpInput::UseImplied) => {
                 // Accumulator mode
                 let mut val = self.registers.accumulator as u8;
-                Machine::shift_right_with_flags(&mut val,
-                                                &mut self.registers.status);
+                Machine::<B>::shift_right_with_flags(
+                    &mut val, &mut self.registers.status);
                 self.registers.accumulator = val as i8;
             }
             (Instruction::LSR, OpInput::UseAddress(addr)) => {
-                Machine::shift_right_with_flags(
-                    self.memory.get_byte_mut_ref(addr),
-                    &mut self.registers.status);
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::shift_right_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
             }
 
             (Instruction::ORA, OpInput::UseImmediate(val)) => {
@@ -307,47 +698,56 @@ impl Machine {
                 self.push_on_stack(val);
             }
             (Instruction::PHP, OpInput::UseImplied) => {
-                // Push status
-                let val = self.registers.status.bits();
-                self.push_on_stack(val);
+                // Push status. As with BRK, the copy pushed has B set.
+                let status = self.registers.status | PS_BRK | PS_UNUSED;
+                self.push_on_stack(status.bits());
             }
             (Instruction::PLA, OpInput::UseImplied) => {
                 // Pull accumulator
                 let val: u8 = self.pull_from_stack();
-                self.registers.accumulator = val as i8;
+                self.load_accumulator(val as i8);
             }
             (Instruction::PLP, OpInput::UseImplied) => {
                 // Pull status
-                let val: u8 = self.pull_from_stack();
-                // The `truncate` here won't do anything because we have a
-                // constant for the single unused flags bit. This probably
-                // corresponds to the behavior of the 6502...? FIXME: verify
-                self.registers.status = Status::from_bits_truncate(val);
+                self.pull_status_from_stack();
             }
 
             (Instruction::ROL, OpInput::UseImplied) => {
                 // Accumulator mode
                 let mut val = self.registers.accumulator as u8;
-                Machine::rotate_left_with_flags(&mut val,
-                                                &mut self.registers.status);
+                Machine::<B>::rotate_left_with_flags(
+                    &mut val, &mut self.registers.status);
                 self.registers.accumulator = val as i8;
             }
             (Instruction::ROL, OpInput::UseAddress(addr)) => {
-                Machine::rotate_left_with_flags(
-                    self.memory.get_byte_mut_ref(addr),
-                    &mut self.registers.status);
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::rotate_left_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
             }
             (Instruction::ROR, OpInput::UseImplied) => {
                 // Accumulator mode
                 let mut val = self.registers.accumulator as u8;
-                Machine::rotate_right_with_flags(&mut val,
-                                                 &mut self.registers.status);
+                Machine::<B>::rotate_right_with_flags(
+                    &mut val, &mut self.registers.status);
                 self.registers.accumulator = val as i8;
             }
             (Instruction::ROR, OpInput::UseAddress(addr)) => {
-                Machine::rotate_right_with_flags(
-                    self.memory.get_byte_mut_ref(addr),
-                    &mut self.registers.status);
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::rotate_right_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
+            }
+
+            (Instruction::RTI, OpInput::UseImplied) => {
+                // Pull status, then the return address. Unlike RTS, the
+                // address on the stack is the actual return address.
+                self.pull_status_from_stack();
+                let addr = self.pull_address_from_stack();
+                self.jump(addr);
+            }
+            (Instruction::RTS, OpInput::UseImplied) => {
+                self.return_from_subroutine();
             }
 
             (Instruction::SBC, OpInput::UseImmediate(val)) => {
@@ -372,13 +772,13 @@ impl Machine {
             }
 
             (Instruction::STA, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.accumulator as u8);
+                self.write_byte(addr, self.registers.accumulator as u8);
             }
             (Instruction::STX, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.index_x as u8);
+                self.write_byte(addr, self.registers.index_x as u8);
             }
             (Instruction::STY, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.index_y as u8);
+                self.write_byte(addr, self.registers.index_y as u8);
             }
 
             (Instruction::TAX, OpInput::UseImplied) => {
@@ -413,21 +813,382 @@ impl Machine {
             (Instruction::NOP, OpInput::UseImplied) => {
                 debug!("NOP instruction");
             }
+            (Instruction::NOP, OpInput::UseImmediate(_)) => {}
+            (Instruction::NOP, OpInput::UseAddress(addr)) => {
+                // The undocumented NOPs with an operand still read it
+                self.memory.get_byte(addr);
+            }
+
+            // Undocumented instructions
+
+            (Instruction::ALR, OpInput::UseImmediate(val)) => {
+                self.and(val as i8);
+                let mut a = self.registers.accumulator as u8;
+                Machine::<B>::shift_right_with_flags(
+                    &mut a, &mut self.registers.status);
+                self.registers.accumulator = a as i8;
+            }
+            (Instruction::ANC, OpInput::UseImmediate(val)) => {
+                // Carry is set from bit 7, like the N flag
+                self.and(val as i8);
+                let negative = self.registers.status.contains(PS_NEGATIVE);
+                self.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: negative,
+                                             ..StatusArgs::none() } ));
+            }
+            (Instruction::ANE, OpInput::UseImmediate(val)) => {
+                let a = (self.registers.accumulator as u8 | UNSTABLE_CONSTANT)
+                      & self.registers.index_x as u8 & val;
+                self.load_accumulator(a as i8);
+            }
+            (Instruction::ARR, OpInput::UseImmediate(val)) => {
+                self.and_rotate_right(val);
+            }
+            (Instruction::DCP, OpInput::UseAddress(addr)) => {
+                let val = self.memory.get_byte(addr).wrapping_sub(1);
+                self.write_byte(addr, val);
+                self.compare_with_a_register(val);
+            }
+            (Instruction::ISC, OpInput::UseAddress(addr)) => {
+                let val = self.memory.get_byte(addr).wrapping_add(1);
+                self.write_byte(addr, val);
+                self.subtract_with_carry(val as i8);
+            }
+            (Instruction::JAM, OpInput::UseImplied) => {
+                // The processor locks up until it's reset. It's as if it kept
+                // on executing the JAM.
+                self.registers.program_counter =
+                    self.registers.program_counter + AddressDiff(-1);
+            }
+            (Instruction::LAS, OpInput::UseAddress(addr)) => {
+                let StackPointer(sp) = self.registers.stack_pointer;
+                let val = self.memory.get_byte(addr) & sp;
+                self.registers.stack_pointer = StackPointer(val);
+                self.registers.index_x = val as i8;
+                self.load_accumulator(val as i8);
+            }
+            (Instruction::LAX, OpInput::UseAddress(addr)) => {
+                let val = self.memory.get_byte(addr) as i8;
+                self.registers.index_x = val;
+                self.load_accumulator(val);
+            }
+            (Instruction::LXA, OpInput::UseImmediate(val)) => {
+                let a = (self.registers.accumulator as u8 | UNSTABLE_CONSTANT)
+                      & val;
+                self.registers.index_x = a as i8;
+                self.load_accumulator(a as i8);
+            }
+            (Instruction::RLA, OpInput::UseAddress(addr)) => {
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::rotate_left_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
+                self.and(val as i8);
+            }
+            (Instruction::RRA, OpInput::UseAddress(addr)) => {
+                // The carry out of the rotate is the carry into the addition
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::rotate_right_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
+                self.add_with_carry(val as i8);
+            }
+            (Instruction::SAX, OpInput::UseAddress(addr)) => {
+                let val = self.registers.accumulator & self.registers.index_x;
+                self.write_byte(addr, val as u8);
+            }
+            (Instruction::SBX, OpInput::UseImmediate(val)) => {
+                // Like CMP, so the carry flag is set by the subtraction but
+                // the decimal flag is ignored
+                let ax = (self.registers.accumulator
+                          & self.registers.index_x) as u8;
+                self.load_x_register(ax.wrapping_sub(val) as i8);
+                self.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: ax >= val,
+                                             ..StatusArgs::none() } ));
+            }
+            (Instruction::SHA, OpInput::UseAddress(addr)) => {
+                let val = self.registers.accumulator & self.registers.index_x;
+                let y = self.registers.index_y as u8;
+                self.store_and_high_byte(addr, y, val as u8);
+            }
+            (Instruction::SHX, OpInput::UseAddress(addr)) => {
+                let val = self.registers.index_x as u8;
+                let y = self.registers.index_y as u8;
+                self.store_and_high_byte(addr, y, val);
+            }
+            (Instruction::SHY, OpInput::UseAddress(addr)) => {
+                let val = self.registers.index_y as u8;
+                let x = self.registers.index_x as u8;
+                self.store_and_high_byte(addr, x, val);
+            }
+            (Instruction::SLO, OpInput::UseAddress(addr)) => {
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::shift_left_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
+                self.inclusive_or(val);
+            }
+            (Instruction::SRE, OpInput::UseAddress(addr)) => {
+                let mut val = self.memory.get_byte(addr);
+                Machine::<B>::shift_right_with_flags(
+                    &mut val, &mut self.registers.status);
+                self.write_byte(addr, val);
+                self.exclusive_or(val);
+            }
+            (Instruction::TAS, OpInput::UseAddress(addr)) => {
+                let val = (self.registers.accumulator
+                           & self.registers.index_x) as u8;
+                self.registers.stack_pointer = StackPointer(val);
+                let y = self.registers.index_y as u8;
+                self.store_and_high_byte(addr, y, val);
+            }
+
+            // 65C02 instructions
+
+            (instr, OpInput::UseZeroPageRelative(addr, rel)) => {
+                // BBRn and BBSn
+                let (bit, set) = instr.bit_operation().unwrap();
+                let is_set = self.memory.get_byte(addr) & (1 << bit) != 0;
+                if is_set == set {
+                    let target = self.registers.program_counter
+                               + AddressDiff(rel as i32);
+                    self.branch(target);
+                }
+            }
+            (instr, OpInput::UseAddress(addr))
+                if instr.bit_operation().is_some() => {
+                // RMBn and SMBn
+                let (bit, set) = instr.bit_operation().unwrap();
+                let val = self.memory.get_byte(addr);
+                let val = if set { val | (1 << bit) }
+                          else   { val & !(1 << bit) };
+                self.write_byte(addr, val);
+            }
+
+            (Instruction::BRA, OpInput::UseRelative(rel)) => {
+                let addr = self.registers.program_counter
+                         + AddressDiff(rel as i32);
+                self.branch(addr);
+            }
+
+            (Instruction::PHX, OpInput::UseImplied) => {
+                let val = self.registers.index_x as u8;
+                self.push_on_stack(val);
+            }
+            (Instruction::PHY, OpInput::UseImplied) => {
+                let val = self.registers.index_y as u8;
+                self.push_on_stack(val);
+            }
+            (Instruction::PLX, OpInput::UseImplied) => {
+                let val = self.pull_from_stack();
+                self.load_x_register(val as i8);
+            }
+            (Instruction::PLY, OpInput::UseImplied) => {
+                let val = self.pull_from_stack();
+                self.load_y_register(val as i8);
+            }
+
+            (Instruction::STP, OpInput::UseImplied) => {
+                self.stopped = true;
+            }
+            (Instruction::STZ, OpInput::UseAddress(addr)) => {
+                self.write_byte(addr, 0);
+            }
+
+            (Instruction::TRB, OpInput::UseAddress(addr)) => {
+                let a = self.registers.accumulator as u8;
+                let val = self.memory.get_byte(addr);
+                self.set_zero_from_test(a & val);
+                self.write_byte(addr, val & !a);
+            }
+            (Instruction::TSB, OpInput::UseAddress(addr)) => {
+                let a = self.registers.accumulator as u8;
+                let val = self.memory.get_byte(addr);
+                self.set_zero_from_test(a & val);
+                self.write_byte(addr, val | a);
+            }
+
+            (Instruction::WAI, OpInput::UseImplied) => {
+                self.waiting = true;
+            }
+
             (_, _) => {
                 debug!("attempting to execute unimplemented or invalid \
                         instruction");
+                return false;
             }
         };
+
+        true
     }
 
-    pub fn run(&mut self) {
-        loop {
-            if let Some(decoded_instr) = self.fetch_next_and_decode() {
-                self.execute_instruction(decoded_instr);
+    // Services any pending interrupt and then runs one instruction, unless
+    // something stops it. An invalid opcode isn't run at all, and nor is a
+    // BRK with `stop_on_brk` set. A JAM runs (and keeps on running each time
+    // this is called), and so does STP, which leaves a 65C02 stopped.
+    //
+    // A 65C02 that's waiting after a WAI just lets a cycle go by, unless
+    // there's an interrupt to wake it up. (It wakes up even if IRQs are
+    // disabled, and then carries on after the WAI without servicing the
+    // IRQ.)
+    pub fn step(&mut self) -> Option<StopReason> {
+        if self.history.is_none() {
+            return self.step_unrecorded();
+        }
+
+        let delta = Delta { registers:   self.registers,
+                            cycles:      self.cycles,
+                            nmi_pending: self.nmi_pending,
+                            waiting:     self.waiting,
+                            stopped:     self.stopped,
+                            writes:      Vec::new() };
+        let cycles_before = self.cycles;
+        if let Some(ref mut history) = self.history {
+            history.begin(delta);
+        }
+
+        let reason = self.step_unrecorded();
+
+        // Every step that does anything takes at least a cycle
+        let changed = self.cycles != cycles_before;
+        if let Some(ref mut history) = self.history {
+            history.finish(changed);
+        }
+        reason
+    }
+
+    fn step_unrecorded(&mut self) -> Option<StopReason> {
+        if self.stopped {
+            return Some(StopReason::Stp);
+        }
+
+        if self.waiting {
+            if self.nmi_pending || self.irq_line || self.memory.irq_asserted() {
+                self.waiting = false;
             } else {
-                break
+                self.cycles += 1;
+                self.memory.tick(1);
+                return None;
             }
         }
+
+        let cycles_before = self.cycles;
+        self.service_interrupts();
+
+        if self.tracer.is_some() {
+            let line = trace::format_line(self);
+            if let Some(ref mut tracer) = self.tracer {
+                (**tracer)(&line);
+            }
+        }
+
+        let pc = self.registers.program_counter;
+        let is_brk = self.stop_on_brk && {
+            let opcode = self.memory.peek_byte(pc);
+            match self.variant.opcodes()[opcode as usize] {
+                Some((Instruction::BRK, _, _)) => true,
+                _ => false,
+            }
+        };
+
+        let reason = if is_brk {
+            Some(StopReason::Brk(pc))
+        } else if let Some(decoded_instr) = self.fetch_next_and_decode() {
+            if !self.execute_instruction(decoded_instr) {
+                self.registers.program_counter = pc;
+                Some(StopReason::InvalidOpcode(pc, self.memory.peek_byte(pc)))
+            } else {
+                match decoded_instr.0 {
+                    Instruction::JAM => Some(StopReason::Jam(pc)),
+                    Instruction::STP => Some(StopReason::Stp),
+                    _ => None,
+                }
+            }
+        } else {
+            Some(StopReason::InvalidOpcode(pc, self.memory.peek_byte(pc)))
+        };
+
+        let elapsed = self.cycles - cycles_before;
+        self.memory.tick(elapsed);
+        reason
+    }
+
+    // Runs until something stops the processor
+    pub fn run(&mut self) -> StopReason {
+        self.run_bounded(&[], None, None, &mut |_| false).reason
+    }
+
+    // Like `run`, but also stops with `Breakpoint` when the PC reaches one of
+    // `breakpoints`, and with `BudgetExhausted` after `max_instructions` if
+    // there is a maximum. Breakpoints are checked before each instruction
+    // apart from the first, so that running again from a breakpoint doesn't
+    // stop straight away.
+    pub fn run_to_breakpoint(&mut self, breakpoints: &[Address],
+                             max_instructions: Option<u64>) -> RunResult {
+        self.run_bounded(breakpoints, max_instructions, None, &mut |_| false)
+    }
+
+    // Like `run`, but stops with `BudgetExhausted` after `count` instructions
+    pub fn run_for_instructions(&mut self, count: u64) -> RunResult {
+        self.run_bounded(&[], Some(count), None, &mut |_| false)
+    }
+
+    // Like `run`, but stops with `BudgetExhausted` once at least `count`
+    // cycles have gone by. The last instruction may take it a few cycles
+    // over.
+    pub fn run_for_cycles(&mut self, count: u64) -> RunResult {
+        self.run_bounded(&[], None, Some(count), &mut |_| false)
+    }
+
+    // Like `run`, but also checks `condition` after each instruction and
+    // stops with `ConditionMet` once it's true
+    pub fn run_until<F>(&mut self, mut condition: F) -> RunResult
+        where F: FnMut(&Machine<B>) -> bool
+    {
+        self.run_bounded(&[], None, None, &mut condition)
+    }
+
+    // Each step counts as an instruction, including the idle cycles of a
+    // processor waiting after WAI, so that a budget always runs out. The
+    // instruction that stopped the processor isn't counted.
+    fn run_bounded(&mut self, breakpoints: &[Address],
+                   max_instructions: Option<u64>, max_cycles: Option<u64>,
+                   condition: &mut FnMut(&Machine<B>) -> bool) -> RunResult {
+        let cycles_before = self.cycles;
+        let mut instructions = 0;
+
+        let reason;
+        loop {
+            let cycles = self.cycles - cycles_before;
+            if max_instructions.map_or(false, |max| instructions >= max)
+               || max_cycles.map_or(false, |max| cycles >= max) {
+                reason = StopReason::BudgetExhausted;
+                break;
+            }
+
+            if let Some(stop) = self.step() {
+                reason = stop;
+                break;
+            }
+            instructions += 1;
+
+            if condition(self) {
+                reason = StopReason::ConditionMet;
+                break;
+            }
+            let pc = self.registers.program_counter;
+            if !self.waiting && breakpoints.contains(&pc) {
+                reason = StopReason::Breakpoint(pc);
+                break;
+            }
+        }
+
+        RunResult { instructions: instructions,
+                    cycles:       self.cycles - cycles_before,
+                    reason:       reason }
     }
 
     fn set_flags_from_i8(status: &mut Status, value: i8) {
@@ -450,7 +1211,7 @@ impl Machine {
             PS_CARRY,
             Status::new(StatusArgs { carry: is_bit_7_set,
                                      ..StatusArgs::none() } ));
-        Machine::set_flags_from_i8(status, *p_val as i8);
+        Machine::<B>::set_flags_from_i8(status, *p_val as i8);
     }
 
     fn shift_right_with_flags(p_val: &mut u8, status: &mut Status) {
@@ -461,7 +1222,7 @@ impl Machine {
             PS_CARRY,
             Status::new(StatusArgs { carry: is_bit_0_set,
                                      ..StatusArgs::none() } ));
-        Machine::set_flags_from_i8(status, *p_val as i8);
+        Machine::<B>::set_flags_from_i8(status, *p_val as i8);
     }
 
     fn rotate_left_with_flags(p_val: &mut u8, status: &mut Status) {
@@ -474,7 +1235,7 @@ impl Machine {
             PS_CARRY,
             Status::new(StatusArgs { carry: is_bit_7_set,
                                      ..StatusArgs::none() } ));
-        Machine::set_flags_from_i8(status, *p_val as i8);
+        Machine::<B>::set_flags_from_i8(status, *p_val as i8);
     }
 
     fn rotate_right_with_flags(p_val: &mut u8, status: &mut Status) {
@@ -487,62 +1248,76 @@ impl Machine {
             PS_CARRY,
             Status::new(StatusArgs { carry: is_bit_0_set,
                                      ..StatusArgs::none() } ));
-        Machine::set_flags_from_i8(status, *p_val as i8);
+        Machine::<B>::set_flags_from_i8(status, *p_val as i8);
     }
 
     fn set_i8_with_flags(mem: &mut i8, status: &mut Status, value: i8) {
         *mem = value;
-        Machine::set_flags_from_i8(status, value);
+        Machine::<B>::set_flags_from_i8(status, value);
     }
 
     fn load_x_register(&mut self, value: i8) {
-        Machine::set_i8_with_flags(&mut self.registers.index_x,
-                                   &mut self.registers.status,
-                                   value);
+        Machine::<B>::set_i8_with_flags(&mut self.registers.index_x,
+                                        &mut self.registers.status,
+                                        value);
     }
 
     fn load_y_register(&mut self, value: i8) {
-        Machine::set_i8_with_flags(&mut self.registers.index_y,
-                                   &mut self.registers.status,
-                                   value);
+        Machine::<B>::set_i8_with_flags(&mut self.registers.index_y,
+                                        &mut self.registers.status,
+                                        value);
     }
 
     fn load_accumulator(&mut self, value: i8) {
-        Machine::set_i8_with_flags(&mut self.registers.accumulator,
-                                   &mut self.registers.status,
-                                   value);
+        Machine::<B>::set_i8_with_flags(&mut self.registers.accumulator,
+                                        &mut self.registers.status,
+                                        value);
+    }
+
+    // Whether ADC and SBC should do decimal arithmetic
+    fn decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode()
+            && self.registers.status.contains(PS_DECIMAL_MODE)
     }
 
     fn add_with_carry(&mut self, value: i8) {
-        if self.registers.status.contains(PS_DECIMAL_MODE) {
-            // TODO akeeton: Implement binary-coded decimal.
-            debug!("binary-coded decimal not implemented for add_with_carry");
+        if self.decimal_mode() {
+            self.decimal_add_with_carry(value as u8);
+            if self.variant == Variant::Cmos {
+                // The 65C02 takes an extra cycle to set N and Z properly
+                // from the decimal result.
+                let a = self.registers.accumulator;
+                self.load_accumulator(a);
+                self.cycles += 1;
+            }
         } else {
-            let a_before: i8 = self.registers.accumulator;
-            let c_before: i8 = if self.registers.status.contains(PS_CARRY)
-                               { 1 } else { 0 };
-            let a_after: i8 = a_before + c_before + value;
+            let c_before = self.registers.status.contains(PS_CARRY);
+            self.binary_add_with_carry(value as u8, c_before);
 
-            debug_assert_eq!(a_after as u8, a_before as u8 + c_before as u8
-                                            + value as u8);
-
-            let did_carry = (a_after as u8) < (a_before as u8);
+            debug!("accumulator: {}", self.registers.accumulator);
+        }
+    }
 
-            let did_overflow =
-            	   (a_before < 0 && value < 0 && a_after >= 0)
-            	|| (a_before > 0 && value > 0 && a_after <= 0);
+    // Adds A, M and C as unsigned bytes. C is set if the sum doesn't fit in a
+    // byte, and V is set if A and M have the same sign and the result
+    // doesn't. SBC is this with M inverted.
+    fn binary_add_with_carry(&mut self, value: u8, carry: bool) {
+        let a_before = self.registers.accumulator as u8;
+        let sum = a_before as u16 + value as u16 + if carry { 1 } else { 0 };
+        let a_after = sum as u8;
 
-            let mask = PS_CARRY | PS_OVERFLOW;
+        let did_carry = sum > 0xff;
+        let did_overflow = (!(a_before ^ value) & (a_before ^ a_after) & 0x80)
+                           != 0;
 
-            self.registers.status.set_with_mask(mask,
-                Status::new(StatusArgs { carry: did_carry,
-                                         overflow: did_overflow,
-                                         ..StatusArgs::none() } ));
+        let mask = PS_CARRY | PS_OVERFLOW;
 
-            self.load_accumulator(a_after);
+        self.registers.status.set_with_mask(mask,
+            Status::new(StatusArgs { carry: did_carry,
+                                     overflow: did_overflow,
+                                     ..StatusArgs::none() } ));
 
-            debug!("accumulator: {}", self.registers.accumulator);
-        }
+        self.load_accumulator(a_after as i8);
     }
 
     fn and(&mut self, value: i8) {
@@ -550,55 +1325,116 @@ impl Machine {
         self.load_accumulator(a_after);
     }
 
-    // TODO: Implement binary-coded decimal
     fn subtract_with_carry(&mut self, value: i8) {
-        if self.registers.status.contains(PS_DECIMAL_MODE) {
-            debug!("binary-coded decimal not implemented for \
-                    subtract_with_carry");
-        } else {
-            // A - M - (1 - C)
+        // A - M - (1 - C), which is A + !M + C. C ends up set when there was
+        // no borrow.
+        let carry = self.registers.status.contains(PS_CARRY);
+        let a_before = self.registers.accumulator as u8;
+
+        self.binary_add_with_carry(!(value as u8), carry);
+
+        if self.decimal_mode() {
+            if self.variant == Variant::Cmos {
+                // C and V are still the binary ones, but the 65C02 takes an
+                // extra cycle to set N and Z from the decimal result.
+                let a_decimal = Machine::<B>::cmos_decimal_subtract(
+                    a_before, value as u8, carry);
+                self.load_accumulator(a_decimal as i8);
+                self.cycles += 1;
+            } else {
+                // On the NMOS 6502 all of the flags are the ones from the
+                // binary subtraction above; only the accumulator is
+                // different.
+                let a_decimal = Machine::<B>::decimal_subtract(a_before,
+                                                               value as u8,
+                                                               carry);
+                self.registers.accumulator = a_decimal as i8;
+            }
+        }
+    }
 
-            // nc -- 'not carry'
-            let nc: i8 = if self.registers.status.contains(PS_CARRY)
-                         { 0 } else { 1 };
+    // The decimal mode algorithms below are the ones described in Bruce
+    // Clark's "Decimal Mode" tutorial
+    // (http://www.6502.org/tutorials/decimal_mode.html), which also describes
+    // what the NMOS 6502 does with invalid BCD operands.
+    fn decimal_add_with_carry(&mut self, value: u8) {
+        let a_before = self.registers.accumulator as u8;
+        let c_before: i32 = if self.registers.status.contains(PS_CARRY)
+                            { 1 } else { 0 };
+
+        // Add the low nibbles, adjusting the result if it's not a valid
+        // decimal digit. An adjusted digit carries into the high nibble.
+        let mut lo = (a_before & 0x0f) as i32 + (value & 0x0f) as i32
+                   + c_before;
+        if lo >= 0x0a {
+            lo = ((lo + 0x06) & 0x0f) + 0x10;
+        }
 
-            let a_before: i8 = self.registers.accumulator;
+        // N and V are computed from the sum before the high nibble is
+        // adjusted, treating the high nibbles as signed.
+        let signed_sum = ((a_before & 0xf0) as i8) as i32
+                       + ((value & 0xf0) as i8) as i32
+                       + lo;
+        let is_negative = (signed_sum & 0x80) != 0;
+        let did_overflow = signed_sum < -128 || signed_sum > 127;
+
+        let mut sum = (a_before & 0xf0) as i32 + (value & 0xf0) as i32 + lo;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        let did_carry = sum >= 0x100;
 
-            let a_after = a_before - value - nc;
+        // Z is the one flag that comes from the binary addition.
+        let is_zero = ((a_before as i32 + value as i32 + c_before) & 0xff) == 0;
 
-            // The carry flag is set on unsigned overflow.
-            let did_carry = (a_after as u8) > (a_before as u8);
+        self.registers.status.set_with_mask(
+            PS_NEGATIVE | PS_OVERFLOW | PS_ZERO | PS_CARRY,
+            Status::new(StatusArgs { negative: is_negative,
+                                     overflow: did_overflow,
+                                     zero:     is_zero,
+                                     carry:    did_carry,
+                                     ..StatusArgs::none() } ));
 
-            // The overflow flag is set on two's-complement overflow.
-            //
-            // range of A              is  -128 to 127
-            // range of - M - (1 - C)  is  -128 to 128
-            //                            -(127 + 1) to -(-128 + 0)
-            //
-            let over = ((nc == 0 && value < 0) || (nc == 1 && value < -1))
-                    && a_before >= 0
-                    && a_after < 0;
+        self.registers.accumulator = (sum & 0xff) as u8 as i8;
+    }
 
-            let under = (a_before < 0) && (-value - nc < 0)
-                     && a_after >= 0;
+    fn decimal_subtract(a: u8, value: u8, carry: bool) -> u8 {
+        let borrow: i32 = if carry { 0 } else { 1 };
 
-            let did_overflow = over || under;
+        let mut lo = (a & 0x0f) as i32 - (value & 0x0f) as i32 - borrow;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0f) - 0x10;
+        }
+
+        let mut diff = (a & 0xf0) as i32 - (value & 0xf0) as i32 + lo;
+        if diff < 0 {
+            diff -= 0x60;
+        }
 
-            let mask = PS_CARRY | PS_OVERFLOW;
+        (diff & 0xff) as u8
+    }
 
-            self.registers.status.set_with_mask(mask,
-                Status::new(StatusArgs { carry: did_carry,
-                                         overflow: did_overflow,
-                                         ..StatusArgs::none() } ));
+    // The 65C02 adjusts the binary difference instead, which only differs
+    // from the above for invalid BCD operands.
+    fn cmos_decimal_subtract(a: u8, value: u8, carry: bool) -> u8 {
+        let borrow: i32 = if carry { 0 } else { 1 };
 
-            self.load_accumulator(a_after);
+        let lo = (a & 0x0f) as i32 - (value & 0x0f) as i32 - borrow;
+        let mut diff = a as i32 - value as i32 - borrow;
+        if diff < 0 {
+            diff -= 0x60;
         }
+        if lo < 0 {
+            diff -= 0x06;
+        }
+
+        (diff & 0xff) as u8
     }
 
     fn decrement_memory(&mut self, addr: Address) {
-        let value_new = self.memory.get_byte(addr) - 1;
+        let value_new = self.memory.get_byte(addr).wrapping_sub(1);
 
-        self.memory.set_byte(addr, value_new);
+        self.write_byte(addr, value_new);
 
         let is_negative = (value_new as i8) < 0;
         let is_zero     = value_new == 0;
@@ -612,52 +1448,88 @@ impl Machine {
 
     fn dec_x(&mut self) {
         let val = self.registers.index_x;
-        self.load_x_register(val - 1);
+        self.load_x_register(val.wrapping_sub(1));
+    }
+
+    fn dec_y(&mut self) {
+        let val = self.registers.index_y;
+        self.load_y_register(val.wrapping_sub(1));
     }
 
     fn jump(&mut self, addr: Address) {
         self.registers.program_counter = addr;
     }
 
+    // JSR pushes the address of the last byte of the JSR instruction (that
+    // is, the return address minus one), high byte first.
+    fn jump_to_subroutine(&mut self, addr: Address) {
+        let last_byte = self.registers.program_counter + AddressDiff(-1);
+        self.push_address_on_stack(last_byte);
+        self.jump(addr);
+    }
+
+    // RTS pulls the address pushed by JSR and adds one to it.
+    fn return_from_subroutine(&mut self) {
+        let addr = self.pull_address_from_stack() + AddressDiff(1);
+        self.jump(addr);
+    }
+
+    // A taken branch costs one extra cycle, and another one if the target is
+    // on a different page than the instruction after the branch.
+    fn branch(&mut self, addr: Address) {
+        let pc = self.registers.program_counter;
+        self.cycles += 1;
+        if pc.get_page_number() != addr.get_page_number() {
+            self.cycles += 1;
+        }
+        self.registers.program_counter = addr;
+    }
+
     fn branch_if_carry_clear(&mut self, addr: Address) {
         if !self.registers.status.contains(PS_CARRY) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
     fn branch_if_carry_set(&mut self, addr: Address) {
         if self.registers.status.contains(PS_CARRY) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
     fn branch_if_equal(&mut self, addr: Address) {
         if self.registers.status.contains(PS_ZERO) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
+        }
+    }
+
+    fn branch_if_not_equal(&mut self, addr: Address) {
+        if !self.registers.status.contains(PS_ZERO) {
+            self.branch(addr);
         }
     }
 
     fn branch_if_minus(&mut self, addr: Address) {
         if self.registers.status.contains(PS_NEGATIVE) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
     fn branch_if_positive(&mut self, addr: Address) {
         if !self.registers.status.contains(PS_NEGATIVE) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
     fn branch_if_overflow_clear(&mut self, addr: Address) {
         if !self.registers.status.contains(PS_OVERFLOW) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
     fn branch_if_overflow_set(&mut self, addr: Address) {
         if self.registers.status.contains(PS_OVERFLOW) {
-            self.registers.program_counter = addr;
+            self.branch(addr);
         }
     }
 
@@ -681,14 +1553,73 @@ impl Machine {
             self.registers.status.remove(PS_ZERO);
         }
 
-        let diff: i8 = (r as i8) - (val as i8);
-        if diff < 0 {
+        let diff = (r as u8).wrapping_sub(val);
+        if (diff & 0x80) != 0 {
             self.registers.status.insert(PS_NEGATIVE);
         } else {
             self.registers.status.remove(PS_NEGATIVE);
         }
     }
 
+    // ARR is an AND followed by a ROR of the accumulator, but its flags come
+    // from the adder, which is involved too. In decimal mode the adder also
+    // applies a BCD correction to each nibble of the result.
+    fn and_rotate_right(&mut self, val: u8) {
+        let and = self.registers.accumulator as u8 & val;
+        let carry_in = self.registers.status.contains(PS_CARRY);
+        let rotated = (and >> 1) | if carry_in { 0x80 } else { 0 };
+
+        let (result, carry, overflow, negative);
+        if self.decimal_mode() {
+            let mut fixed = rotated;
+            if (and & 0x0f) + (and & 0x01) > 0x05 {
+                fixed = (fixed & 0xf0) | (fixed.wrapping_add(0x06) & 0x0f);
+            }
+            carry = (and as u16 & 0xf0) + (and as u16 & 0x10) > 0x50;
+            if carry {
+                fixed = (fixed & 0x0f) | (fixed.wrapping_add(0x60) & 0xf0);
+            }
+            result = fixed;
+            overflow = (and ^ rotated) & 0x40 != 0;
+            negative = carry_in;
+        } else {
+            result = rotated;
+            carry = rotated & 0x40 != 0;
+            overflow = ((rotated >> 6) ^ (rotated >> 5)) & 0x01 != 0;
+            negative = rotated & 0x80 != 0;
+        }
+
+        self.registers.accumulator = result as i8;
+        self.registers.status.set_with_mask(
+            PS_CARRY | PS_OVERFLOW | PS_NEGATIVE | PS_ZERO,
+            Status::new(StatusArgs { carry: carry,
+                                     overflow: overflow,
+                                     negative: negative,
+                                     zero: rotated == 0,
+                                     ..StatusArgs::none() } ));
+    }
+
+    // SHA, SHX, SHY and TAS store a value ANDed with the high byte of the
+    // base address plus one. If indexing crossed a page, the value also
+    // replaces the high byte of the address that gets written to.
+    fn store_and_high_byte(&mut self, addr: Address, index: u8, val: u8) {
+        let base = addr.to_u16().wrapping_sub(index as u16);
+        let result = val & ((base >> 8) as u8).wrapping_add(1);
+        let target = if base & 0xff00 == addr.to_u16() & 0xff00 {
+            addr
+        } else {
+            Address(((result as u16) << 8) | (addr.to_u16() & 0x00ff))
+        };
+        self.write_byte(target, result);
+    }
+
+    fn set_zero_from_test(&mut self, val: u8) {
+        self.registers.status.set_with_mask(
+            PS_ZERO,
+            Status::new(StatusArgs { zero: val == 0,
+                                     ..StatusArgs::none() } ));
+    }
+
     fn compare_with_a_register(&mut self, val: u8) {
         let a = self.registers.accumulator;
         self.compare(a, val);
@@ -716,27 +1647,110 @@ impl Machine {
         self.load_accumulator(a_after);
     }
 
+    // Everything the machine writes goes through here, so that it can be
+    // recorded in the history
+    fn write_byte(&mut self, address: Address, value: u8) {
+        let old_value = self.memory.set_byte(address, value);
+        if let Some(ref mut history) = self.history {
+            history.record_write(address, old_value);
+        }
+    }
+
     fn push_on_stack(&mut self, val: u8) {
         let addr = self.registers.stack_pointer.to_address();
-        self.memory.set_byte(addr, val);
+        self.write_byte(addr, val);
         self.registers.stack_pointer.decrement();
     }
 
+    // The stack pointer points at the next free slot, so pulling has to
+    // increment it before reading.
     fn pull_from_stack(&mut self) -> u8 {
-        let addr = self.registers.stack_pointer.to_address();
-        let out = self.memory.get_byte(addr);
         self.registers.stack_pointer.increment();
-        out
+        let addr = self.registers.stack_pointer.to_address();
+        self.memory.get_byte(addr)
+    }
+
+    // Addresses are pushed high byte first, so that they end up in memory in
+    // the usual little-endian order.
+    fn push_address_on_stack(&mut self, addr: Address) {
+        let val = addr.to_u16();
+        self.push_on_stack((val >> 8) as u8);
+        self.push_on_stack((val & 0xff) as u8);
+    }
+
+    fn pull_address_from_stack(&mut self) -> Address {
+        let lo = self.pull_from_stack() as u16;
+        let hi = self.pull_from_stack() as u16;
+        Address((hi << 8) | lo)
+    }
+
+    // B and the unused bit only exist in the copies of the status on the
+    // stack, so PLP and RTI ignore B, and the unused bit always reads as set.
+    fn pull_status_from_stack(&mut self) {
+        let val = self.pull_from_stack();
+        self.registers.status =
+            (Status::from_bits_truncate(val) - PS_BRK) | PS_UNUSED;
     }
 }
 
-impl std::fmt::Debug for Machine {
+impl<B> std::fmt::Debug for Machine<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Machine Dump:\n\nAccumulator: {}",
-               self.registers.accumulator)
+        let regs = &self.registers;
+        write!(f, "Machine Dump:\n\n\
+                   A: ${:02X}  X: ${:02X}  Y: ${:02X}  \
+                   SP: ${:02X}  PC: ${:04X}\n\
+                   P: ${:02X}  {}\n\
+                   Cycles: {}",
+               regs.accumulator as u8, regs.index_x as u8,
+               regs.index_y as u8, regs.stack_pointer.0,
+               regs.program_counter.to_u16(), regs.status.bits(),
+               regs.status, self.cycles)
+    }
+}
+
+// 2 KiB of RAM mirrored across the whole address space
+#[cfg(test)]
+struct MirroredRam {
+    bytes: [u8; 0x800]
+}
+
+#[cfg(test)]
+impl Bus for MirroredRam {
+    fn get_byte(&mut self, address: Address) -> u8 {
+        self.peek_byte(address)
+    }
+
+    fn peek_byte(&self, address: Address) -> u8 {
+        self.bytes[address.to_usize() & 0x7ff]
+    }
+
+    fn set_byte(&mut self, address: Address, value: u8) -> u8 {
+        let old_value = self.peek_byte(address);
+        self.bytes[address.to_usize() & 0x7ff] = value;
+        old_value
     }
 }
 
+#[test]
+fn custom_bus_test() {
+    let mut machine = Machine::with_bus(MirroredRam { bytes: [0; 0x800] });
+
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xA9, 0x42,       // LDA #$42
+        0x8D, 0x10, 0x08, // STA $0810
+        0xAE, 0x10, 0x00, // LDX $0010
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+
+    for _ in 0..3 {
+        let decoded_instr = machine.fetch_next_and_decode().unwrap();
+        machine.execute_instruction(decoded_instr);
+    }
+
+    assert_eq!(machine.registers.index_x, 0x42);
+    assert_eq!(machine.memory.peek_byte(Address(0xF810)), 0x42);
+}
+
 #[test]
 fn add_with_carry_test() {
     let mut machine = Machine::new();
@@ -786,28 +1800,250 @@ fn add_with_carry_test() {
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE),  true);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
 
-    machine.add_with_carry(127);
-    assert_eq!(machine.registers.accumulator, -1);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    machine.add_with_carry(127);
+    assert_eq!(machine.registers.accumulator, -1);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE),  true);
+    assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
+
+    let mut machine = Machine::new();
+
+    machine.add_with_carry(127);
+    assert_eq!(machine.registers.accumulator, 127);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
+    assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
+
+    machine.add_with_carry(1);
+    assert_eq!(machine.registers.accumulator, -128);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE),  true);
+    assert_eq!(machine.registers.status.contains(PS_OVERFLOW),  true);
+}
+
+// Reference model for binary ADC and SBC, computing the result in wider
+// integers: C from the unsigned sum and V from the signed one. Returns
+// (A, C, Z, N, V).
+#[cfg(test)]
+fn binary_adc_reference(a: u8, m: u8, c: bool)
+    -> (u8, bool, bool, bool, bool)
+{
+    let c = if c { 1 } else { 0 };
+    let unsigned = a as i32 + m as i32 + c;
+    let signed = (a as i8) as i32 + (m as i8) as i32 + c;
+    let result = (unsigned & 0xff) as u8;
+
+    (result, unsigned > 0xff, result == 0, (result as i8) < 0,
+     signed < -128 || signed > 127)
+}
+
+#[cfg(test)]
+fn binary_sbc_reference(a: u8, m: u8, c: bool)
+    -> (u8, bool, bool, bool, bool)
+{
+    let borrow = if c { 0 } else { 1 };
+    let unsigned = a as i32 - m as i32 - borrow;
+    let signed = (a as i8) as i32 - (m as i8) as i32 - borrow;
+    let result = (unsigned & 0xff) as u8;
+
+    (result, unsigned >= 0, result == 0, (result as i8) < 0,
+     signed < -128 || signed > 127)
+}
+
+// Runs ADC or SBC over every accumulator, operand and carry, comparing the
+// result and flags against the reference model.
+#[cfg(test)]
+fn check_binary_arithmetic<F, R>(mut op: F, reference: R)
+    where F: FnMut(&mut Machine, i8),
+          R: Fn(u8, u8, bool) -> (u8, bool, bool, bool, bool)
+{
+    let mut machine = Machine::new();
+
+    for a in range_incl(0u8, 255u8) {
+        for m in range_incl(0u8, 255u8) {
+            for &c in [false, true].iter() {
+                machine.registers.accumulator = a as i8;
+                machine.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: c,
+                                             ..StatusArgs::none() } ));
+
+                op(&mut machine, m as i8);
+
+                let (a_after, carry, zero, negative, overflow) =
+                    reference(a, m, c);
+                let status = machine.registers.status;
+                assert_eq!(machine.registers.accumulator as u8, a_after);
+                assert_eq!(status.contains(PS_CARRY),    carry);
+                assert_eq!(status.contains(PS_ZERO),     zero);
+                assert_eq!(status.contains(PS_NEGATIVE), negative);
+                assert_eq!(status.contains(PS_OVERFLOW), overflow);
+            }
+        }
+    }
+}
+
+#[test]
+fn binary_arithmetic_exhaustive_test() {
+    check_binary_arithmetic(|machine, m| machine.add_with_carry(m),
+                            binary_adc_reference);
+    check_binary_arithmetic(|machine, m| machine.subtract_with_carry(m),
+                            binary_sbc_reference);
+}
+
+// Reference model for decimal mode ADC, written independently of the
+// implementation above (this is how VICE describes the NMOS 6502).
+// Returns (A, C, Z, N, V).
+#[cfg(test)]
+fn decimal_adc_reference(a: u8, m: u8, c: bool)
+    -> (u8, bool, bool, bool, bool)
+{
+    let a = a as u32;
+    let m = m as u32;
+    let c = if c { 1 } else { 0 };
+
+    let mut tmp = (a & 0x0f) + (m & 0x0f) + c;
+    if tmp > 0x09 {
+        tmp += 0x06;
+    }
+    if tmp <= 0x0f {
+        tmp = (tmp & 0x0f) + (a & 0xf0) + (m & 0xf0);
+    } else {
+        tmp = (tmp & 0x0f) + (a & 0xf0) + (m & 0xf0) + 0x10;
+    }
+
+    let zero = ((a + m + c) & 0xff) == 0;
+    let negative = (tmp & 0x80) != 0;
+    let overflow = ((a ^ tmp) & 0x80) != 0 && ((a ^ m) & 0x80) == 0;
+
+    if (tmp & 0x1f0) > 0x90 {
+        tmp += 0x60;
+    }
+    let carry = (tmp & 0xff0) > 0xf0;
+
+    ((tmp & 0xff) as u8, carry, zero, negative, overflow)
+}
+
+// Reference model for the decimal mode SBC accumulator result. (The flags are
+// the binary ones; see binary_sbc_reference.)
+#[cfg(test)]
+fn decimal_sbc_reference(a: u8, m: u8, c: bool) -> u8 {
+    let a = a as i32;
+    let m = m as i32;
+    let borrow = if c { 0 } else { 1 };
+
+    let mut tmp = ((a & 0x0f) - (m & 0x0f) - borrow) & 0x1ff;
+    if (tmp & 0x10) != 0 {
+        tmp = ((tmp - 0x06) & 0x0f) | (((a & 0xf0) - (m & 0xf0) - 0x10) & 0x1ff);
+    } else {
+        tmp = (tmp & 0x0f) | (((a & 0xf0) - (m & 0xf0)) & 0x1ff);
+    }
+    if (tmp & 0x100) != 0 {
+        tmp -= 0x60;
+    }
+
+    (tmp & 0xff) as u8
+}
+
+#[test]
+fn decimal_add_with_carry_test() {
+    let mut machine = Machine::new();
+    machine.execute_instruction((Instruction::SED, OpInput::UseImplied));
+
+    machine.execute_instruction((Instruction::CLC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x19;
+    machine.add_with_carry(0x28);
+    assert_eq!(machine.registers.accumulator as u8, 0x47);
+    assert_eq!(machine.registers.status.contains(PS_CARRY), false);
+
+    machine.execute_instruction((Instruction::SEC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x58;
+    machine.add_with_carry(0x46);
+    assert_eq!(machine.registers.accumulator as u8, 0x05);
+    assert_eq!(machine.registers.status.contains(PS_CARRY), true);
+
+    // 99 + 1 = 00 with carry, but Z reflects the binary result ($9A).
+    machine.execute_instruction((Instruction::CLC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x99 as u8 as i8;
+    machine.add_with_carry(0x01);
+    assert_eq!(machine.registers.accumulator, 0);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
-    assert_eq!(machine.registers.status.contains(PS_NEGATIVE),  true);
-    assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
+
+    for a in range_incl(0u8, 255u8) {
+        for m in range_incl(0u8, 255u8) {
+            for &c in [false, true].iter() {
+                machine.registers.accumulator = a as i8;
+                machine.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: c,
+                                             ..StatusArgs::none() } ));
+
+                machine.add_with_carry(m as i8);
 
+                let (a_after, carry, zero, negative, overflow) =
+                    decimal_adc_reference(a, m, c);
+                let status = machine.registers.status;
+                assert_eq!(machine.registers.accumulator as u8, a_after);
+                assert_eq!(status.contains(PS_CARRY),    carry);
+                assert_eq!(status.contains(PS_ZERO),     zero);
+                assert_eq!(status.contains(PS_NEGATIVE), negative);
+                assert_eq!(status.contains(PS_OVERFLOW), overflow);
+            }
+        }
+    }
+}
+
+#[test]
+fn decimal_subtract_with_carry_test() {
     let mut machine = Machine::new();
+    machine.execute_instruction((Instruction::SED, OpInput::UseImplied));
 
-    machine.add_with_carry(127);
-    assert_eq!(machine.registers.accumulator, 127);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
-    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
-    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
-    assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
+    machine.execute_instruction((Instruction::SEC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x46;
+    machine.subtract_with_carry(0x12);
+    assert_eq!(machine.registers.accumulator as u8, 0x34);
 
-    machine.add_with_carry(1);
-    assert_eq!(machine.registers.accumulator, -128);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
-    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
-    assert_eq!(machine.registers.status.contains(PS_NEGATIVE),  true);
-    assert_eq!(machine.registers.status.contains(PS_OVERFLOW),  true);
+    machine.execute_instruction((Instruction::SEC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x40;
+    machine.subtract_with_carry(0x13);
+    assert_eq!(machine.registers.accumulator as u8, 0x27);
+
+    machine.execute_instruction((Instruction::CLC, OpInput::UseImplied));
+    machine.registers.accumulator = 0x00;
+    machine.subtract_with_carry(0x00);
+    assert_eq!(machine.registers.accumulator as u8, 0x99);
+
+    for a in range_incl(0u8, 255u8) {
+        for m in range_incl(0u8, 255u8) {
+            for &c in [false, true].iter() {
+                machine.registers.accumulator = a as i8;
+                machine.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: c,
+                                             ..StatusArgs::none() } ));
+                machine.subtract_with_carry(m as i8);
+
+                // The flags are the ones from the binary subtraction, which
+                // decimal mode leaves alone on the NMOS 6502. C is set when
+                // there's no borrow.
+                let (_, carry, zero, negative, overflow) =
+                    binary_sbc_reference(a, m, c);
+                let status = machine.registers.status;
+                assert_eq!(machine.registers.accumulator as u8,
+                           decimal_sbc_reference(a, m, c));
+                assert_eq!(status.contains(PS_CARRY),    carry);
+                assert_eq!(status.contains(PS_ZERO),     zero);
+                assert_eq!(status.contains(PS_NEGATIVE), negative);
+                assert_eq!(status.contains(PS_OVERFLOW), overflow);
+                assert!(status.contains(PS_DECIMAL_MODE));
+            }
+        }
+    }
 }
 
 #[test]
@@ -848,7 +2084,7 @@ fn subtract_with_carry_test() {
 
     machine.subtract_with_carry(1);
     assert_eq!(machine.registers.accumulator, -1);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
@@ -857,7 +2093,7 @@ fn subtract_with_carry_test() {
     machine.registers.accumulator = -128;
     machine.subtract_with_carry(1);
     assert_eq!(machine.registers.accumulator, 127);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), true);
@@ -866,7 +2102,7 @@ fn subtract_with_carry_test() {
     machine.registers.accumulator = 127;
     machine.subtract_with_carry(-1);
     assert_eq!(machine.registers.accumulator, -128);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), true);
@@ -875,7 +2111,7 @@ fn subtract_with_carry_test() {
     machine.registers.accumulator = -64;
     machine.subtract_with_carry(64);
     assert_eq!(machine.registers.accumulator, 127);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), true);
@@ -884,7 +2120,7 @@ fn subtract_with_carry_test() {
     machine.registers.accumulator = 0;
     machine.subtract_with_carry(-128);
     assert_eq!(machine.registers.accumulator, -128);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), true);
@@ -893,7 +2129,7 @@ fn subtract_with_carry_test() {
     machine.registers.accumulator = 0;
     machine.subtract_with_carry(127);
     assert_eq!(machine.registers.accumulator, -128);
-    assert_eq!(machine.registers.status.contains(PS_CARRY),    true);
+    assert_eq!(machine.registers.status.contains(PS_CARRY),    false);
     assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
     assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
@@ -1020,6 +2256,101 @@ fn dec_x_test() {
     assert_eq!(machine.registers.status.contains(PS_OVERFLOW), false);
 }
 
+#[test]
+fn dec_y_test() {
+    let mut machine = Machine::new();
+
+    machine.load_y_register(2);
+    machine.execute_instruction((Instruction::DEY, OpInput::UseImplied));
+    assert_eq!(machine.registers.index_y, 1);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
+
+    machine.execute_instruction((Instruction::DEY, OpInput::UseImplied));
+    assert_eq!(machine.registers.index_y, 0);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     true);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), false);
+
+    machine.execute_instruction((Instruction::DEY, OpInput::UseImplied));
+    assert_eq!(machine.registers.index_y, -1);
+    assert_eq!(machine.registers.status.contains(PS_ZERO),     false);
+    assert_eq!(machine.registers.status.contains(PS_NEGATIVE), true);
+}
+
+#[test]
+fn increment_decrement_wraparound_test() {
+    let mut machine = Machine::new();
+    let addr = Address(0x0200);
+
+    for v in range_incl(0u8, 255u8) {
+        let up = ((v as u32 + 1) & 0xff) as u8;
+        let down = ((v as u32 + 0xff) & 0xff) as u8;
+
+        let ops = [(Instruction::INX, up), (Instruction::DEX, down),
+                   (Instruction::INY, up), (Instruction::DEY, down),
+                   (Instruction::INC, up), (Instruction::DEC, down)];
+        for &(instr, expected) in ops.iter() {
+            machine.registers.index_x = v as i8;
+            machine.registers.index_y = v as i8;
+            machine.memory.set_byte(addr, v);
+
+            let result = match instr {
+                Instruction::INX | Instruction::DEX => {
+                    machine.execute_instruction((instr,
+                                                 OpInput::UseImplied));
+                    machine.registers.index_x as u8
+                }
+                Instruction::INY | Instruction::DEY => {
+                    machine.execute_instruction((instr,
+                                                 OpInput::UseImplied));
+                    machine.registers.index_y as u8
+                }
+                _ => {
+                    machine.execute_instruction((instr,
+                                                 OpInput::UseAddress(addr)));
+                    machine.memory.get_byte(addr)
+                }
+            };
+
+            assert_eq!(result, expected);
+            let status = machine.registers.status;
+            assert_eq!(status.contains(PS_ZERO),     expected == 0);
+            assert_eq!(status.contains(PS_NEGATIVE), expected >= 0x80);
+        }
+    }
+}
+
+#[test]
+fn compare_exhaustive_test() {
+    let mut machine = Machine::new();
+
+    for r in range_incl(0u8, 255u8) {
+        for m in range_incl(0u8, 255u8) {
+            machine.registers.accumulator = r as i8;
+            machine.compare_with_a_register(m);
+
+            let diff = (r as i32 - m as i32) & 0xff;
+            let status = machine.registers.status;
+            assert_eq!(status.contains(PS_CARRY),    r >= m);
+            assert_eq!(status.contains(PS_ZERO),     r == m);
+            assert_eq!(status.contains(PS_NEGATIVE), diff >= 0x80);
+        }
+    }
+}
+
+#[test]
+fn stack_pointer_wraparound_test() {
+    let mut machine = Machine::new();
+
+    machine.registers.stack_pointer = StackPointer(0x00);
+    machine.push_on_stack(0x12);
+    assert_eq!(machine.memory.get_byte(Address(0x0100)), 0x12);
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFF));
+
+    assert_eq!(machine.pull_from_stack(), 0x12);
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0x00));
+}
+
 #[test]
 fn jump_test() {
     let mut machine = Machine::new();
@@ -1029,6 +2360,325 @@ fn jump_test() {
     assert_eq!(machine.registers.program_counter, addr);
 }
 
+#[test]
+fn jump_to_subroutine_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    // As if a JSR at $1000 had just been fetched
+    machine.registers.program_counter = Address(0x1003);
+    machine.execute_instruction((Instruction::JSR,
+                                 OpInput::UseAddress(Address(0xA1B1))));
+    assert_eq!(machine.registers.program_counter, Address(0xA1B1));
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFD));
+    assert_eq!(machine.memory.get_byte(Address(0x01FF)), 0x10);
+    assert_eq!(machine.memory.get_byte(Address(0x01FE)), 0x02);
+
+    machine.execute_instruction((Instruction::RTS, OpInput::UseImplied));
+    assert_eq!(machine.registers.program_counter, Address(0x1003));
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFF));
+}
+
+#[test]
+fn break_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_LO, 0x34);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_HI, 0x12);
+    machine.registers.status.remove(PS_DISABLE_INTERRUPTS);
+    machine.registers.status.insert(PS_CARRY);
+
+    // As if a BRK at $2000 had just been fetched
+    machine.registers.program_counter = Address(0x2001);
+    machine.execute_instruction((Instruction::BRK, OpInput::UseImplied));
+    assert_eq!(machine.registers.program_counter, Address(0x1234));
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFC));
+    assert_eq!(machine.memory.get_byte(Address(0x01FF)), 0x20);
+    assert_eq!(machine.memory.get_byte(Address(0x01FE)), 0x02);
+
+    let pushed = Status::from_bits_truncate(
+        machine.memory.get_byte(Address(0x01FD)));
+    assert!(pushed.contains(PS_BRK));
+    assert!(pushed.contains(PS_CARRY));
+    assert!(!pushed.contains(PS_DISABLE_INTERRUPTS));
+
+    assert!(!machine.registers.status.contains(PS_BRK));
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+}
+
+#[test]
+fn return_from_interrupt_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    machine.push_address_on_stack(Address(0xABCD));
+    machine.push_on_stack((PS_CARRY | PS_NEGATIVE).bits());
+
+    machine.execute_instruction((Instruction::RTI, OpInput::UseImplied));
+    assert_eq!(machine.registers.program_counter, Address(0xABCD));
+    assert_eq!(machine.registers.status,
+               PS_CARRY | PS_NEGATIVE | PS_UNUSED);
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFF));
+}
+
+#[test]
+fn bit_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_byte(Address(0x0010), 0xC0);
+
+    // N and V come from the byte in memory, even where A has those bits clear
+    machine.registers.accumulator = 0x01;
+    machine.execute_instruction((Instruction::BIT,
+                                 OpInput::UseAddress(Address(0x0010))));
+    assert!(machine.registers.status.contains(PS_ZERO));
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+    assert!(machine.registers.status.contains(PS_OVERFLOW));
+
+    machine.registers.accumulator = 0x40;
+    machine.memory.set_byte(Address(0x0010), 0x41);
+    machine.execute_instruction((Instruction::BIT,
+                                 OpInput::UseAddress(Address(0x0010))));
+    assert!(!machine.registers.status.contains(PS_ZERO));
+    assert!(!machine.registers.status.contains(PS_NEGATIVE));
+    assert!(machine.registers.status.contains(PS_OVERFLOW));
+}
+
+#[test]
+fn pull_accumulator_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    machine.push_on_stack(0x80);
+    machine.execute_instruction((Instruction::PLA, OpInput::UseImplied));
+    assert_eq!(machine.registers.accumulator as u8, 0x80);
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+    assert!(!machine.registers.status.contains(PS_ZERO));
+
+    machine.push_on_stack(0x00);
+    machine.execute_instruction((Instruction::PLA, OpInput::UseImplied));
+    assert_eq!(machine.registers.accumulator, 0);
+    assert!(!machine.registers.status.contains(PS_NEGATIVE));
+    assert!(machine.registers.status.contains(PS_ZERO));
+}
+
+#[test]
+fn push_status_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+    machine.registers.status = PS_CARRY;
+
+    machine.execute_instruction((Instruction::PHP, OpInput::UseImplied));
+    assert_eq!(machine.memory.get_byte(Address(0x01FF)),
+               (PS_CARRY | PS_BRK | PS_UNUSED).bits());
+    assert_eq!(machine.registers.status, PS_CARRY);
+}
+
+#[test]
+fn pull_status_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    machine.push_on_stack((PS_ZERO | PS_BRK).bits());
+    machine.execute_instruction((Instruction::PLP, OpInput::UseImplied));
+    assert_eq!(machine.registers.status, PS_ZERO | PS_UNUSED);
+
+    machine.push_address_on_stack(Address(0xABCD));
+    machine.push_on_stack(PS_BRK.bits());
+    machine.execute_instruction((Instruction::RTI, OpInput::UseImplied));
+    assert_eq!(machine.registers.status, PS_UNUSED);
+}
+
+#[test]
+fn reset_test() {
+    let mut memory = Memory::new();
+    memory.set_byte(RESET_VECTOR_LO, 0x00);
+    memory.set_byte(RESET_VECTOR_HI, 0xC0);
+
+    let mut machine = Machine::with_bus(memory);
+    assert_eq!(machine.registers.program_counter, Address(0xC000));
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFC));
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+    assert_eq!(machine.cycles, 7);
+
+    machine.registers.program_counter = Address(0x1234);
+    machine.registers.status.remove(PS_DISABLE_INTERRUPTS);
+    machine.trigger_nmi();
+    machine.reset();
+    assert_eq!(machine.registers.program_counter, Address(0xC000));
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xF9));
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+    assert!(!machine.nmi_pending);
+    assert_eq!(machine.cycles, 14);
+}
+
+#[test]
+fn irq_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_LO, 0x00);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_HI, 0x90);
+    machine.registers.stack_pointer = StackPointer(0xFF);
+    machine.registers.program_counter = Address(0x1234);
+    machine.cycles = 0;
+
+    // Masked
+    machine.assert_irq();
+    assert!(!machine.service_interrupts());
+    assert_eq!(machine.registers.program_counter, Address(0x1234));
+
+    machine.registers.status.remove(PS_DISABLE_INTERRUPTS);
+    machine.registers.status.insert(PS_CARRY);
+    assert!(machine.service_interrupts());
+    assert_eq!(machine.registers.program_counter, Address(0x9000));
+    assert_eq!(machine.memory.get_byte(Address(0x01FF)), 0x12);
+    assert_eq!(machine.memory.get_byte(Address(0x01FE)), 0x34);
+    assert_eq!(machine.cycles, 7);
+
+    let pushed = Status::from_bits_truncate(
+        machine.memory.get_byte(Address(0x01FD)));
+    assert!(!pushed.contains(PS_BRK));
+    assert!(pushed.contains(PS_CARRY));
+    assert!(!pushed.contains(PS_DISABLE_INTERRUPTS));
+
+    // The handler runs with interrupts disabled, so a still-asserted IRQ
+    // doesn't retrigger until RTI
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+    assert!(!machine.service_interrupts());
+
+    machine.execute_instruction((Instruction::RTI, OpInput::UseImplied));
+    assert_eq!(machine.registers.program_counter, Address(0x1234));
+    assert!(machine.service_interrupts());
+
+    machine.execute_instruction((Instruction::RTI, OpInput::UseImplied));
+    machine.deassert_irq();
+    assert!(!machine.service_interrupts());
+    assert_eq!(machine.registers.program_counter, Address(0x1234));
+}
+
+#[test]
+fn nmi_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_byte(NMI_INTERRUPT_VECTOR_LO, 0x00);
+    machine.memory.set_byte(NMI_INTERRUPT_VECTOR_HI, 0xA0);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_LO, 0x00);
+    machine.memory.set_byte(IRQ_INTERRUPT_VECTOR_HI, 0x90);
+    machine.registers.program_counter = Address(0x1234);
+
+    // NMI can't be masked, and takes priority over IRQ
+    machine.registers.status.remove(PS_DISABLE_INTERRUPTS);
+    machine.assert_irq();
+    machine.trigger_nmi();
+    assert!(machine.service_interrupts());
+    assert_eq!(machine.registers.program_counter, Address(0xA000));
+    assert!(machine.registers.status.contains(PS_DISABLE_INTERRUPTS));
+
+    // It's edge-triggered, so it only happens once
+    assert!(!machine.service_interrupts());
+    assert_eq!(machine.registers.program_counter, Address(0xA000));
+}
+
+#[test]
+fn stack_test() {
+    let mut machine = Machine::new();
+    machine.registers.stack_pointer = StackPointer(0xFF);
+
+    machine.push_on_stack(0x11);
+    machine.push_on_stack(0x22);
+    assert_eq!(machine.memory.get_byte(Address(0x01FF)), 0x11);
+    assert_eq!(machine.memory.get_byte(Address(0x01FE)), 0x22);
+    assert_eq!(machine.pull_from_stack(), 0x22);
+    assert_eq!(machine.pull_from_stack(), 0x11);
+    assert_eq!(machine.registers.stack_pointer, StackPointer(0xFF));
+
+    machine.push_address_on_stack(Address(0xBEEF));
+    assert_eq!(machine.pull_address_from_stack(), Address(0xBEEF));
+}
+
+#[test]
+fn cycles_test() {
+    let mut machine = Machine::new();
+    machine.cycles = 0;
+
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xA9, 0x01,       // LDA #$01      2 cycles
+        0x8D, 0x00, 0x20, // STA $2000     4 cycles
+        0xE6, 0x10,       // INC $10       5 cycles
+        0xEA,             // NOP           2 cycles
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+
+    let expected = [2, 6, 11, 13];
+    for &cycles in expected.iter() {
+        let decoded_instr = machine.fetch_next_and_decode().unwrap();
+        machine.execute_instruction(decoded_instr);
+        assert_eq!(machine.cycles, cycles);
+    }
+}
+
+#[test]
+fn page_crossing_cycles_test() {
+    let mut machine = Machine::new();
+    machine.cycles = 0;
+
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xBD, 0x80, 0x20, // LDA $2080,X
+        0x9D, 0x80, 0x20, // STA $2080,X
+        0xB1, 0x10,       // LDA ($10),Y
+    ]);
+    machine.memory.set_bytes(Address(0x0010), &[0xF0, 0x30]);
+    machine.registers.program_counter = Address(0x1000);
+
+    // Without a page crossing
+    machine.registers.index_x = 0x10;
+    let decoded_instr = machine.fetch_next_and_decode().unwrap();
+    machine.execute_instruction(decoded_instr);
+    assert_eq!(machine.cycles, 4);
+
+    // With a page crossing
+    machine.registers.program_counter = Address(0x1000);
+    machine.registers.index_x = -1;
+    machine.cycles = 0;
+    let decoded_instr = machine.fetch_next_and_decode().unwrap();
+    machine.execute_instruction(decoded_instr);
+    assert_eq!(machine.cycles, 5);
+
+    // Stores always take the extra cycle
+    machine.cycles = 0;
+    let decoded_instr = machine.fetch_next_and_decode().unwrap();
+    machine.execute_instruction(decoded_instr);
+    assert_eq!(machine.cycles, 5);
+
+    // $30F0 + $10 crosses into page $31
+    machine.registers.index_y = 0x10;
+    machine.cycles = 0;
+    let decoded_instr = machine.fetch_next_and_decode().unwrap();
+    machine.execute_instruction(decoded_instr);
+    assert_eq!(machine.cycles, 6);
+}
+
+#[test]
+fn branch_cycles_test() {
+    let mut machine = Machine::new();
+    machine.cycles = 0;
+
+    machine.registers.program_counter = Address(0x10F0);
+
+    // Not taken
+    machine.registers.status.insert(PS_ZERO);
+    machine.branch_if_not_equal(Address(0x10F8));
+    assert_eq!(machine.cycles, 0);
+
+    // Taken, same page
+    machine.branch_if_equal(Address(0x10F8));
+    assert_eq!(machine.cycles, 1);
+    assert_eq!(machine.registers.program_counter, Address(0x10F8));
+
+    // Taken, different page
+    machine.branch_if_equal(Address(0x1102));
+    assert_eq!(machine.cycles, 3);
+    assert_eq!(machine.registers.program_counter, Address(0x1102));
+}
+
 #[test]
 fn branch_if_carry_clear_test() {
     let mut machine = Machine::new();
@@ -1067,6 +2717,19 @@ fn branch_if_equal_test() {
     assert_eq!(machine.registers.program_counter, Address(0xABCD));
 }
 
+#[test]
+fn branch_if_not_equal_test() {
+    let mut machine = Machine::new();
+
+    machine.registers.status.insert(PS_ZERO);
+    machine.branch_if_not_equal(Address(0xABCD));
+    assert_eq!(machine.registers.program_counter, Address(0));
+
+    machine.registers.status.remove(PS_ZERO);
+    machine.branch_if_not_equal(Address(0xABCD));
+    assert_eq!(machine.registers.program_counter, Address(0xABCD));
+}
+
 #[test]
 fn branch_if_minus_test() {
     {
@@ -1285,3 +2948,609 @@ fn inclusive_or_test() {
         }
     }
 }
+
+#[test]
+fn lax_sax_test() {
+    let mut machine = Machine::new();
+
+    machine.memory.set_byte(Address(0x10), 0x80);
+    machine.execute_instruction((Instruction::LAX,
+                                 OpInput::UseAddress(Address(0x10))));
+    assert_eq!(machine.registers.accumulator as u8, 0x80);
+    assert_eq!(machine.registers.index_x as u8, 0x80);
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+
+    machine.registers.index_x = 0x0F;
+    machine.execute_instruction((Instruction::SAX,
+                                 OpInput::UseAddress(Address(0x11))));
+    assert_eq!(machine.memory.get_byte(Address(0x11)), 0x00);
+
+    // SAX doesn't touch the flags
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+    assert!(!machine.registers.status.contains(PS_ZERO));
+}
+
+#[test]
+fn read_modify_write_combination_test() {
+    let mut machine = Machine::new();
+    let addr = Address(0x20);
+
+    // SLO: ASL, then ORA
+    machine.memory.set_byte(addr, 0x81);
+    machine.registers.accumulator = 0x10;
+    machine.execute_instruction((Instruction::SLO, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0x02);
+    assert_eq!(machine.registers.accumulator, 0x12);
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    // RLA: ROL (carry in), then AND
+    machine.memory.set_byte(addr, 0x40);
+    machine.registers.accumulator = 0x7F;
+    machine.execute_instruction((Instruction::RLA, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0x81);
+    assert_eq!(machine.registers.accumulator, 0x01);
+    assert!(!machine.registers.status.contains(PS_CARRY));
+
+    // SRE: LSR, then EOR
+    machine.memory.set_byte(addr, 0x03);
+    machine.registers.accumulator = 0x01;
+    machine.execute_instruction((Instruction::SRE, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0x01);
+    assert_eq!(machine.registers.accumulator, 0x00);
+    assert!(machine.registers.status.contains(PS_ZERO));
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    // RRA: ROR (carry in, carry out into the ADC), then ADC
+    machine.memory.set_byte(addr, 0x03);
+    machine.registers.accumulator = 0x10;
+    machine.execute_instruction((Instruction::RRA, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0x81);
+    assert_eq!(machine.registers.accumulator as u8, 0x92);
+
+    // DCP: DEC, then CMP
+    machine.memory.set_byte(addr, 0x00);
+    machine.registers.accumulator = 0x10;
+    machine.execute_instruction((Instruction::DCP, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0xFF);
+    assert!(!machine.registers.status.contains(PS_CARRY));
+    assert!(!machine.registers.status.contains(PS_ZERO));
+
+    // ISC: INC, then SBC
+    machine.memory.set_byte(addr, 0xFF);
+    machine.registers.accumulator = 0x10;
+    machine.registers.status.insert(PS_CARRY);
+    machine.execute_instruction((Instruction::ISC, OpInput::UseAddress(addr)));
+    assert_eq!(machine.memory.get_byte(addr), 0x00);
+    assert_eq!(machine.registers.accumulator, 0x10);
+}
+
+#[test]
+fn immediate_combination_test() {
+    let mut machine = Machine::new();
+
+    // ANC copies N into C
+    machine.registers.accumulator = 0xF0u8 as i8;
+    machine.execute_instruction((Instruction::ANC,
+                                 OpInput::UseImmediate(0x81)));
+    assert_eq!(machine.registers.accumulator as u8, 0x80);
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    // ALR is AND then LSR
+    machine.registers.accumulator = 0x0F;
+    machine.execute_instruction((Instruction::ALR,
+                                 OpInput::UseImmediate(0x03)));
+    assert_eq!(machine.registers.accumulator, 0x01);
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    // ARR takes C from bit 6 and V from bit 6 XOR bit 5
+    machine.registers.accumulator = 0xFFu8 as i8;
+    machine.registers.status.insert(PS_CARRY);
+    machine.execute_instruction((Instruction::ARR,
+                                 OpInput::UseImmediate(0x80)));
+    assert_eq!(machine.registers.accumulator as u8, 0xC0);
+    assert!(machine.registers.status.contains(PS_CARRY));
+    assert!(machine.registers.status.contains(PS_OVERFLOW));
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+
+    // ...and corrects each nibble in decimal mode
+    machine.registers.accumulator = 0xFFu8 as i8;
+    machine.registers.status.remove(PS_CARRY);
+    machine.registers.status.insert(PS_DECIMAL_MODE);
+    machine.execute_instruction((Instruction::ARR,
+                                 OpInput::UseImmediate(0xFF)));
+    assert_eq!(machine.registers.accumulator as u8, 0xD5);
+    assert!(machine.registers.status.contains(PS_CARRY));
+    assert!(!machine.registers.status.contains(PS_NEGATIVE));
+    machine.registers.status.remove(PS_DECIMAL_MODE);
+
+    // SBX subtracts from A AND X, ignoring the carry
+    machine.registers.accumulator = 0x3F;
+    machine.registers.index_x = 0x1E;
+    machine.registers.status.remove(PS_CARRY);
+    machine.execute_instruction((Instruction::SBX,
+                                 OpInput::UseImmediate(0x0E)));
+    assert_eq!(machine.registers.index_x, 0x10);
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    // LXA loads A and X
+    machine.registers.accumulator = 0x00;
+    machine.execute_instruction((Instruction::LXA,
+                                 OpInput::UseImmediate(0x33)));
+    assert_eq!(machine.registers.accumulator, 0x22);
+    assert_eq!(machine.registers.index_x, 0x22);
+}
+
+#[test]
+fn store_and_high_byte_test() {
+    let mut machine = Machine::new();
+    machine.registers.index_x = 0xFFu8 as i8;
+    machine.registers.index_y = 0x10;
+
+    // SHX $1200,Y stores X & $13
+    machine.execute_instruction((Instruction::SHX,
+                                 OpInput::UseAddress(Address(0x1210))));
+    assert_eq!(machine.memory.get_byte(Address(0x1210)), 0x13);
+
+    // When indexing crosses a page, the value also becomes the high byte of
+    // the address: SHY $12F8,X with X = $10 stores $10 & $13 at $1008
+    machine.registers.index_x = 0x10;
+    machine.execute_instruction((Instruction::SHY,
+                                 OpInput::UseAddress(Address(0x1308))));
+    assert_eq!(machine.memory.get_byte(Address(0x1008)), 0x10);
+    assert_eq!(machine.memory.get_byte(Address(0x1308)), 0x00);
+}
+
+#[test]
+fn jam_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xE8,             // INX
+        0x02,             // JAM
+        0xE8,             // INX
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+
+    assert_eq!(machine.run(), StopReason::Jam(Address(0x1001)));
+    assert_eq!(machine.registers.index_x, 1);
+    assert_eq!(machine.registers.program_counter, Address(0x1001));
+
+    // It stays stuck
+    assert_eq!(machine.step(), Some(StopReason::Jam(Address(0x1001))));
+    assert_eq!(machine.registers.program_counter, Address(0x1001));
+}
+
+#[test]
+fn strict_mode_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xA7, 0x10,       // LAX $10
+        0xEA,             // NOP
+        0x1A,             // NOP (undocumented)
+    ]);
+    machine.memory.set_byte(Address(0x10), 0x42);
+    machine.registers.program_counter = Address(0x1000);
+    machine.strict = true;
+
+    // The undocumented opcode is invalid, so nothing runs
+    assert_eq!(machine.step(),
+               Some(StopReason::InvalidOpcode(Address(0x1000), 0xA7)));
+    assert_eq!(machine.registers.program_counter, Address(0x1000));
+
+    machine.strict = false;
+    assert_eq!(machine.step(), None);
+    assert_eq!(machine.registers.accumulator, 0x42);
+
+    machine.strict = true;
+    assert_eq!(machine.step(), None);
+    assert_eq!(machine.step(),
+               Some(StopReason::InvalidOpcode(Address(0x1003), 0x1A)));
+    assert_eq!(machine.registers.program_counter, Address(0x1003));
+}
+
+#[test]
+fn stop_reason_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xE8,             // INX
+        0xE8,             // INX
+        0xE8,             // INX
+        0x00, 0x00,       // BRK
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+    let breakpoints = [Address(0x1002)];
+
+    // A breakpoint stops `run_to_breakpoint` before the instruction there,
+    // and running again carries on past it
+    let result = machine.run_to_breakpoint(&breakpoints, None);
+    assert_eq!(result.reason, StopReason::Breakpoint(Address(0x1002)));
+    assert_eq!(result.instructions, 2);
+    assert_eq!(machine.registers.index_x, 2);
+
+    machine.stop_on_brk = true;
+    let reason = machine.run_to_breakpoint(&breakpoints, None).reason;
+    assert_eq!(reason, StopReason::Brk(Address(0x1003)));
+    assert_eq!(reason.to_string(), "BRK at $1003");
+    assert_eq!(machine.registers.index_x, 3);
+    assert_eq!(machine.registers.program_counter, Address(0x1003));
+
+    // Otherwise BRK is just an instruction
+    machine.stop_on_brk = false;
+    assert_eq!(machine.step(), None);
+    assert_eq!(machine.registers.program_counter, Address(0x0000));
+
+    // A combination the tables never produce isn't run
+    assert!(!machine.execute_instruction((Instruction::STA,
+                                          OpInput::UseImmediate(0x00))));
+}
+
+#[test]
+fn bounded_run_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xE8,             // INX
+        0x4C, 0x00, 0x10, // JMP $1000
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+
+    // The loop never ends, but the budgets do
+    let result = machine.run_for_instructions(5);
+    assert_eq!(result, RunResult { instructions: 5, cycles: 12,
+                                   reason: StopReason::BudgetExhausted });
+    assert_eq!(machine.registers.index_x, 3);
+
+    let result = machine.run_for_cycles(10);
+    assert_eq!(result, RunResult { instructions: 4, cycles: 10,
+                                   reason: StopReason::BudgetExhausted });
+    assert_eq!(machine.registers.index_x, 5);
+
+    let result = machine.run_until(|m| m.registers.index_x == 0x10);
+    assert_eq!(result.reason, StopReason::ConditionMet);
+    assert_eq!(result.instructions, 22);
+    assert_eq!(machine.registers.program_counter, Address(0x1001));
+
+    // Anything else that stops the processor still does
+    machine.memory.set_byte(Address(0x1001), 0x02);
+    let result = machine.run_for_instructions(100);
+    assert_eq!(result.reason, StopReason::Jam(Address(0x1001)));
+    assert_eq!(result.instructions, 0);
+}
+
+#[cfg(test)]
+fn cmos_machine(program: &[u8]) -> Machine {
+    let mut machine = Machine::with_variant(Memory::new(), Variant::Cmos);
+    machine.memory.set_bytes(Address(0x1000), program);
+    machine.registers.program_counter = Address(0x1000);
+    machine
+}
+
+#[test]
+fn cmos_instructions_test() {
+    let mut machine = cmos_machine(&[
+        0xA9, 0x0F,       // LDA #$0F
+        0x1A,             // INC A
+        0x04, 0x20,       // TSB $20
+        0x14, 0x21,       // TRB $21
+        0x64, 0x22,       // STZ $22
+        0xDA,             // PHX
+        0x7A,             // PLY
+        0xB2, 0x30,       // LDA ($30)
+        0x89, 0x01,       // BIT #$01
+        0x02, 0x00,       // NOP #$00
+        0xDB,             // STP
+    ]);
+    machine.registers.index_x = 0x55;
+    machine.memory.set_byte(Address(0x20), 0x81);
+    machine.memory.set_byte(Address(0x21), 0xF0);
+    machine.memory.set_byte(Address(0x22), 0x99);
+    machine.memory.set_bytes(Address(0x30), &[0x00, 0x20]);
+    machine.memory.set_byte(Address(0x2000), 0xFE);
+
+    assert_eq!(machine.run(), StopReason::Stp);
+    assert!(machine.stopped);
+    assert_eq!(machine.registers.program_counter, Address(0x1012));
+
+    assert_eq!(machine.memory.get_byte(Address(0x20)), 0x91);
+    assert_eq!(machine.memory.get_byte(Address(0x21)), 0xE0);
+    assert_eq!(machine.memory.get_byte(Address(0x22)), 0x00);
+    assert_eq!(machine.registers.index_y, 0x55);
+    assert_eq!(machine.registers.accumulator as u8, 0xFE);
+
+    // BIT #imm only touches Z, so N is still set from the LDA
+    assert!(machine.registers.status.contains(PS_ZERO));
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+
+    // Stopped until the next reset
+    assert_eq!(machine.step(), Some(StopReason::Stp));
+    machine.reset();
+    assert!(!machine.stopped);
+}
+
+#[test]
+fn cmos_shift_cycles_test() {
+    // Shifts and rotates take 6 cycles for abs,X on the 65C02, and 7 when
+    // indexing crosses a page. INC and DEC always take 7.
+    let mut machine = cmos_machine(&[
+        0x1E, 0x00, 0x20, // ASL $2000,X
+        0x3E, 0xFF, 0x20, // ROL $20FF,X
+        0x5E, 0x00, 0x20, // LSR $2000,X
+        0x7E, 0xFF, 0x20, // ROR $20FF,X
+        0xFE, 0x00, 0x20, // INC $2000,X
+    ]);
+    machine.registers.index_x = 1;
+
+    let mut cycles = Vec::new();
+    for _ in 0..5 {
+        let before = machine.cycles;
+        machine.step();
+        cycles.push(machine.cycles - before);
+    }
+    assert_eq!(cycles, vec![6, 7, 6, 7, 7]);
+
+    // The NMOS 6502 always takes 7
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[0x1E, 0x00, 0x20]);
+    machine.registers.program_counter = Address(0x1000);
+    let before = machine.cycles;
+    machine.step();
+    assert_eq!(machine.cycles - before, 7);
+}
+
+#[test]
+fn cmos_bit_branch_test() {
+    let mut machine = cmos_machine(&[
+        0x87, 0x40,       // SMB0 $40
+        0x0F, 0x40, 0x02, // BBR0 $40,+2
+        0x80, 0x02,       // BRA +2
+        0xDB,             // STP
+        0xDB,             // STP
+        0x17, 0x40,       // RMB1 $40
+        0x8F, 0x40, 0xF9, // BBS0 $40,-7
+    ]);
+    machine.memory.set_byte(Address(0x40), 0x02);
+
+    // SMB0, BBR0 not taken, BRA over the STPs, RMB1, BBS0 back to the first
+    // STP
+    machine.run();
+    assert_eq!(machine.memory.get_byte(Address(0x40)), 0x01);
+    assert_eq!(machine.registers.program_counter, Address(0x1008));
+}
+
+#[test]
+fn cmos_decimal_test() {
+    let mut machine = cmos_machine(&[
+        0xF8,             // SED
+        0x38,             // SEC
+        0x69, 0x01,       // ADC #$01
+        0xE9, 0x10,       // SBC #$10
+    ]);
+    machine.registers.accumulator = 0x98u8 as i8;
+    for _ in 0..3 {
+        machine.step();
+    }
+
+    // Unlike on the NMOS 6502, N and Z come from the decimal result, and
+    // setting them takes an extra cycle
+    assert_eq!(machine.registers.accumulator as u8, 0x00);
+    assert!(machine.registers.status.contains(PS_ZERO));
+    assert!(!machine.registers.status.contains(PS_NEGATIVE));
+    assert!(machine.registers.status.contains(PS_CARRY));
+
+    let cycles_before = machine.cycles;
+    machine.step();
+    assert_eq!(machine.registers.accumulator as u8, 0x90);
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+    assert!(!machine.registers.status.contains(PS_ZERO));
+    assert_eq!(machine.cycles - cycles_before, 3);
+
+    // Reset clears the decimal flag on the 65C02
+    machine.reset();
+    assert!(!machine.registers.status.contains(PS_DECIMAL_MODE));
+}
+
+#[test]
+fn wai_test() {
+    let mut machine = cmos_machine(&[
+        0xCB,             // WAI
+        0xE8,             // INX
+    ]);
+    machine.registers.status.or(PS_DISABLE_INTERRUPTS);
+
+    machine.step();
+    assert!(machine.waiting);
+    let cycles_before = machine.cycles;
+    assert_eq!(machine.step(), None);
+    assert_eq!(machine.step(), None);
+    assert_eq!(machine.cycles - cycles_before, 2);
+    assert_eq!(machine.registers.index_x, 0);
+
+    // With interrupts disabled, an IRQ just wakes it up
+    machine.irq_line = true;
+    machine.step();
+    assert!(!machine.waiting);
+    assert_eq!(machine.registers.index_x, 1);
+    assert_eq!(machine.registers.program_counter, Address(0x1002));
+}
+
+#[test]
+fn ricoh_2a03_test() {
+    let mut machine = Machine::with_variant(Memory::new(), Variant::Ricoh2A03);
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xF8,             // SED
+        0x18,             // CLC
+        0xA9, 0x09,       // LDA #$09
+        0x69, 0x01,       // ADC #$01
+        0xA9, 0x10,       // LDA #$10
+        0x38,             // SEC
+        0xE9, 0x01,       // SBC #$01
+        0xA7, 0x20,       // LAX $20
+    ]);
+    machine.memory.set_byte(Address(0x0020), 0x42);
+    machine.registers.program_counter = Address(0x1000);
+
+    // D can still be set, but ADC and SBC ignore it
+    for _ in 0..4 {
+        machine.step();
+    }
+    assert!(machine.registers.status.contains(PS_DECIMAL_MODE));
+    assert_eq!(machine.registers.accumulator, 0x0A);
+
+    let cycles_before = machine.cycles;
+    for _ in 0..3 {
+        machine.step();
+    }
+    assert_eq!(machine.registers.accumulator, 0x0F);
+    assert_eq!(machine.cycles - cycles_before, 6);
+
+    // The undocumented instructions are the NMOS ones
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x42);
+    assert_eq!(machine.registers.index_x, 0x42);
+}
+
+#[test]
+fn zero_page_wraparound_test() {
+    let mut machine = Machine::new();
+
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xB5, 0xF0,       // LDA $F0,X
+        0xB6, 0xF0,       // LDX $F0,Y
+        0xA1, 0x80,       // LDA ($80,X)
+        0xB1, 0xFF,       // LDA ($FF),Y
+        0x95, 0xFF,       // STA $FF,X
+    ]);
+    machine.memory.set_byte(Address(0x0000), 0x30);
+    machine.memory.set_byte(Address(0x0010), 0x11);
+    machine.memory.set_byte(Address(0x0020), 0x22);
+    machine.memory.set_byte(Address(0x00FF), 0x10);
+    machine.memory.set_byte(Address(0x3010), 0x33);
+    machine.memory.set_byte(Address(0x3015), 0x44);
+    machine.registers.program_counter = Address(0x1000);
+
+    // $F0 + $20 stays in the zero page
+    machine.registers.index_x = 0x20;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x11);
+
+    machine.registers.index_y = 0x30;
+    machine.step();
+    assert_eq!(machine.registers.index_x, 0x22);
+
+    // The pointer at $FF takes its high byte from $00
+    machine.registers.index_x = 0x7F;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x33);
+
+    machine.registers.index_y = 0x05;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x44);
+
+    machine.registers.index_x = 0x02;
+    machine.step();
+    assert_eq!(machine.memory.get_byte(Address(0x0001)), 0x44);
+    assert_eq!(machine.memory.get_byte(Address(0x0101)), 0x00);
+}
+
+#[test]
+fn absolute_wraparound_test() {
+    let mut machine = Machine::new();
+
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xBD, 0xFF, 0xFF, // LDA $FFFF,X
+        0xB9, 0xF0, 0xFF, // LDA $FFF0,Y
+    ]);
+    machine.memory.set_byte(Address(0x0001), 0x55);
+    machine.memory.set_byte(Address(0x0010), 0x66);
+    machine.registers.program_counter = Address(0x1000);
+
+    machine.registers.index_x = 0x02;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x55);
+
+    machine.registers.index_y = 0x20;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x66);
+}
+
+#[test]
+fn indirect_jump_page_wraparound_test() {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0x1000), &[0x6C, 0xFF, 0x12]); // JMP ($12FF)
+    memory.set_byte(Address(0x12FF), 0x34);
+    memory.set_byte(Address(0x1200), 0x56);
+    memory.set_byte(Address(0x1300), 0x78);
+
+    // The NMOS 6502 (and the 2A03) fetch the high byte from $1200
+    for &variant in [Variant::Nmos, Variant::Ricoh2A03].iter() {
+        let mut machine = Machine::with_variant(memory, variant);
+        machine.registers.program_counter = Address(0x1000);
+        machine.step();
+        assert_eq!(machine.registers.program_counter, Address(0x5634));
+    }
+
+    // The 65C02 fixed it
+    let mut machine = Machine::with_variant(memory, Variant::Cmos);
+    machine.registers.program_counter = Address(0x1000);
+    machine.step();
+    assert_eq!(machine.registers.program_counter, Address(0x7834));
+}
+
+#[test]
+fn history_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xA2, 0x00,       // LDX #$00
+        0x8A,             // TXA
+        0x9D, 0x00, 0x20, // STA $2000,X
+        0x20, 0x00, 0x30, // JSR $3000
+        0xE8,             // INX
+        0xE0, 0x04,       // CPX #$04
+        0xD0, 0xF4,       // BNE $1002
+        0x02,             // JAM
+    ]);
+    machine.memory.set_byte(Address(0x3000), 0x60); // RTS
+    machine.memory.set_byte(Address(0x2002), 0xAA);
+    machine.registers.program_counter = Address(0x1000);
+    let registers = machine.registers;
+    let cycles = machine.cycles;
+
+    // Nothing is recorded until asked for
+    assert!(!machine.step_back());
+
+    machine.record_history(1000);
+    assert_eq!(machine.run(), StopReason::Jam(Address(0x100E)));
+    // 29 instructions, and then the JAM
+    assert_eq!(machine.history().unwrap().steps(), 30);
+    assert_eq!(machine.memory.get_byte(Address(0x2002)), 0x02);
+
+    // Rewinding all the way undoes the stores and the stack writes
+    assert!(machine.rewind_to(0));
+    assert_eq!(machine.registers, registers);
+    assert_eq!(machine.cycles, cycles);
+    assert_eq!(machine.memory.get_byte(Address(0x2002)), 0xAA);
+    assert_eq!(machine.memory.get_byte(Address(0x01FB)), 0x00);
+    assert!(!machine.step_back());
+    assert!(!machine.rewind_to(1));
+
+    // Run forwards, then back to a breakpoint
+    machine.run();
+    let breakpoints = [Address(0x1009)];
+    let at_inx = StopReason::Breakpoint(Address(0x1009));
+    assert_eq!(machine.run_backward(&breakpoints), at_inx);
+    assert_eq!(machine.registers.index_x, 3);
+    assert_eq!(machine.run_backward(&breakpoints), at_inx);
+    assert_eq!(machine.registers.index_x, 2);
+    assert_eq!(machine.memory.get_byte(Address(0x2003)), 0x00);
+
+    // Stepping forwards again from there records over what was undone
+    let steps = machine.history().unwrap().steps();
+    machine.step();
+    assert_eq!(machine.history().unwrap().steps(), steps + 1);
+
+    // Only the most recent steps are kept
+    machine.record_history(5);
+    machine.run();
+    assert_eq!(machine.history().unwrap().earliest_step(),
+               machine.history().unwrap().steps() - 5);
+    assert_eq!(machine.run_backward(&[]), StopReason::HistoryExhausted);
+    assert!(!machine.step_back());
+}
@@ -0,0 +1,1346 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// A 65C816 core. It shares the 6502's status flags, but has its own
+// instructions and addressing modes (`instruction816`), registers
+// (`Registers816`) and a 24-bit bus (`LongBus`).
+//
+// The processor starts out in emulation mode, where it behaves like a 65C02
+// with 8-bit registers and the stack in page 1. XCE switches to native mode,
+// where the M and X flags select 8- or 16-bit accumulator and memory
+// accesses, and index registers.
+
+use std;
+
+use address::{Address, AddressDiff, LongAddress};
+use bus::LongBus;
+use instruction816::{AddressingMode816, DecodedInstr816, Instruction816,
+                     Location, OpInput816, OPCODES_816};
+use machine::StopReason;
+use memory::{LongMemory, COP_VECTOR_LO, IRQ_INTERRUPT_VECTOR_LO,
+             NATIVE_BRK_VECTOR_LO, NATIVE_COP_VECTOR_LO,
+             NATIVE_IRQ_VECTOR_LO, NATIVE_NMI_VECTOR_LO,
+             NMI_INTERRUPT_VECTOR_LO, RESET_VECTOR_LO};
+use registers::{Registers816, Status, StatusArgs};
+use registers::{PS_NEGATIVE, PS_DECIMAL_MODE, PS_OVERFLOW, PS_ZERO, PS_CARRY,
+                PS_DISABLE_INTERRUPTS, PS_BRK, PS_MEMORY_8BIT,
+                PS_INDEX_8BIT};
+
+pub struct Machine816<B = LongMemory> {
+    pub registers: Registers816,
+    pub memory:    B,
+
+    // Number of clock cycles executed so far
+    pub cycles:    u64,
+
+    // Interrupt inputs, as on `Machine`
+    pub irq_line:    bool,
+    pub nmi_pending: bool,
+
+    // Set by WAI until an interrupt comes along, and by STP until the next
+    // reset
+    pub waiting:     bool,
+    pub stopped:     bool
+}
+
+impl Machine816 {
+    pub fn new() -> Machine816 {
+        Machine816::with_bus(LongMemory::new())
+    }
+}
+
+impl<B: LongBus> Machine816<B> {
+    // Creates a machine and puts it through the reset sequence, so the bus
+    // should already hold the reset vector.
+    pub fn with_bus(bus: B) -> Machine816<B> {
+        let mut machine = Machine816 {
+            registers:   Registers816::new(),
+            memory:      bus,
+            cycles:      0,
+            irq_line:    false,
+            nmi_pending: false,
+            waiting:     false,
+            stopped:     false
+        };
+        machine.reset();
+        machine
+    }
+
+    // Reset puts the processor back into emulation mode, with the banks and
+    // the direct page register cleared, and jumps through the reset vector.
+    pub fn reset(&mut self) {
+        self.registers.emulation = true;
+        self.registers.direct_page = 0;
+        self.registers.data_bank = 0;
+        self.registers.program_bank = 0;
+        self.registers.status.or(PS_DISABLE_INTERRUPTS);
+        self.registers.status.remove(PS_DECIMAL_MODE);
+        self.update_register_widths();
+        let sp = self.registers.stack_pointer.wrapping_sub(3);
+        self.registers.stack_pointer = self.wrap_stack_pointer(sp);
+
+        self.nmi_pending = false;
+        self.waiting = false;
+        self.stopped = false;
+
+        self.registers.program_counter = self.read_vector(RESET_VECTOR_LO);
+        self.cycles += 7;
+    }
+
+    pub fn assert_irq(&mut self) {
+        self.irq_line = true;
+    }
+
+    pub fn deassert_irq(&mut self) {
+        self.irq_line = false;
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Like `Machine::service_interrupts`, but using the native mode vectors
+    // in native mode.
+    pub fn service_interrupts(&mut self) -> bool {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.hardware_interrupt(NATIVE_NMI_VECTOR_LO,
+                                    NMI_INTERRUPT_VECTOR_LO);
+            true
+        } else if (self.irq_line || self.memory.irq_asserted())
+               && !self.registers.status.contains(PS_DISABLE_INTERRUPTS) {
+            self.hardware_interrupt(NATIVE_IRQ_VECTOR_LO,
+                                    IRQ_INTERRUPT_VECTOR_LO);
+            true
+        } else {
+            false
+        }
+    }
+
+    // In emulation mode, hardware interrupts push the status with B clear,
+    // as on the 6502. (In native mode that bit is the X flag.)
+    fn hardware_interrupt(&mut self, native_vector: Address,
+                          emulation_vector: Address) {
+        let status = if self.registers.emulation {
+            self.registers.status - PS_BRK
+        } else {
+            self.registers.status
+        };
+        self.interrupt(native_vector, emulation_vector, status);
+        self.cycles += 7;
+    }
+
+    // The interrupt sequence. Native mode also pushes the program bank, and
+    // takes an extra cycle for it.
+    fn interrupt(&mut self, native_vector: Address, emulation_vector: Address,
+                 status: Status) {
+        if !self.registers.emulation {
+            let bank = self.registers.program_bank;
+            self.push_byte(bank);
+            self.cycles += 1;
+        }
+        let return_addr = self.registers.program_counter.to_u16();
+        self.push_word(return_addr);
+        self.push_byte(status.bits());
+
+        self.registers.status.or(PS_DISABLE_INTERRUPTS);
+        self.registers.status.remove(PS_DECIMAL_MODE);
+        self.registers.program_bank = 0;
+
+        let vector = if self.registers.emulation { emulation_vector }
+                     else { native_vector };
+        self.registers.program_counter = self.read_vector(vector);
+    }
+
+    fn read_vector(&mut self, vector: Address) -> Address {
+        let lo = self.memory.get_byte(LongAddress::new(0, vector)) as u16;
+        let hi = self.memory.get_byte(LongAddress::new(0, vector
+                                                          + AddressDiff(1)));
+        Address(((hi as u16) << 8) | lo)
+    }
+
+    // Whether the accumulator and memory accesses are 16 bits wide
+    pub fn wide_memory(&self) -> bool {
+        !self.registers.status.contains(PS_MEMORY_8BIT)
+    }
+
+    // Whether the index registers are 16 bits wide
+    pub fn wide_index(&self) -> bool {
+        !self.registers.status.contains(PS_INDEX_8BIT)
+    }
+
+    // The address of a direct page operand. In emulation mode with the direct
+    // page register on a page boundary, indexing wraps around within the
+    // page, like on the 6502.
+    pub fn direct_address(&self, offset: u8, index: u16) -> Address {
+        let direct_page = self.registers.direct_page;
+        if self.registers.emulation && (direct_page & 0xFF) == 0 {
+            let offset = (offset as u16).wrapping_add(index) & 0xFF;
+            Address((direct_page & 0xFF00) | offset)
+        } else {
+            Address(direct_page.wrapping_add(offset as u16)
+                               .wrapping_add(index))
+        }
+    }
+
+    pub fn fetch_next_and_decode(&mut self) -> DecodedInstr816 {
+        let pc = self.registers.program_counter;
+        let bank = self.registers.program_bank;
+        let opcode = self.memory.get_byte(LongAddress::new(bank, pc));
+        let (instr, am, cycles) = OPCODES_816[opcode as usize];
+
+        let wide_memory = self.wide_memory();
+        let wide_index = self.wide_index();
+        let AddressDiff(len) = am.extra_bytes(wide_memory, wide_index);
+
+        // The program counter wraps around within the program bank
+        let mut data = [0u8; 3];
+        for i in 0..(len as usize) {
+            let address = pc + AddressDiff(1 + i as i32);
+            data[i] = self.memory.get_byte(LongAddress::new(bank, address));
+        }
+        let slice = &data[..(len as usize)];
+
+        self.cycles += cycles as u64;
+        if am.is_direct_page() && (self.registers.direct_page & 0xFF) != 0 {
+            self.cycles += 1;
+        }
+        if instr.has_page_crossing_penalty()
+           && am.has_index_penalty(self, slice) {
+            self.cycles += 1;
+        }
+        match am {
+            AddressingMode816::ImmediateM if wide_memory => self.cycles += 1,
+            AddressingMode816::ImmediateX if wide_index => self.cycles += 1,
+            _ => {}
+        }
+
+        let am_out = am.process(self, slice);
+        self.registers.program_counter = pc + AddressDiff(1 + len);
+
+        (instr, am_out)
+    }
+
+    pub fn execute_instruction(&mut self, decoded_instr: DecodedInstr816) {
+        let wide_memory = self.wide_memory();
+        let wide_index = self.wide_index();
+
+        match decoded_instr {
+            (Instruction816::ADC, OpInput816::UseImmediate(val)) => {
+                self.add_with_carry(val);
+            }
+            (Instruction816::ADC, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                self.add_with_carry(val);
+            }
+            (Instruction816::SBC, OpInput816::UseImmediate(val)) => {
+                self.subtract_with_carry(val);
+            }
+            (Instruction816::SBC, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                self.subtract_with_carry(val);
+            }
+
+            (Instruction816::AND, OpInput816::UseImmediate(val)) => {
+                let a = self.get_accumulator();
+                self.load_accumulator(a & val);
+            }
+            (Instruction816::AND, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let a = self.get_accumulator();
+                self.load_accumulator(a & val);
+            }
+            (Instruction816::EOR, OpInput816::UseImmediate(val)) => {
+                let a = self.get_accumulator();
+                self.load_accumulator(a ^ val);
+            }
+            (Instruction816::EOR, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let a = self.get_accumulator();
+                self.load_accumulator(a ^ val);
+            }
+            (Instruction816::ORA, OpInput816::UseImmediate(val)) => {
+                let a = self.get_accumulator();
+                self.load_accumulator(a | val);
+            }
+            (Instruction816::ORA, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let a = self.get_accumulator();
+                self.load_accumulator(a | val);
+            }
+
+            (Instruction816::BIT, OpInput816::UseImmediate(val)) => {
+                // As on the 65C02, BIT #imm only sets Z
+                let is_zero = (self.get_accumulator() & val) == 0;
+                self.registers.status.set_with_mask(
+                    PS_ZERO,
+                    Status::new(StatusArgs { zero: is_zero,
+                                             ..StatusArgs::none() } ));
+            }
+            (Instruction816::BIT, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let sign = Machine816::<B>::sign_bit(wide_memory);
+                let is_zero = (self.get_accumulator() & val) == 0;
+                self.registers.status.set_with_mask(
+                    PS_ZERO | PS_NEGATIVE | PS_OVERFLOW,
+                    Status::new(StatusArgs { zero: is_zero,
+                                             negative: (val & sign) != 0,
+                                             overflow: (val & (sign >> 1))
+                                                       != 0,
+                                             ..StatusArgs::none() } ));
+            }
+
+            (Instruction816::CMP, OpInput816::UseImmediate(val)) => {
+                let a = self.get_accumulator();
+                self.compare(a, val, wide_memory);
+            }
+            (Instruction816::CMP, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let a = self.get_accumulator();
+                self.compare(a, val, wide_memory);
+            }
+            (Instruction816::CPX, OpInput816::UseImmediate(val)) => {
+                let x = self.registers.index_x;
+                self.compare(x, val, wide_index);
+            }
+            (Instruction816::CPX, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_index);
+                let x = self.registers.index_x;
+                self.compare(x, val, wide_index);
+            }
+            (Instruction816::CPY, OpInput816::UseImmediate(val)) => {
+                let y = self.registers.index_y;
+                self.compare(y, val, wide_index);
+            }
+            (Instruction816::CPY, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_index);
+                let y = self.registers.index_y;
+                self.compare(y, val, wide_index);
+            }
+
+            (Instruction816::LDA, OpInput816::UseImmediate(val)) => {
+                self.load_accumulator(val);
+            }
+            (Instruction816::LDA, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                self.load_accumulator(val);
+            }
+            (Instruction816::LDX, OpInput816::UseImmediate(val)) => {
+                self.load_x_register(val);
+            }
+            (Instruction816::LDX, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_index);
+                self.load_x_register(val);
+            }
+            (Instruction816::LDY, OpInput816::UseImmediate(val)) => {
+                self.load_y_register(val);
+            }
+            (Instruction816::LDY, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_index);
+                self.load_y_register(val);
+            }
+
+            (Instruction816::STA, OpInput816::UseAddress(location)) => {
+                let a = self.registers.accumulator;
+                self.write_data(location, wide_memory, a);
+            }
+            (Instruction816::STX, OpInput816::UseAddress(location)) => {
+                let x = self.registers.index_x;
+                self.write_data(location, wide_index, x);
+            }
+            (Instruction816::STY, OpInput816::UseAddress(location)) => {
+                let y = self.registers.index_y;
+                self.write_data(location, wide_index, y);
+            }
+            (Instruction816::STZ, OpInput816::UseAddress(location)) => {
+                self.write_data(location, wide_memory, 0);
+            }
+
+            (Instruction816::ASL, OpInput816::UseImplied) |
+            (Instruction816::LSR, OpInput816::UseImplied) |
+            (Instruction816::ROL, OpInput816::UseImplied) |
+            (Instruction816::ROR, OpInput816::UseImplied) |
+            (Instruction816::INC, OpInput816::UseImplied) |
+            (Instruction816::DEC, OpInput816::UseImplied) => {
+                // Accumulator mode
+                let a = self.get_accumulator();
+                let val = self.modify(decoded_instr.0, a, wide_memory);
+                self.set_accumulator(val);
+            }
+            (Instruction816::ASL, OpInput816::UseAddress(location)) |
+            (Instruction816::LSR, OpInput816::UseAddress(location)) |
+            (Instruction816::ROL, OpInput816::UseAddress(location)) |
+            (Instruction816::ROR, OpInput816::UseAddress(location)) |
+            (Instruction816::INC, OpInput816::UseAddress(location)) |
+            (Instruction816::DEC, OpInput816::UseAddress(location)) => {
+                let val = self.read_data(location, wide_memory);
+                let val = self.modify(decoded_instr.0, val, wide_memory);
+                self.write_data(location, wide_memory, val);
+            }
+
+            (Instruction816::TRB, OpInput816::UseAddress(location)) |
+            (Instruction816::TSB, OpInput816::UseAddress(location)) => {
+                let a = self.get_accumulator();
+                let val = self.read_data(location, wide_memory);
+                self.registers.status.set_with_mask(
+                    PS_ZERO,
+                    Status::new(StatusArgs { zero: (a & val) == 0,
+                                             ..StatusArgs::none() } ));
+                let val = if decoded_instr.0 == Instruction816::TSB {
+                    val | a
+                } else {
+                    val & !a
+                };
+                self.write_data(location, wide_memory, val);
+            }
+
+            (Instruction816::DEX, OpInput816::UseImplied) => {
+                let x = self.registers.index_x.wrapping_sub(1);
+                self.load_x_register(x);
+            }
+            (Instruction816::DEY, OpInput816::UseImplied) => {
+                let y = self.registers.index_y.wrapping_sub(1);
+                self.load_y_register(y);
+            }
+            (Instruction816::INX, OpInput816::UseImplied) => {
+                let x = self.registers.index_x.wrapping_add(1);
+                self.load_x_register(x);
+            }
+            (Instruction816::INY, OpInput816::UseImplied) => {
+                let y = self.registers.index_y.wrapping_add(1);
+                self.load_y_register(y);
+            }
+
+            (Instruction816::BRL, OpInput816::UseRelative(rel)) => {
+                let pc = self.registers.program_counter.to_u16();
+                self.registers.program_counter =
+                    Address(pc.wrapping_add(rel as u16));
+            }
+            (instr, OpInput816::UseRelative(rel))
+                if instr != Instruction816::PER => {
+                let status = self.registers.status;
+                let taken = match instr {
+                    Instruction816::BCC => !status.contains(PS_CARRY),
+                    Instruction816::BCS => status.contains(PS_CARRY),
+                    Instruction816::BEQ => status.contains(PS_ZERO),
+                    Instruction816::BMI => status.contains(PS_NEGATIVE),
+                    Instruction816::BNE => !status.contains(PS_ZERO),
+                    Instruction816::BPL => !status.contains(PS_NEGATIVE),
+                    Instruction816::BVC => !status.contains(PS_OVERFLOW),
+                    Instruction816::BVS => status.contains(PS_OVERFLOW),
+                    _ => true,
+                };
+                if taken {
+                    self.branch(rel);
+                }
+            }
+
+            (Instruction816::BRK, OpInput816::UseImmediate(_)) => {
+                // The signature byte has already been skipped. In emulation
+                // mode B is set in the pushed status, as on the 6502.
+                let status = self.registers.status;
+                self.interrupt(NATIVE_BRK_VECTOR_LO, IRQ_INTERRUPT_VECTOR_LO,
+                               status);
+            }
+            (Instruction816::COP, OpInput816::UseImmediate(_)) => {
+                let status = self.registers.status;
+                self.interrupt(NATIVE_COP_VECTOR_LO, COP_VECTOR_LO, status);
+            }
+            (Instruction816::RTI, OpInput816::UseImplied) => {
+                let status = self.pull_byte();
+                self.set_status(status);
+                let pc = self.pull_word();
+                self.registers.program_counter = Address(pc);
+                if !self.registers.emulation {
+                    self.registers.program_bank = self.pull_byte();
+                    self.cycles += 1;
+                }
+            }
+
+            (Instruction816::CLC, OpInput816::UseImplied) => {
+                self.registers.status.remove(PS_CARRY);
+            }
+            (Instruction816::CLD, OpInput816::UseImplied) => {
+                self.registers.status.remove(PS_DECIMAL_MODE);
+            }
+            (Instruction816::CLI, OpInput816::UseImplied) => {
+                self.registers.status.remove(PS_DISABLE_INTERRUPTS);
+            }
+            (Instruction816::CLV, OpInput816::UseImplied) => {
+                self.registers.status.remove(PS_OVERFLOW);
+            }
+            (Instruction816::SEC, OpInput816::UseImplied) => {
+                self.registers.status.or(PS_CARRY);
+            }
+            (Instruction816::SED, OpInput816::UseImplied) => {
+                self.registers.status.or(PS_DECIMAL_MODE);
+            }
+            (Instruction816::SEI, OpInput816::UseImplied) => {
+                self.registers.status.or(PS_DISABLE_INTERRUPTS);
+            }
+            (Instruction816::REP, OpInput816::UseImmediate(val)) => {
+                let status = self.registers.status.bits() & !(val as u8);
+                self.set_status(status);
+            }
+            (Instruction816::SEP, OpInput816::UseImmediate(val)) => {
+                let status = self.registers.status.bits() | val as u8;
+                self.set_status(status);
+            }
+
+            (Instruction816::JMP, OpInput816::UseAddress(location)) => {
+                self.registers.program_counter = location.get_address();
+            }
+            (Instruction816::JML, OpInput816::UseAddress(location)) => {
+                self.jump_long(location.byte(0));
+            }
+            (Instruction816::JSR, OpInput816::UseAddress(location)) => {
+                // As on the 6502, the address pushed is that of the last
+                // byte of the instruction.
+                let pc = self.registers.program_counter.to_u16();
+                let return_addr = pc.wrapping_sub(1);
+                self.push_word(return_addr);
+                self.registers.program_counter = location.get_address();
+            }
+            (Instruction816::JSL, OpInput816::UseAddress(location)) => {
+                let bank = self.registers.program_bank;
+                self.push_byte(bank);
+                let pc = self.registers.program_counter.to_u16();
+                let return_addr = pc.wrapping_sub(1);
+                self.push_word(return_addr);
+                self.jump_long(location.byte(0));
+            }
+            (Instruction816::RTS, OpInput816::UseImplied) => {
+                let pc = self.pull_word().wrapping_add(1);
+                self.registers.program_counter = Address(pc);
+            }
+            (Instruction816::RTL, OpInput816::UseImplied) => {
+                let pc = self.pull_word().wrapping_add(1);
+                self.registers.program_counter = Address(pc);
+                self.registers.program_bank = self.pull_byte();
+            }
+
+            (Instruction816::MVN, OpInput816::UseBlockMove(dest, src)) |
+            (Instruction816::MVP, OpInput816::UseBlockMove(dest, src)) => {
+                // Moves one byte, and then runs again until the count in C
+                // runs out, so that interrupts can be taken in between.
+                let x = self.registers.index_x;
+                let y = self.registers.index_y;
+                let val = self.memory.get_byte(LongAddress::new(src,
+                                                                Address(x)));
+                self.memory.set_byte(LongAddress::new(dest, Address(y)), val);
+                self.registers.data_bank = dest;
+
+                let step: u16 = if decoded_instr.0 == Instruction816::MVN { 1 }
+                                else { 0xFFFF };
+                self.set_x_register(x.wrapping_add(step));
+                self.set_y_register(y.wrapping_add(step));
+                self.registers.accumulator =
+                    self.registers.accumulator.wrapping_sub(1);
+                if self.registers.accumulator != 0xFFFF {
+                    self.registers.program_counter =
+                        self.registers.program_counter + AddressDiff(-3);
+                }
+            }
+
+            (Instruction816::NOP, OpInput816::UseImplied) => {}
+            (Instruction816::WDM, OpInput816::UseImmediate(_)) => {}
+
+            (Instruction816::PEA, OpInput816::UseAddress(location)) |
+            (Instruction816::PEI, OpInput816::UseAddress(location)) => {
+                // These push the operand address itself
+                let val = location.get_address().to_u16();
+                self.push_word(val);
+            }
+            (Instruction816::PER, OpInput816::UseRelative(rel)) => {
+                let pc = self.registers.program_counter.to_u16();
+                self.push_word(pc.wrapping_add(rel as u16));
+            }
+
+            (Instruction816::PHA, OpInput816::UseImplied) => {
+                let a = self.registers.accumulator;
+                self.push_sized(a, wide_memory);
+            }
+            (Instruction816::PHX, OpInput816::UseImplied) => {
+                let x = self.registers.index_x;
+                self.push_sized(x, wide_index);
+            }
+            (Instruction816::PHY, OpInput816::UseImplied) => {
+                let y = self.registers.index_y;
+                self.push_sized(y, wide_index);
+            }
+            (Instruction816::PHB, OpInput816::UseImplied) => {
+                let bank = self.registers.data_bank;
+                self.push_byte(bank);
+            }
+            (Instruction816::PHD, OpInput816::UseImplied) => {
+                let direct_page = self.registers.direct_page;
+                self.push_word(direct_page);
+            }
+            (Instruction816::PHK, OpInput816::UseImplied) => {
+                let bank = self.registers.program_bank;
+                self.push_byte(bank);
+            }
+            (Instruction816::PHP, OpInput816::UseImplied) => {
+                // In emulation mode the M and X bits are the 6502's B and
+                // unused bits, which are always pushed as set.
+                let status = self.registers.status.bits();
+                self.push_byte(status);
+            }
+
+            (Instruction816::PLA, OpInput816::UseImplied) => {
+                let val = self.pull_sized(wide_memory);
+                self.load_accumulator(val);
+            }
+            (Instruction816::PLX, OpInput816::UseImplied) => {
+                let val = self.pull_sized(wide_index);
+                self.load_x_register(val);
+            }
+            (Instruction816::PLY, OpInput816::UseImplied) => {
+                let val = self.pull_sized(wide_index);
+                self.load_y_register(val);
+            }
+            (Instruction816::PLB, OpInput816::UseImplied) => {
+                let val = self.pull_byte();
+                self.registers.data_bank = val;
+                self.set_flags(val as u16, false);
+            }
+            (Instruction816::PLD, OpInput816::UseImplied) => {
+                let val = self.pull_word();
+                self.registers.direct_page = val;
+                self.set_flags(val, true);
+            }
+            (Instruction816::PLP, OpInput816::UseImplied) => {
+                let status = self.pull_byte();
+                self.set_status(status);
+            }
+
+            (Instruction816::TAX, OpInput816::UseImplied) => {
+                let a = self.registers.accumulator;
+                self.load_x_register(a);
+            }
+            (Instruction816::TAY, OpInput816::UseImplied) => {
+                let a = self.registers.accumulator;
+                self.load_y_register(a);
+            }
+            (Instruction816::TXA, OpInput816::UseImplied) => {
+                let x = self.registers.index_x;
+                self.load_accumulator(x);
+            }
+            (Instruction816::TYA, OpInput816::UseImplied) => {
+                let y = self.registers.index_y;
+                self.load_accumulator(y);
+            }
+            (Instruction816::TXY, OpInput816::UseImplied) => {
+                let x = self.registers.index_x;
+                self.load_y_register(x);
+            }
+            (Instruction816::TYX, OpInput816::UseImplied) => {
+                let y = self.registers.index_y;
+                self.load_x_register(y);
+            }
+            (Instruction816::TSX, OpInput816::UseImplied) => {
+                let sp = self.registers.stack_pointer;
+                self.load_x_register(sp);
+            }
+            (Instruction816::TXS, OpInput816::UseImplied) => {
+                let x = self.registers.index_x;
+                self.registers.stack_pointer = self.wrap_stack_pointer(x);
+            }
+            (Instruction816::TCS, OpInput816::UseImplied) => {
+                let c = self.registers.accumulator;
+                self.registers.stack_pointer = self.wrap_stack_pointer(c);
+            }
+            (Instruction816::TSC, OpInput816::UseImplied) => {
+                let sp = self.registers.stack_pointer;
+                self.registers.accumulator = sp;
+                self.set_flags(sp, true);
+            }
+            (Instruction816::TCD, OpInput816::UseImplied) => {
+                let c = self.registers.accumulator;
+                self.registers.direct_page = c;
+                self.set_flags(c, true);
+            }
+            (Instruction816::TDC, OpInput816::UseImplied) => {
+                let direct_page = self.registers.direct_page;
+                self.registers.accumulator = direct_page;
+                self.set_flags(direct_page, true);
+            }
+            (Instruction816::XBA, OpInput816::UseImplied) => {
+                let c = self.registers.accumulator;
+                let c = (c << 8) | (c >> 8);
+                self.registers.accumulator = c;
+                self.set_flags(c, false);
+            }
+            (Instruction816::XCE, OpInput816::UseImplied) => {
+                let carry = self.registers.status.contains(PS_CARRY);
+                let emulation = self.registers.emulation;
+                self.registers.status.set_with_mask(
+                    PS_CARRY,
+                    Status::new(StatusArgs { carry: emulation,
+                                             ..StatusArgs::none() } ));
+                self.registers.emulation = carry;
+                self.update_register_widths();
+                let sp = self.registers.stack_pointer;
+                self.registers.stack_pointer = self.wrap_stack_pointer(sp);
+            }
+
+            (Instruction816::STP, OpInput816::UseImplied) => {
+                self.stopped = true;
+            }
+            (Instruction816::WAI, OpInput816::UseImplied) => {
+                self.waiting = true;
+            }
+
+            (_, _) => {
+                debug!("attempting to execute unimplemented or invalid \
+                        instruction");
+            }
+        }
+    }
+
+    // Services any pending interrupt and then runs one instruction. Every
+    // opcode is valid, so as on the 65C02 the only thing that stops it is
+    // STP, and a processor waiting after WAI lets one cycle go by at a time
+    // until an interrupt wakes it up.
+    pub fn step(&mut self) -> Option<StopReason> {
+        if self.stopped {
+            return Some(StopReason::Stp);
+        }
+
+        if self.waiting {
+            if self.nmi_pending || self.irq_line || self.memory.irq_asserted() {
+                self.waiting = false;
+            } else {
+                self.cycles += 1;
+                self.memory.tick(1);
+                return None;
+            }
+        }
+
+        let cycles_before = self.cycles;
+        self.service_interrupts();
+        let decoded_instr = self.fetch_next_and_decode();
+        self.execute_instruction(decoded_instr);
+        let elapsed = self.cycles - cycles_before;
+        self.memory.tick(elapsed);
+        if self.stopped { Some(StopReason::Stp) } else { None }
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+        }
+    }
+
+    fn sign_bit(wide: bool) -> u16 {
+        if wide { 0x8000 } else { 0x80 }
+    }
+
+    fn mask(wide: bool) -> u16 {
+        if wide { 0xFFFF } else { 0xFF }
+    }
+
+    // Sets N and Z from an 8- or 16-bit value
+    fn set_flags(&mut self, value: u16, wide: bool) {
+        let value = value & Machine816::<B>::mask(wide);
+        let is_negative = (value & Machine816::<B>::sign_bit(wide)) != 0;
+        self.registers.status.set_with_mask(
+            PS_ZERO | PS_NEGATIVE,
+            Status::new(StatusArgs { zero: value == 0,
+                                     negative: is_negative,
+                                     ..StatusArgs::none() } ));
+    }
+
+    fn set_carry_and_overflow(&mut self, carry: bool, overflow: bool) {
+        self.registers.status.set_with_mask(
+            PS_CARRY | PS_OVERFLOW,
+            Status::new(StatusArgs { carry: carry,
+                                     overflow: overflow,
+                                     ..StatusArgs::none() } ));
+    }
+
+    // Sets the status register, e.g. from REP, SEP or PLP. In emulation mode
+    // M and X stay set.
+    fn set_status(&mut self, bits: u8) {
+        self.registers.status = Status::from_bits_truncate(bits);
+        self.update_register_widths();
+    }
+
+    // Enforces the register widths after the mode or the M and X flags have
+    // changed: emulation mode forces 8-bit registers, and the high bytes of
+    // the index registers are cleared when they become 8 bits wide.
+    fn update_register_widths(&mut self) {
+        if self.registers.emulation {
+            self.registers.status.or(PS_MEMORY_8BIT | PS_INDEX_8BIT);
+        }
+        if !self.wide_index() {
+            self.registers.index_x &= 0xFF;
+            self.registers.index_y &= 0xFF;
+        }
+    }
+
+    // The part of the accumulator in use: A, or all of C
+    fn get_accumulator(&self) -> u16 {
+        self.registers.accumulator
+            & Machine816::<B>::mask(self.wide_memory())
+    }
+
+    // With an 8-bit accumulator, the high byte (B) is left alone
+    fn set_accumulator(&mut self, value: u16) {
+        if self.wide_memory() {
+            self.registers.accumulator = value;
+        } else {
+            self.registers.accumulator = (self.registers.accumulator & 0xFF00)
+                                       | (value & 0xFF);
+        }
+    }
+
+    fn load_accumulator(&mut self, value: u16) {
+        let wide = self.wide_memory();
+        self.set_accumulator(value);
+        self.set_flags(value, wide);
+    }
+
+    fn set_x_register(&mut self, value: u16) {
+        self.registers.index_x = value
+                               & Machine816::<B>::mask(self.wide_index());
+    }
+
+    fn set_y_register(&mut self, value: u16) {
+        self.registers.index_y = value
+                               & Machine816::<B>::mask(self.wide_index());
+    }
+
+    fn load_x_register(&mut self, value: u16) {
+        let wide = self.wide_index();
+        self.set_x_register(value);
+        self.set_flags(value, wide);
+    }
+
+    fn load_y_register(&mut self, value: u16) {
+        let wide = self.wide_index();
+        self.set_y_register(value);
+        self.set_flags(value, wide);
+    }
+
+    // Reads an 8- or 16-bit operand. The second byte costs an extra cycle.
+    fn read_data(&mut self, location: Location, wide: bool) -> u16 {
+        let lo = self.memory.get_byte(location.byte(0)) as u16;
+        if wide {
+            self.cycles += 1;
+            let hi = self.memory.get_byte(location.byte(1)) as u16;
+            lo | (hi << 8)
+        } else {
+            lo
+        }
+    }
+
+    fn write_data(&mut self, location: Location, wide: bool, value: u16) {
+        self.memory.set_byte(location.byte(0), value as u8);
+        if wide {
+            self.cycles += 1;
+            self.memory.set_byte(location.byte(1), (value >> 8) as u8);
+        }
+    }
+
+    // The shifts, rotates, increments and decrements
+    fn modify(&mut self, instr: Instruction816, value: u16, wide: bool) -> u16 {
+        let sign = Machine816::<B>::sign_bit(wide);
+        let mask = Machine816::<B>::mask(wide);
+        let carry_in = self.registers.status.contains(PS_CARRY);
+
+        let carry_bits = |bit: u16| if carry_in { bit } else { 0 };
+        let (result, carry) = match instr {
+            Instruction816::ASL => (value << 1, (value & sign) != 0),
+            Instruction816::LSR => (value >> 1, (value & 1) != 0),
+            Instruction816::ROL => ((value << 1) | carry_bits(1),
+                                    (value & sign) != 0),
+            Instruction816::ROR => ((value >> 1) | carry_bits(sign),
+                                    (value & 1) != 0),
+            Instruction816::INC => (value.wrapping_add(1), carry_in),
+            _                   => (value.wrapping_sub(1), carry_in),
+        };
+        let result = result & mask;
+
+        self.registers.status.set_with_mask(
+            PS_CARRY,
+            Status::new(StatusArgs { carry: carry, ..StatusArgs::none() } ));
+        self.set_flags(result, wide);
+        result
+    }
+
+    fn compare(&mut self, register: u16, value: u16, wide: bool) {
+        let mask = Machine816::<B>::mask(wide);
+        let register = register & mask;
+        let value = value & mask;
+        self.registers.status.set_with_mask(
+            PS_CARRY,
+            Status::new(StatusArgs { carry: register >= value,
+                                     ..StatusArgs::none() } ));
+        self.set_flags(register.wrapping_sub(value), wide);
+    }
+
+    fn add_with_carry(&mut self, value: u16) {
+        let wide = self.wide_memory();
+        let value = value & Machine816::<B>::mask(wide);
+        self.add(value, wide, false);
+    }
+
+    // A - M - (1 - C) is A + !M + C, in both binary and decimal mode
+    fn subtract_with_carry(&mut self, value: u16) {
+        let wide = self.wide_memory();
+        let value = !value & Machine816::<B>::mask(wide);
+        self.add(value, wide, true);
+    }
+
+    fn add(&mut self, value: u16, wide: bool, subtract: bool) {
+        let a = self.get_accumulator();
+        let carry = self.registers.status.contains(PS_CARRY);
+
+        let (result, did_carry, did_overflow) =
+            if self.registers.status.contains(PS_DECIMAL_MODE) {
+                let digits = if wide { 4 } else { 2 };
+                Machine816::<B>::decimal_add(a, value, carry, digits, subtract)
+            } else {
+                let mask = Machine816::<B>::mask(wide);
+                let sign = Machine816::<B>::sign_bit(wide);
+                let sum = a as u32 + value as u32 + if carry { 1 } else { 0 };
+                let result = (sum as u16) & mask;
+                let did_overflow = (!(a ^ value) & (a ^ result) & sign) != 0;
+                (result, sum > mask as u32, did_overflow)
+            };
+
+        self.set_carry_and_overflow(did_carry, did_overflow);
+        self.load_accumulator(result);
+    }
+
+    // Adds a digit at a time, adjusting each digit of the result as it goes.
+    // For subtraction `b` has already been complemented, so a digit that
+    // didn't carry needs adjusting instead. V comes from the result before
+    // the top digit is adjusted.
+    fn decimal_add(a: u16, b: u16, carry: bool, digits: u32, subtract: bool)
+        -> (u16, bool, bool)
+    {
+        let mut result: i32 = 0;
+        let mut carry: i32 = if carry { 1 } else { 0 };
+        let mut did_overflow = false;
+
+        for i in 0..digits {
+            let shift = 4 * i;
+            let mut digit = ((a >> shift) & 0xF) as i32
+                          + ((b >> shift) & 0xF) as i32 + carry;
+
+            if i == digits - 1 {
+                let unadjusted = (result | (digit << shift)) as u16;
+                let sign = 1 << (shift + 3);
+                did_overflow = (!(a ^ b) & (a ^ unadjusted) & sign) != 0;
+            }
+
+            if subtract {
+                if digit <= 0xF {
+                    digit -= 0x6;
+                }
+            } else if digit > 0x9 {
+                digit += 0x6;
+            }
+
+            carry = if digit > 0xF { 1 } else { 0 };
+            result |= (digit & 0xF) << shift;
+        }
+
+        (result as u16, carry != 0, did_overflow)
+    }
+
+    fn branch(&mut self, rel: i16) {
+        let pc = self.registers.program_counter;
+        let target = Address(pc.to_u16().wrapping_add(rel as u16));
+        self.cycles += 1;
+        if self.registers.emulation
+           && pc.get_page_number() != target.get_page_number() {
+            self.cycles += 1;
+        }
+        self.registers.program_counter = target;
+    }
+
+    fn jump_long(&mut self, address: LongAddress) {
+        self.registers.program_bank = address.get_bank();
+        self.registers.program_counter = address.get_address();
+    }
+
+    // In emulation mode the stack is confined to page 1
+    fn wrap_stack_pointer(&self, sp: u16) -> u16 {
+        if self.registers.emulation { 0x0100 | (sp & 0xFF) } else { sp }
+    }
+
+    fn push_byte(&mut self, value: u8) {
+        let sp = self.registers.stack_pointer;
+        self.memory.set_byte(LongAddress(sp as u32), value);
+        self.registers.stack_pointer =
+            self.wrap_stack_pointer(sp.wrapping_sub(1));
+    }
+
+    fn pull_byte(&mut self) -> u8 {
+        let sp = self.registers.stack_pointer;
+        let sp = self.wrap_stack_pointer(sp.wrapping_add(1));
+        self.registers.stack_pointer = sp;
+        self.memory.get_byte(LongAddress(sp as u32))
+    }
+
+    fn push_word(&mut self, value: u16) {
+        self.push_byte((value >> 8) as u8);
+        self.push_byte(value as u8);
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        let lo = self.pull_byte() as u16;
+        let hi = self.pull_byte() as u16;
+        (hi << 8) | lo
+    }
+
+    // Pushes a register, taking an extra cycle if it's 16 bits wide
+    fn push_sized(&mut self, value: u16, wide: bool) {
+        if wide {
+            self.cycles += 1;
+            self.push_word(value);
+        } else {
+            self.push_byte(value as u8);
+        }
+    }
+
+    fn pull_sized(&mut self, wide: bool) -> u16 {
+        if wide {
+            self.cycles += 1;
+            self.pull_word()
+        } else {
+            self.pull_byte() as u16
+        }
+    }
+}
+
+impl<B> std::fmt::Debug for Machine816<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let regs = &self.registers;
+        let flags = [(PS_NEGATIVE, 'N'), (PS_OVERFLOW, 'V'),
+                     (PS_MEMORY_8BIT, 'M'), (PS_INDEX_8BIT, 'X'),
+                     (PS_DECIMAL_MODE, 'D'), (PS_DISABLE_INTERRUPTS, 'I'),
+                     (PS_ZERO, 'Z'), (PS_CARRY, 'C')];
+        let flags: String = flags.iter()
+                                 .map(|&(flag, c)| {
+                                     if regs.status.contains(flag) { c }
+                                     else { '.' }
+                                 })
+                                 .collect();
+        write!(f, "Machine Dump:\n\n\
+                   C: ${:04X}  X: ${:04X}  Y: ${:04X}  \
+                   S: ${:04X}  D: ${:04X}\n\
+                   PC: ${:02X}:{:04X}  DBR: ${:02X}  {}\n\
+                   P: ${:02X}  {}\n\
+                   Cycles: {}",
+               regs.accumulator, regs.index_x, regs.index_y,
+               regs.stack_pointer, regs.direct_page, regs.program_bank,
+               regs.program_counter.to_u16(), regs.data_bank,
+               if regs.emulation { "emulation" } else { "native" },
+               regs.status.bits(), flags, self.cycles)
+    }
+}
+
+// Creates a machine that starts running `program` at $8000 in bank 0
+#[cfg(test)]
+fn machine816_with_program(program: &[u8]) -> Machine816 {
+    let mut memory = LongMemory::new();
+    memory.set_bytes(LongAddress(0x8000), program);
+    memory.set_bytes(LongAddress(0xFFFC), &[0x00, 0x80]);
+    Machine816::with_bus(memory)
+}
+
+#[test]
+fn native_mode_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xC2, 0x30,       // REP #$30
+        0xA9, 0x34, 0x12, // LDA #$1234
+        0xA2, 0xCD, 0xAB, // LDX #$ABCD
+        0xA8,             // TAY
+        0x8D, 0x00, 0x20, // STA $2000
+        0xE2, 0x30,       // SEP #$30
+        0xA9, 0xFF,       // LDA #$FF
+        0x38,             // SEC
+        0xFB,             // XCE
+        0xDB,             // STP
+    ]);
+
+    for _ in 0..3 {
+        machine.step();
+    }
+    assert!(!machine.registers.emulation);
+    assert!(machine.registers.status.contains(PS_CARRY));
+    assert!(machine.wide_memory());
+    assert!(machine.wide_index());
+
+    // 16-bit immediates and stores take an extra cycle
+    let cycles = machine.cycles;
+    machine.step();
+    assert_eq!(machine.registers.accumulator, 0x1234);
+    assert_eq!(machine.cycles - cycles, 3);
+
+    machine.step();
+    machine.step();
+    assert_eq!(machine.registers.index_x, 0xABCD);
+    assert_eq!(machine.registers.index_y, 0x1234);
+    assert!(!machine.registers.status.contains(PS_NEGATIVE));
+
+    let cycles = machine.cycles;
+    machine.step();
+    assert_eq!(machine.memory.get_byte(LongAddress(0x2000)), 0x34);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x2001)), 0x12);
+    assert_eq!(machine.cycles - cycles, 5);
+
+    // Narrowing the index registers clears their high bytes, but the
+    // accumulator's high byte is kept
+    machine.step();
+    machine.step();
+    assert_eq!(machine.registers.index_x, 0xCD);
+    assert_eq!(machine.registers.index_y, 0x34);
+    assert_eq!(machine.registers.accumulator, 0x12FF);
+
+    assert_eq!(machine.run(), StopReason::Stp);
+    assert!(machine.registers.emulation);
+    assert!(!machine.registers.status.contains(PS_CARRY));
+    assert_eq!(machine.registers.stack_pointer & 0xFF00, 0x0100);
+}
+
+#[test]
+fn long_addressing_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,                   // CLC
+        0xFB,                   // XCE
+        0xC2, 0x20,             // REP #$20
+        0xA9, 0xEF, 0xBE,       // LDA #$BEEF
+        0x8F, 0x00, 0x10, 0x7E, // STA $7E1000
+        0xA2, 0x02,             // LDX #$02
+        0xBF, 0xFE, 0x0F, 0x7E, // LDA $7E0FFE,X
+        0x85, 0x10,             // STA $10
+        0x22, 0x00, 0x80, 0x01, // JSL $018000
+        0xE2, 0x20,             // SEP #$20
+        0xA9, 0x7E,             // LDA #$7E
+        0x48,                   // PHA
+        0xAB,                   // PLB
+        0xAD, 0x01, 0x10,       // LDA $1001
+        0xDB,                   // STP
+    ]);
+    machine.memory.set_bytes(LongAddress(0x018000), &[
+        0x1A,                   // INC A
+        0x6B,                   // RTL
+    ]);
+    let stack_pointer = machine.registers.stack_pointer;
+
+    machine.run();
+
+    assert_eq!(machine.memory.get_byte(LongAddress(0x7E1000)), 0xEF);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x7E1001)), 0xBE);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x000010)), 0xEF);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x000011)), 0xBE);
+
+    // INC A in bank 1 made it $BEF0, and then the low byte was loaded from
+    // $7E1001 through the data bank register
+    assert_eq!(machine.registers.accumulator, 0xBEBE);
+    assert_eq!(machine.registers.data_bank, 0x7E);
+    assert_eq!(machine.registers.program_bank, 0x00);
+    assert_eq!(machine.registers.program_counter, Address(0x8021));
+    assert_eq!(machine.registers.stack_pointer, stack_pointer);
+}
+
+#[test]
+fn direct_page_and_stack_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xC2, 0x30,       // REP #$30
+        0xA9, 0x00, 0x03, // LDA #$0300
+        0x5B,             // TCD
+        0xA9, 0x42, 0x42, // LDA #$4242
+        0x85, 0x10,       // STA $10
+        0xF4, 0x34, 0x12, // PEA $1234
+        0xA3, 0x01,       // LDA $01,S
+        0x85, 0x20,       // STA $20
+        0xD4, 0x10,       // PEI ($10)
+        0xFA,             // PLX
+        0x68,             // PLA
+        0xDB,             // STP
+    ]);
+    let stack_pointer = machine.registers.stack_pointer;
+
+    machine.run();
+
+    assert_eq!(machine.registers.direct_page, 0x0300);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x0310)), 0x42);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x0320)), 0x34);
+    assert_eq!(machine.memory.get_byte(LongAddress(0x0321)), 0x12);
+    assert_eq!(machine.registers.index_x, 0x4242);
+    assert_eq!(machine.registers.accumulator, 0x1234);
+    assert_eq!(machine.registers.stack_pointer, stack_pointer);
+}
+
+#[test]
+fn direct_page_wraparound_test() {
+    let mut machine = machine816_with_program(&[
+        0xA2, 0x20,       // LDX #$20
+        0xB5, 0xF0,       // LDA $F0,X
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xB4, 0xF0,       // LDY $F0,X
+        0xDB,             // STP
+    ]);
+    machine.memory.set_bytes(LongAddress(0x0010), &[0x11]);
+    machine.memory.set_bytes(LongAddress(0x0110), &[0x22]);
+
+    machine.run();
+
+    // In emulation mode indexing wraps around within the direct page, but in
+    // native mode it doesn't
+    assert_eq!(machine.registers.accumulator, 0x11);
+    assert_eq!(machine.registers.index_y, 0x22);
+}
+
+#[test]
+fn block_move_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xC2, 0x30,       // REP #$30
+        0xA9, 0x04, 0x00, // LDA #$0004
+        0xA2, 0x00, 0x10, // LDX #$1000
+        0xA0, 0x00, 0x20, // LDY #$2000
+        0x54, 0x03, 0x02, // MVN $03,$02
+        0xDB,             // STP
+    ]);
+    machine.memory.set_bytes(LongAddress(0x021000), b"HELLO");
+
+    for _ in 0..6 {
+        machine.step();
+    }
+    let cycles = machine.cycles;
+    machine.run();
+
+    for (i, &c) in b"HELLO".iter().enumerate() {
+        let address = LongAddress(0x032000 + i as u32);
+        assert_eq!(machine.memory.get_byte(address), c);
+    }
+    assert_eq!(machine.registers.accumulator, 0xFFFF);
+    assert_eq!(machine.registers.index_x, 0x1005);
+    assert_eq!(machine.registers.index_y, 0x2005);
+    assert_eq!(machine.registers.data_bank, 0x03);
+    assert_eq!(machine.cycles - cycles, 5 * 7 + 3);
+}
+
+#[test]
+fn decimal_816_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xF8,             // SED
+        0xC2, 0x21,       // REP #$21
+        0xA9, 0x99, 0x19, // LDA #$1999
+        0x69, 0x01, 0x00, // ADC #$0001
+        0xDB,             // STP
+    ]);
+    machine.run();
+    assert_eq!(machine.registers.accumulator, 0x2000);
+    assert!(!machine.registers.status.contains(PS_CARRY));
+
+    let mut machine = machine816_with_program(&[
+        0x18,             // CLC
+        0xFB,             // XCE
+        0xF8,             // SED
+        0xC2, 0x20,       // REP #$20
+        0xA9, 0x00, 0x00, // LDA #$0000
+        0xE9, 0x01, 0x00, // SBC #$0001
+        0xDB,             // STP
+    ]);
+    machine.run();
+
+    // XCE left C set, so there's no borrow in
+    assert_eq!(machine.registers.accumulator, 0x9999);
+    assert!(!machine.registers.status.contains(PS_CARRY));
+    assert!(machine.registers.status.contains(PS_NEGATIVE));
+
+    let mut machine = machine816_with_program(&[
+        0xF8,             // SED
+        0x38,             // SEC
+        0xA9, 0x98,       // LDA #$98
+        0x69, 0x01,       // ADC #$01
+        0xDB,             // STP
+    ]);
+    machine.run();
+    assert_eq!(machine.registers.accumulator, 0x00);
+    assert!(machine.registers.status.contains(PS_CARRY));
+    assert!(machine.registers.status.contains(PS_ZERO));
+}
+
+#[test]
+fn native_interrupt_test() {
+    let mut machine = machine816_with_program(&[
+        0x18,                   // CLC
+        0xFB,                   // XCE
+        0x5C, 0x00, 0x80, 0x02, // JML $028000
+    ]);
+    machine.memory.set_bytes(LongAddress(0x028000), &[
+        0x58,                   // CLI
+        0xEA,                   // NOP
+        0xDB,                   // STP
+    ]);
+    machine.memory.set_bytes(LongAddress(0x009000), &[
+        0xA9, 0x55,             // LDA #$55
+        0x40,                   // RTI
+    ]);
+    machine.memory.set_bytes(LongAddress(0x00FFEE), &[0x00, 0x90]);
+
+    for _ in 0..4 {
+        machine.step();
+    }
+    assert_eq!(machine.registers.program_bank, 0x02);
+    let stack_pointer = machine.registers.stack_pointer;
+
+    // Native mode interrupts push the program bank too, and run the handler
+    // in bank 0
+    machine.assert_irq();
+    machine.step();
+    machine.deassert_irq();
+    assert_eq!(machine.registers.program_bank, 0x00);
+    assert_eq!(machine.registers.stack_pointer, stack_pointer - 4);
+    let bank_address = LongAddress(stack_pointer as u32);
+    assert_eq!(machine.memory.get_byte(bank_address), 0x02);
+
+    machine.run();
+    assert_eq!(machine.registers.accumulator, 0x55);
+    assert_eq!(machine.registers.program_bank, 0x02);
+    assert_eq!(machine.registers.program_counter, Address(0x8003));
+    assert_eq!(machine.registers.stack_pointer, stack_pointer);
+}
@@ -25,16 +25,9 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
-use address::{Address, AddressDiff};
-
-// JAM: We can probably come up with a better way to represent address ranges.
-//      Address range type?
-// 
-// // Address range -- inclusive on both sides
-// pub struct AddressRangeIncl {
-//     begin: Address,
-//     end: Address,
-// }
+use address::{Address, AddressDiff, LongAddress};
+use bus::{Bus, LongBus};
+use save_state::{StateReader, StateWriter};
 
 const ADDR_LO_BARE: u16 = 0x0000;
 const ADDR_HI_BARE: u16 = 0xFFFF;
@@ -43,9 +36,26 @@ pub const MEMORY_ADDRESS_LO:       Address = Address(ADDR_LO_BARE);
 pub const MEMORY_ADDRESS_HI:       Address = Address(ADDR_HI_BARE);
 pub const STACK_ADDRESS_LO:        Address = Address(0x0100);
 pub const STACK_ADDRESS_HI:        Address = Address(0x01FF);
+pub const NMI_INTERRUPT_VECTOR_LO: Address = Address(0xFFFA);
+pub const NMI_INTERRUPT_VECTOR_HI: Address = Address(0xFFFB);
+pub const RESET_VECTOR_LO:         Address = Address(0xFFFC);
+pub const RESET_VECTOR_HI:         Address = Address(0xFFFD);
 pub const IRQ_INTERRUPT_VECTOR_LO: Address = Address(0xFFFE);
 pub const IRQ_INTERRUPT_VECTOR_HI: Address = Address(0xFFFF);
 
+// The 65C816 has a separate set of vectors for native mode, and a COP vector
+// in both modes. All of them are in bank 0.
+pub const NATIVE_COP_VECTOR_LO:    Address = Address(0xFFE4);
+pub const NATIVE_COP_VECTOR_HI:    Address = Address(0xFFE5);
+pub const NATIVE_BRK_VECTOR_LO:    Address = Address(0xFFE6);
+pub const NATIVE_BRK_VECTOR_HI:    Address = Address(0xFFE7);
+pub const NATIVE_NMI_VECTOR_LO:    Address = Address(0xFFEA);
+pub const NATIVE_NMI_VECTOR_HI:    Address = Address(0xFFEB);
+pub const NATIVE_IRQ_VECTOR_LO:    Address = Address(0xFFEE);
+pub const NATIVE_IRQ_VECTOR_HI:    Address = Address(0xFFEF);
+pub const COP_VECTOR_LO:           Address = Address(0xFFF4);
+pub const COP_VECTOR_HI:           Address = Address(0xFFF5);
+
 const MEMORY_SIZE: usize = (ADDR_HI_BARE - ADDR_LO_BARE) as usize + 1us;
 
 // FIXME: Should this use indirection for `bytes`?
@@ -101,3 +111,81 @@ impl Memory {
     }
 }
 
+impl Bus for Memory {
+    fn get_byte(&mut self, address: Address) -> u8 {
+        Memory::get_byte(self, address)
+    }
+
+    fn peek_byte(&self, address: Address) -> u8 {
+        Memory::get_byte(self, address)
+    }
+
+    fn set_byte(&mut self, address: Address, value: u8) -> u8 {
+        Memory::set_byte(self, address, value)
+    }
+
+    fn set_bytes(&mut self, start: Address, values: &[u8]) {
+        Memory::set_bytes(self, start, values)
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.slice(&self.bytes);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), String> {
+        let bytes = try!(input.slice(MEMORY_SIZE));
+        Memory::set_bytes(self, MEMORY_ADDRESS_LO, bytes);
+        Ok(())
+    }
+}
+
+const BANK_SIZE: usize = 0x10000;
+
+// The 65C816's 16 MiB of RAM. A bank is only allocated when something is
+// written to it, so untouched banks read as zero and cost nothing.
+pub struct LongMemory {
+    banks: Vec<Vec<u8>>
+}
+
+impl LongMemory {
+    pub fn new() -> LongMemory {
+        let mut banks = Vec::new();
+        for _ in 0..256 {
+            banks.push(Vec::new());
+        }
+        LongMemory { banks: banks }
+    }
+
+    pub fn get_byte(&self, address: LongAddress) -> u8 {
+        let bank = &self.banks[address.get_bank() as usize];
+        if bank.is_empty() {
+            0
+        } else {
+            bank[address.get_address().to_usize()]
+        }
+    }
+
+    pub fn set_byte(&mut self, address: LongAddress, value: u8) -> u8 {
+        let old_value = self.get_byte(address);
+        let bank = &mut self.banks[address.get_bank() as usize];
+        if bank.is_empty() {
+            *bank = vec![0; BANK_SIZE];
+        }
+        bank[address.get_address().to_usize()] = value;
+        old_value
+    }
+}
+
+impl LongBus for LongMemory {
+    fn get_byte(&mut self, address: LongAddress) -> u8 {
+        LongMemory::get_byte(self, address)
+    }
+
+    fn peek_byte(&self, address: LongAddress) -> u8 {
+        LongMemory::get_byte(self, address)
+    }
+
+    fn set_byte(&mut self, address: LongAddress, value: u8) -> u8 {
+        LongMemory::set_byte(self, address, value)
+    }
+}
@@ -0,0 +1,577 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// A machine-language monitor in the spirit of the Apple II one. Each command
+// is a line of text, and produces some text to show. Numbers are hex, with an
+// optional leading `$`. The commands are listed in `HELP`, which is what `?`
+// shows.
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use disassembler::{disassemble_instruction, DisassembledInstr};
+use machine::{Machine, StopReason};
+use memory::Memory;
+use registers::{Status, StackPointer};
+
+static HELP: &'static str = "\
+ADDR            examine a byte
+ADDR.END        examine a range of memory
+ADDR: BB BB ..  deposit bytes
+L [ADDR]        disassemble from ADDR, or around the PC
+R               show the registers
+R REG VALUE     set A, X, Y, SP, P or PC
+S [N]           step N instructions
+G [ADDR]        run from ADDR (or the PC) until a breakpoint, an invalid
+                opcode, a JAM or 10000000 instructions
+B [ADDR]        set a breakpoint, or list them
+BC ADDR         clear a breakpoint
+U [N]           undo N steps
+GB              run backwards until a breakpoint or the start of the history
+T N             go back to step N, counting from when the history started
+Q               quit";
+
+// How many instructions `L` shows
+const LIST_LENGTH: usize = 12;
+
+// How many steps can be undone
+const HISTORY_LENGTH: usize = 100000;
+
+// How many instructions `G` runs before giving up, so that a program that
+// loops forever doesn't hang the monitor
+const GO_LIMIT: u64 = 10000000;
+
+// The history that U, GB and T use is the machine's own.
+pub struct Monitor<B = Memory> {
+    pub machine:  Machine<B>,
+
+    // The most instructions `G` runs
+    pub go_limit: u64,
+
+    // Where G and GB stop, kept sorted
+    breakpoints:  Vec<Address>,
+}
+
+impl<B: Bus> Monitor<B> {
+    // Starts recording the machine's history, unless it already is
+    pub fn new(mut machine: Machine<B>) -> Monitor<B> {
+        if machine.history().is_none() {
+            machine.record_history(HISTORY_LENGTH);
+        }
+        Monitor { machine: machine, go_limit: GO_LIMIT,
+                  breakpoints: Vec::new() }
+    }
+
+    // Runs one command, returning what it has to say
+    pub fn command(&mut self, line: &str) -> String {
+        match self.try_command(line.trim()) {
+            Ok(output) => output,
+            Err(message) => format!("error: {}", message),
+        }
+    }
+
+    fn try_command(&mut self, line: &str) -> Result<String, String> {
+        if line.is_empty() {
+            return Ok(String::new());
+        }
+
+        if let Some(colon) = line.find(':') {
+            let address = try!(parse_address(&line[..colon]));
+            return self.deposit(address, &line[colon + 1..]);
+        }
+
+        let words: Vec<&str> = line.split(' ').filter(|w| !w.is_empty())
+                                   .collect();
+        let command = to_upper(words[0]);
+        let args = &words[1..];
+
+        match &command[..] {
+            "?" | "H" | "HELP" => Ok(HELP.to_string()),
+            "R" if args.is_empty() => Ok(self.registers()),
+            "R" if args.len() == 2 => {
+                try!(self.set_register(args[0], args[1]));
+                Ok(self.registers())
+            }
+            "L" if args.len() <= 1 => {
+                let start = match args.first() {
+                    Some(arg) => try!(parse_address(arg)),
+                    None => self.list_start(),
+                };
+                Ok(self.list(start))
+            }
+            "S" if args.len() <= 1 => {
+                let count = match args.first() {
+                    Some(arg) => try!(parse_number(arg)),
+                    None => 1,
+                };
+                Ok(self.step(count))
+            }
+            "G" if args.len() <= 1 => {
+                if let Some(arg) = args.first() {
+                    self.machine.registers.program_counter =
+                        try!(parse_address(arg));
+                }
+                Ok(self.go())
+            }
+            "B" if args.is_empty() => {
+                let list: Vec<String> =
+                    self.breakpoints.iter()
+                                    .map(|b| format!("{:04X}", b.to_u16()))
+                                    .collect();
+                Ok(if list.is_empty() { "no breakpoints".to_string() }
+                   else { list.connect(" ") })
+            }
+            "B" if args.len() == 1 => {
+                let address = try!(parse_address(args[0]));
+                if !self.breakpoints.contains(&address) {
+                    self.breakpoints.push(address);
+                    self.breakpoints.sort();
+                }
+                Ok(format!("breakpoint at {:04X}", address.to_u16()))
+            }
+            "BC" if args.len() == 1 => {
+                let address = try!(parse_address(args[0]));
+                if self.breakpoints.contains(&address) {
+                    self.breakpoints.retain(|&b| b != address);
+                    Ok(format!("cleared {:04X}", address.to_u16()))
+                } else {
+                    Err(format!("no breakpoint at {:04X}", address.to_u16()))
+                }
+            }
+            "U" if args.len() <= 1 => {
+                let count = match args.first() {
+                    Some(arg) => try!(parse_number(arg)),
+                    None => 1,
+                };
+                self.step_back(count)
+            }
+            "GB" if args.is_empty() => {
+                let reason = self.machine.run_backward(&self.breakpoints);
+                Ok(self.stopped(reason))
+            }
+            "T" if args.len() == 1 => {
+                let step = try!(parse_number(args[0]));
+                self.rewind_to(step)
+            }
+            _ if words.len() == 1 => self.examine(words[0]),
+            _ => Err(format!("unknown command: {}", line)),
+        }
+    }
+
+    fn examine(&mut self, range: &str) -> Result<String, String> {
+        let (start, end) = match range.find('.') {
+            Some(dot) => (try!(parse_address(&range[..dot])),
+                          try!(parse_address(&range[dot + 1..]))),
+            None => {
+                let address = try!(parse_address(range));
+                (address, address)
+            }
+        };
+        if end < start {
+            return Err("the end of the range is before the start".to_string());
+        }
+
+        // Eight bytes to a line, with each line starting on a multiple of 8
+        let mut lines = Vec::new();
+        let mut address = start.to_u16() as u32;
+        while address <= end.to_u16() as u32 {
+            let mut line = format!("{:04X}:", address);
+            loop {
+                let byte = self.machine.memory
+                               .peek_byte(Address(address as u16));
+                line.push_str(&format!(" {:02X}", byte));
+                address += 1;
+                if address > end.to_u16() as u32 || address % 8 == 0 {
+                    break;
+                }
+            }
+            lines.push(line);
+        }
+        Ok(lines.connect("\n"))
+    }
+
+    fn deposit(&mut self, start: Address, bytes: &str)
+        -> Result<String, String>
+    {
+        let mut values = Vec::new();
+        for word in bytes.split(' ').filter(|w| !w.is_empty()) {
+            let value = try!(parse_number(word));
+            if value > 0xFF {
+                return Err(format!("not a byte: {}", word));
+            }
+            values.push(value as u8);
+        }
+        if start.to_u16() as usize + values.len() > 0x10000 {
+            return Err("that runs past the end of memory".to_string());
+        }
+        self.machine.memory.set_bytes(start, &values);
+
+        let end = start + AddressDiff(values.len() as i32 - 1);
+        if values.is_empty() {
+            Ok(String::new())
+        } else {
+            self.examine(&format!("{:04X}.{:04X}", start.to_u16(),
+                                  end.to_u16()))
+        }
+    }
+
+    fn registers(&self) -> String {
+        let regs = &self.machine.registers;
+        let StackPointer(sp) = regs.stack_pointer;
+        format!("PC={:04X} A={:02X} X={:02X} Y={:02X} SP={:02X} P={:02X} {} \
+                 CYC={}",
+                regs.program_counter.to_u16(), regs.accumulator as u8,
+                regs.index_x as u8, regs.index_y as u8, sp,
+                regs.status.bits(), regs.status, self.machine.cycles)
+    }
+
+    fn set_register(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let value = try!(parse_number(value));
+        let regs = &mut self.machine.registers;
+        let name = to_upper(name);
+
+        if name == "PC" {
+            if value > 0xFFFF {
+                return Err(format!("not an address: {:X}", value));
+            }
+            regs.program_counter = Address(value as u16);
+            return Ok(());
+        }
+
+        if value > 0xFF {
+            return Err(format!("not a byte: {:X}", value));
+        }
+        let byte = value as u8;
+        match &name[..] {
+            "A" => regs.accumulator = byte as i8,
+            "X" => regs.index_x = byte as i8,
+            "Y" => regs.index_y = byte as i8,
+            "SP" => regs.stack_pointer = StackPointer(byte),
+            "P" => regs.status = Status::from_bits_truncate(byte),
+            _ => return Err(format!("unknown register: {}", name)),
+        }
+        Ok(())
+    }
+
+    // Instructions can't be decoded backwards, so look for a point a little
+    // before the PC from which decoding lands on the PC.
+    fn list_start(&self) -> Address {
+        let pc = self.machine.registers.program_counter;
+        for back in (1..10).rev() {
+            if (pc.to_u16() as i32) < back {
+                continue;
+            }
+            let start = pc + AddressDiff(-back);
+
+            let mut address = start;
+            let mut count = 0;
+            while address < pc && count < 4 {
+                address = self.disassemble(address).next_address();
+                count += 1;
+            }
+            if address == pc && count <= 3 {
+                return start;
+            }
+        }
+        pc
+    }
+
+    fn disassemble(&self, address: Address) -> DisassembledInstr {
+        disassemble_instruction(&self.machine.memory, address,
+                                self.machine.variant)
+    }
+
+    // The PC is marked with `>` and breakpoints with `*`
+    fn list(&self, start: Address) -> String {
+        let pc = self.machine.registers.program_counter;
+        let mut lines = Vec::new();
+        let mut address = start.to_u16() as u32;
+
+        while lines.len() < LIST_LENGTH && address <= 0xFFFF {
+            let instr = self.disassemble(Address(address as u16));
+            address += instr.bytes.len() as u32;
+            lines.push(self.format_instr(&instr, pc));
+        }
+        lines.connect("\n")
+    }
+
+    fn format_instr(&self, instr: &DisassembledInstr, pc: Address) -> String {
+        let pc_mark = if instr.address == pc { '>' } else { ' ' };
+        let break_mark = if self.breakpoints.contains(&instr.address)
+                         { '*' } else { ' ' };
+        format!("{}{} {}", pc_mark, break_mark, instr)
+    }
+
+    // Says why the machine stopped, in the monitor's hex style
+    fn describe(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::InvalidOpcode(address, opcode) =>
+                format!("invalid opcode {:02X} at {:04X}", opcode,
+                        address.to_u16()),
+            StopReason::Brk(address) =>
+                format!("BRK at {:04X}", address.to_u16()),
+            StopReason::Breakpoint(address) =>
+                format!("breakpoint at {:04X}", address.to_u16()),
+            StopReason::BudgetExhausted => "budget exhausted".to_string(),
+            StopReason::ConditionMet => "condition met".to_string(),
+            StopReason::Jam(address) =>
+                format!("JAM {:02X} at {:04X}",
+                        self.machine.memory.peek_byte(address),
+                        address.to_u16()),
+            StopReason::Stp => "stopped by STP".to_string(),
+            StopReason::HistoryExhausted => "start of history".to_string(),
+        }
+    }
+
+    // Runs one instruction, returning a message if it couldn't
+    fn step_one(&mut self) -> Option<String> {
+        self.machine.step().map(|reason| self.describe(reason))
+    }
+
+    fn step(&mut self, count: u64) -> String {
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            let pc = self.machine.registers.program_counter;
+            let instr = self.disassemble(pc);
+            if let Some(message) = self.step_one() {
+                lines.push(message);
+                break;
+            }
+            lines.push(format!("{}", instr));
+        }
+        lines.push(self.registers());
+        lines.connect("\n")
+    }
+
+    fn go(&mut self) -> String {
+        let result = self.machine.run_to_breakpoint(&self.breakpoints,
+                                                    Some(self.go_limit));
+        self.stopped(result.reason)
+    }
+
+    // Why the machine stopped, followed by the registers and the
+    // instruction at the PC
+    fn stopped(&self, reason: StopReason) -> String {
+        let message = self.describe(reason);
+        format!("{}\n{}", message, self.position())
+    }
+
+    fn position(&self) -> String {
+        let pc = self.machine.registers.program_counter;
+        format!("{}\n{}", self.registers(),
+                self.format_instr(&self.disassemble(pc), pc))
+    }
+
+    fn step_back(&mut self, count: u64) -> Result<String, String> {
+        for i in 0..count {
+            if !self.machine.step_back() {
+                if i == 0 {
+                    return Err("no history to undo".to_string());
+                }
+                break;
+            }
+        }
+        Ok(self.position())
+    }
+
+    fn rewind_to(&mut self, step: u64) -> Result<String, String> {
+        if self.machine.rewind_to(step) {
+            return Ok(self.position());
+        }
+
+        match self.machine.history() {
+            Some(history) =>
+                Err(format!("the history goes from step {:X} to {:X}",
+                            history.earliest_step(), history.steps())),
+            None => Err("no history".to_string()),
+        }
+    }
+}
+
+fn to_upper(s: &str) -> String {
+    s.chars()
+     .map(|c| if 'a' <= c && c <= 'z' { (c as u8 - b'a' + b'A') as char }
+              else { c })
+     .collect()
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let digits = if text.starts_with("$") { &text[1..] } else { text };
+    if digits.is_empty() || digits.len() > 8 {
+        return Err(format!("not a number: {}", text));
+    }
+
+    let mut value = 0;
+    for c in digits.chars() {
+        match c.to_digit(16) {
+            Some(digit) => value = value * 16 + digit as u64,
+            None => return Err(format!("not a number: {}", text)),
+        }
+    }
+    Ok(value)
+}
+
+fn parse_address(text: &str) -> Result<Address, String> {
+    let value = try!(parse_number(text.trim()));
+    if value > 0xFFFF {
+        return Err(format!("not an address: {}", text.trim()));
+    }
+    Ok(Address(value as u16))
+}
+
+#[cfg(test)]
+fn test_monitor() -> Monitor {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x0600), &[
+        0xA2, 0x03,     // LDX #$03
+        0xCA,           // DEX
+        0xD0, 0xFD,     // BNE $0602
+        0xE8,           // INX
+        0x02,           // JAM
+    ]);
+    machine.registers.program_counter = Address(0x0600);
+    machine.cycles = 0;
+    Monitor::new(machine)
+}
+
+#[test]
+fn examine_and_deposit_test() {
+    let mut monitor = test_monitor();
+
+    assert_eq!(monitor.command("600"), "0600: A2");
+    assert_eq!(monitor.command("$0604.060A"),
+               "0604: FD E8 02 00\n0608: 00 00 00");
+    assert_eq!(monitor.command("300: 1 2 $FF"), "0300: 01 02 FF");
+    assert_eq!(monitor.machine.memory.get_byte(Address(0x0302)), 0xFF);
+    assert_eq!(monitor.command("300: 100"), "error: not a byte: 100");
+}
+
+#[test]
+fn registers_test() {
+    let mut monitor = test_monitor();
+
+    assert_eq!(monitor.command("r a 80"),
+               "PC=0600 A=80 X=00 Y=00 SP=FC P=24 ..-..I.. CYC=0");
+    assert_eq!(monitor.machine.registers.accumulator as u8, 0x80);
+
+    monitor.command("R PC 1234");
+    monitor.command("R P C3");
+    assert_eq!(monitor.machine.registers.program_counter, Address(0x1234));
+    assert_eq!(monitor.machine.registers.status.bits(), 0xC3);
+    assert_eq!(monitor.command("R Q 1"), "error: unknown register: Q");
+}
+
+#[test]
+fn step_test() {
+    let mut monitor = test_monitor();
+
+    assert_eq!(monitor.command("S 2"),
+               "0600  A2 03     LDX #$03\n\
+                0602  CA        DEX\n\
+                PC=0603 A=00 X=02 Y=00 SP=FC P=24 ..-..I.. CYC=4");
+}
+
+#[test]
+fn breakpoint_test() {
+    let mut monitor = test_monitor();
+
+    assert_eq!(monitor.command("B 602"), "breakpoint at 0602");
+    assert_eq!(monitor.command("B"), "0602");
+
+    // Going again from a breakpoint carries on past it
+    monitor.command("G");
+    assert_eq!(monitor.machine.registers.index_x, 3);
+    monitor.command("G");
+    assert_eq!(monitor.machine.registers.index_x, 2);
+
+    assert_eq!(monitor.command("BC 602"), "cleared 0602");
+    assert_eq!(monitor.command("B"), "no breakpoints");
+    assert_eq!(monitor.command("G"),
+               "JAM 02 at 0606\n\
+                PC=0606 A=00 X=01 Y=00 SP=FC P=24 ..-..I.. CYC=20\n\
+                >  0606  02        JAM");
+}
+
+#[test]
+fn go_limit_test() {
+    let mut monitor = test_monitor();
+    monitor.command("600: 4C 00 06");
+    monitor.go_limit = 1000;
+
+    // JMP * would otherwise never stop
+    assert_eq!(monitor.command("G"),
+               "budget exhausted\n\
+                PC=0600 A=00 X=00 Y=00 SP=FC P=24 ..-..I.. CYC=3000\n\
+                >  0600  4C 00 06  JMP $0600");
+}
+
+#[test]
+fn list_test() {
+    let mut monitor = test_monitor();
+    monitor.command("S 4");
+    monitor.command("B 605");
+
+    // Starts a few instructions before the PC
+    let listing = monitor.command("L");
+    let lines: Vec<&str> = listing.lines().take(5).collect();
+    assert_eq!(lines, vec![
+        "   05FF  00        BRK",
+        "   0600  A2 03     LDX #$03",
+        "   0602  CA        DEX",
+        ">  0603  D0 FD     BNE $0602",
+        " * 0605  E8        INX",
+    ]);
+}
+
+#[test]
+fn step_back_test() {
+    let mut monitor = test_monitor();
+
+    assert_eq!(monitor.command("U"), "error: no history to undo");
+
+    monitor.command("S 4");
+    assert_eq!(monitor.command("U 2"),
+               "PC=0603 A=00 X=02 Y=00 SP=FC P=24 ..-..I.. CYC=4\n\
+                >  0603  D0 FD     BNE $0602");
+
+    // Back to the DEX the second time round
+    monitor.command("G");
+    monitor.command("B 602");
+    assert_eq!(monitor.command("GB"),
+               "breakpoint at 0602\n\
+                PC=0602 A=00 X=01 Y=00 SP=FC P=24 ..-..I.. CYC=12\n\
+                >* 0602  CA        DEX");
+
+    assert_eq!(monitor.command("T 1"),
+               "PC=0602 A=00 X=03 Y=00 SP=FC P=24 ..-..I.. CYC=2\n\
+                >* 0602  CA        DEX");
+    assert_eq!(monitor.command("T 2"),
+               "error: the history goes from step 0 to 1");
+    assert_eq!(monitor.command("GB"),
+               "start of history\n\
+                PC=0600 A=00 X=00 Y=00 SP=FC P=24 ..-..I.. CYC=0\n\
+                >  0600  A2 03     LDX #$03");
+}
@@ -1,5 +1,7 @@
 use std::num::Int;
 
+use address::Address;
+
 pub struct RangeIncl<T: Int> {
     state: Option<T>,
     end: T,
@@ -32,3 +34,35 @@ impl<T: Int> Iterator for RangeIncl<T> {
         }
     }
 }
+
+// Address range -- inclusive on both sides
+#[derive(Copy, PartialEq, Eq, Debug)]
+pub struct AddressRangeIncl {
+    pub begin: Address,
+    pub end: Address,
+}
+
+impl AddressRangeIncl {
+    pub fn new(begin: Address, end: Address) -> AddressRangeIncl {
+        debug_assert!(begin <= end);
+        AddressRangeIncl { begin: begin, end: end }
+    }
+
+    pub fn contains(&self, address: Address) -> bool {
+        self.begin <= address && address <= self.end
+    }
+
+    pub fn overlaps(&self, other: &AddressRangeIncl) -> bool {
+        self.begin <= other.end && other.begin <= self.end
+    }
+
+    // Offset of the given address from the start of the range
+    pub fn offset_of(&self, address: Address) -> u16 {
+        debug_assert!(self.contains(address));
+        address.to_u16() - self.begin.to_u16()
+    }
+
+    pub fn iter(&self) -> RangeIncl<u16> {
+        range_incl(self.begin.to_u16(), self.end.to_u16())
+    }
+}
@@ -25,6 +25,8 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
+use std::fmt;
+
 use address::{Address, AddressDiff};
 use memory::{STACK_ADDRESS_LO, STACK_ADDRESS_HI};
 
@@ -71,6 +73,12 @@ pub bitflags! {
     }
 }
 
+// In the 65C816's native mode, the bits that are B and unused on the 6502
+// select 8-bit (set) or 16-bit (clear) accumulator and memory accesses, and
+// index registers.
+pub const PS_MEMORY_8BIT: Status = PS_UNUSED;
+pub const PS_INDEX_8BIT:  Status = PS_BRK;
+
 impl Status {
     pub fn default() -> Status {
         // TODO akeeton: Revisit these defaults.
@@ -121,6 +129,21 @@ impl Status {
     }
 }
 
+// Shows the flags in the usual NV-BDIZC order, with a dot for each clear flag
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [(PS_NEGATIVE, 'N'), (PS_OVERFLOW, 'V'), (PS_UNUSED, '-'),
+                     (PS_BRK, 'B'), (PS_DECIMAL_MODE, 'D'),
+                     (PS_DISABLE_INTERRUPTS, 'I'), (PS_ZERO, 'Z'),
+                     (PS_CARRY, 'C')];
+        let text: String = flags.iter()
+                                .map(|&(flag, c)| if self.contains(flag) { c }
+                                                  else { '.' })
+                                .collect();
+        write!(f, "{}", text)
+    }
+}
+
 #[derive(Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct StackPointer(pub u8);
 
@@ -131,16 +154,15 @@ impl StackPointer {
         STACK_ADDRESS_LO + AddressDiff(sp as i32)
     }
 
-    // JAM: FIXME: Should we prevent overflow here? What would a 6502 do?
-
+    // The stack pointer wraps around within page 1, as on a real 6502
     pub fn decrement(&mut self) {
         let StackPointer(val) = *self;
-        *self = StackPointer(val - 1);
+        *self = StackPointer(val.wrapping_sub(1));
     }
 
     pub fn increment(&mut self) {
         let StackPointer(val) = *self;
-        *self = StackPointer(val + 1);
+        *self = StackPointer(val.wrapping_add(1));
     }
 }
 
@@ -168,3 +190,37 @@ impl Registers {
     }
 }
 
+// The 65C816's registers. Which parts of the 16-bit ones are in use depends
+// on the emulation flag and the M and X status bits. The stack pointer is
+// kept as a full address in bank 0.
+#[derive(Copy, PartialEq, Eq, Debug)]
+pub struct Registers816 {
+    pub accumulator:     u16,
+    pub index_x:         u16,
+    pub index_y:         u16,
+    pub stack_pointer:   u16,
+    pub direct_page:     u16,
+    pub data_bank:       u8,
+    pub program_bank:    u8,
+    pub program_counter: Address,
+    pub status:          Status,
+    pub emulation:       bool
+}
+
+impl Registers816 {
+    pub fn new() -> Registers816 {
+        Registers816 {
+            accumulator:     0,
+            index_x:         0,
+            index_y:         0,
+            stack_pointer:   STACK_ADDRESS_HI.to_u16(),
+            direct_page:     0,
+            data_bank:       0,
+            program_bank:    0,
+            program_counter: Address(0),
+            status:          Status::default() | PS_MEMORY_8BIT
+                                               | PS_INDEX_8BIT,
+            emulation:       true
+        }
+    }
+}
@@ -0,0 +1,600 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// Save states: everything needed to carry on running a machine later, or on
+// someone else's computer.
+//
+// A save state starts with a header that's never compressed:
+//
+//     "6502SAVE"     magic number
+//     version        1 byte, currently 1
+//     variant        1 byte: 0 NMOS, 1 65C02, 2 2A03
+//     length         4 bytes, the size of the body once decompressed
+//     checksum       4 bytes, the Adler-32 of the decompressed body
+//
+// followed by the body, compressed with PackBits run-length encoding (which
+// does well on the long runs of identical bytes in most memory images). The
+// body holds the registers, the cycle counter, the interrupt and run state,
+// and then whatever the bus saves. Multi-byte numbers are little-endian.
+
+use std::fmt;
+
+use address::Address;
+use bus::Bus;
+use instruction::Variant;
+use machine::Machine;
+use registers::{Registers, StackPointer, Status};
+
+const MAGIC: &'static [u8] = b"6502SAVE";
+const HEADER_SIZE: usize = 18;
+
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    // The data doesn't start with the magic number
+    NotASaveState,
+
+    // Saved by a version of the format this doesn't understand
+    UnsupportedVersion(u8),
+
+    // Saved from one kind of processor (the first) and being loaded into
+    // another (the second)
+    VariantMismatch(Variant, Variant),
+
+    // Truncated or damaged, or saved from a machine with a different bus
+    Corrupt(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::NotASaveState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) =>
+                write!(f, "unsupported save state version {} (expected {})",
+                       version, VERSION),
+            StateError::VariantMismatch(saved, machine) =>
+                write!(f, "saved from {}, but this is {}",
+                       variant_name(saved), variant_name(machine)),
+            StateError::Corrupt(ref message) =>
+                write!(f, "corrupt save state: {}", message),
+        }
+    }
+}
+
+// What buses and devices write their state to
+pub struct StateWriter {
+    pub bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { bytes: Vec::new() }
+    }
+
+    pub fn byte(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.byte(if value { 1 } else { 0 });
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.u64_bytes(value as u64, 2);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.u64_bytes(value as u64, 4);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.u64_bytes(value, 8);
+    }
+
+    pub fn slice(&mut self, values: &[u8]) {
+        for &value in values.iter() {
+            self.bytes.push(value);
+        }
+    }
+
+    fn u64_bytes(&mut self, value: u64, count: usize) {
+        for i in 0..count {
+            self.byte((value >> (8 * i)) as u8);
+        }
+    }
+}
+
+// What buses and devices read their state back from. Every read fails once
+// the data runs out.
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos:   usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> StateReader<'a> {
+        StateReader { bytes: bytes, pos: 0 }
+    }
+
+    pub fn byte(&mut self) -> Result<u8, String> {
+        let bytes = try!(self.slice(1));
+        Ok(bytes[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        match try!(self.byte()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("expected a boolean, found {}", other)),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        self.u64_bytes(2).map(|value| value as u16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        self.u64_bytes(4).map(|value| value as u32)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        self.u64_bytes(8)
+    }
+
+    pub fn slice(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < count {
+            return Err("unexpected end of data".to_string());
+        }
+        let slice = &self.bytes[self.pos..(self.pos + count)];
+        self.pos += count;
+        Ok(slice)
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn u64_bytes(&mut self, count: usize) -> Result<u64, String> {
+        let bytes = try!(self.slice(count));
+        let mut value = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            value |= (byte as u64) << (8 * i);
+        }
+        Ok(value)
+    }
+}
+
+fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Nmos      => "an NMOS 6502",
+        Variant::Cmos      => "a 65C02",
+        Variant::Ricoh2A03 => "a 2A03",
+    }
+}
+
+fn variant_code(variant: Variant) -> u8 {
+    match variant {
+        Variant::Nmos      => 0,
+        Variant::Cmos      => 1,
+        Variant::Ricoh2A03 => 2,
+    }
+}
+
+fn variant_from_code(code: u8) -> Option<Variant> {
+    match code {
+        0 => Some(Variant::Nmos),
+        1 => Some(Variant::Cmos),
+        2 => Some(Variant::Ricoh2A03),
+        _ => None,
+    }
+}
+
+// PackBits: a control byte n of 0-127 is followed by n + 1 literal bytes, and
+// one of 129-255 by a single byte to be repeated 257 - n times.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && run < 128 && data[i + run] == data[i] {
+            run += 1;
+        }
+
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+        } else {
+            // Take literals until the next run of three or more, which is
+            // where a run starts to pay for itself
+            let start = i;
+            while i < data.len() && i - start < 128 {
+                if i + 2 < data.len() && data[i] == data[i + 1]
+                                      && data[i] == data[i + 2] {
+                    break;
+                }
+                i += 1;
+            }
+            out.push((i - start - 1) as u8);
+            for &byte in data[start..i].iter() {
+                out.push(byte);
+            }
+        }
+    }
+
+    out
+}
+
+// The length comes from the header, so it can't be trusted to reserve space
+// with. The body is checked against it as it grows instead.
+fn decompress(data: &[u8], length: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut reader = StateReader::new(data);
+
+    while !reader.at_end() {
+        let control = try!(reader.byte());
+        if control < 128 {
+            let literals = try!(reader.slice(control as usize + 1));
+            for &byte in literals.iter() {
+                out.push(byte);
+            }
+        } else if control > 128 {
+            let byte = try!(reader.byte());
+            for _ in 0..(257 - control as usize) {
+                out.push(byte);
+            }
+        }
+        // 128 is a no-op in PackBits
+
+        if out.len() > length {
+            return Err("body is longer than the header says".to_string());
+        }
+    }
+
+    if out.len() != length {
+        return Err("body is shorter than the header says".to_string());
+    }
+    Ok(out)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for &byte in data.iter() {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+impl<B: Bus> Machine<B> {
+    // Saves the registers, cycle counter, interrupt and run state and
+    // everything on the bus. Breakpoints aren't saved, since they belong to
+    // whoever is debugging rather than to the machine.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut body = StateWriter::new();
+        let registers = &self.registers;
+        body.byte(registers.accumulator as u8);
+        body.byte(registers.index_x as u8);
+        body.byte(registers.index_y as u8);
+        body.byte(registers.status.bits());
+        let StackPointer(sp) = registers.stack_pointer;
+        body.byte(sp);
+        body.u16(registers.program_counter.to_u16());
+
+        body.u64(self.cycles);
+        body.bool(self.irq_line);
+        body.bool(self.nmi_pending);
+        body.bool(self.strict);
+        body.bool(self.waiting);
+        body.bool(self.stopped);
+        body.bool(self.stop_on_brk);
+
+        self.memory.save_state(&mut body);
+
+        let mut out = StateWriter::new();
+        out.slice(MAGIC);
+        out.byte(VERSION);
+        out.byte(variant_code(self.variant));
+        out.u32(body.bytes.len() as u32);
+        out.u32(adler32(&body.bytes));
+        out.slice(&compress(&body.bytes));
+        out.bytes
+    }
+
+    // Restores a state saved by `save_state`. The processor variant has to
+    // match, and so does the bus: a state saved with devices attached can
+    // only be loaded into a bus with the same devices. Everything but the
+    // bus is checked before anything is changed, but a bus that turns out
+    // not to match may be left partly restored.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotASaveState);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(StateError::Corrupt("header is truncated"
+                                           .to_string()));
+        }
+
+        let mut header = StateReader::new(&data[MAGIC.len()..HEADER_SIZE]);
+
+        let version = try!(header.byte().map_err(StateError::Corrupt));
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let code = try!(header.byte().map_err(StateError::Corrupt));
+        let variant = match variant_from_code(code) {
+            Some(variant) => variant,
+            None => return Err(StateError::Corrupt(
+                format!("unknown processor {}", code))),
+        };
+        if variant != self.variant {
+            return Err(StateError::VariantMismatch(variant, self.variant));
+        }
+
+        let length = try!(header.u32().map_err(StateError::Corrupt)) as usize;
+        let checksum = try!(header.u32().map_err(StateError::Corrupt));
+        let body = try!(decompress(&data[HEADER_SIZE..], length)
+                            .map_err(StateError::Corrupt));
+        if adler32(&body) != checksum {
+            return Err(StateError::Corrupt("checksum doesn't match"
+                                           .to_string()));
+        }
+
+        let mut reader = StateReader::new(&body);
+        let result = self.load_body(&mut reader).and_then(|_| {
+            if reader.at_end() {
+                Ok(())
+            } else {
+                Err("unexpected data after the bus state".to_string())
+            }
+        });
+        result.map_err(StateError::Corrupt)
+    }
+
+    fn load_body(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        let accumulator = try!(reader.byte());
+        let index_x = try!(reader.byte());
+        let index_y = try!(reader.byte());
+        let status = try!(reader.byte());
+        let sp = try!(reader.byte());
+        let pc = try!(reader.u16());
+        let registers = Registers {
+            accumulator:     accumulator as i8,
+            index_x:         index_x as i8,
+            index_y:         index_y as i8,
+            stack_pointer:   StackPointer(sp),
+            program_counter: Address(pc),
+            status:          Status::from_bits_truncate(status),
+        };
+
+        let cycles = try!(reader.u64());
+        let irq_line = try!(reader.bool());
+        let nmi_pending = try!(reader.bool());
+        let strict = try!(reader.bool());
+        let waiting = try!(reader.bool());
+        let stopped = try!(reader.bool());
+        let stop_on_brk = try!(reader.bool());
+
+        try!(self.memory.load_state(reader));
+
+        self.registers = registers;
+        self.cycles = cycles;
+        self.irq_line = irq_line;
+        self.nmi_pending = nmi_pending;
+        self.strict = strict;
+        self.waiting = waiting;
+        self.stopped = stopped;
+        self.stop_on_brk = stop_on_brk;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use std::iter;
+
+#[cfg(test)]
+use address::AddressDiff;
+
+#[cfg(test)]
+use device::{Device, DeviceBus};
+
+#[cfg(test)]
+use memory::Memory;
+
+#[cfg(test)]
+use range_incl::AddressRangeIncl;
+
+#[cfg(test)]
+use machine::StopReason;
+
+// Counts the values written to it in a register that reads back the total
+#[cfg(test)]
+struct TestCounter {
+    total: u8,
+}
+
+#[cfg(test)]
+impl Device for TestCounter {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.total
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.total = self.total.wrapping_add(value);
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.byte(self.total);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader)
+        -> Result<(), String>
+    {
+        self.total = try!(input.byte());
+        Ok(())
+    }
+}
+
+#[test]
+fn compress_test() {
+    let cases: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![7],
+        vec![1, 2, 3, 3, 3, 3, 4, 5, 5],
+        iter::repeat(0).take(1000).collect(),
+        (0..1000).map(|i| (i * 37 % 251) as u8).collect(),
+    ];
+    for data in cases.iter() {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), *data);
+    }
+
+    assert_eq!(compress(&[9; 300]).len(), 6);
+    assert!(decompress(&compress(&[9; 300]), 299).is_err());
+    assert!(decompress(&[5, 1, 2], 6).is_err());
+}
+
+#[test]
+fn save_and_load_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_bytes(Address(0x1000), &[
+        0xA2, 0x00,       // LDX #$00
+        0x8A,             // TXA
+        0x9D, 0x00, 0x20, // STA $2000,X
+        0xE8,             // INX
+        0xD0, 0xF9,       // BNE $1002
+        0x02,             // JAM
+    ]);
+    machine.registers.program_counter = Address(0x1000);
+    machine.run_for_instructions(300);
+
+    let state = machine.save_state();
+
+    // Most of memory is zeroes, so the state is much smaller than 64 KiB
+    assert!(state.len() < 2048);
+
+    let mut restored = Machine::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.registers, machine.registers);
+    assert_eq!(restored.cycles, machine.cycles);
+
+    // Both carry on the same way
+    assert_eq!(machine.run(), StopReason::Jam(Address(0x1009)));
+    assert_eq!(restored.run(), StopReason::Jam(Address(0x1009)));
+    assert_eq!(restored.registers, machine.registers);
+    assert_eq!(restored.cycles, machine.cycles);
+    for i in 0..0x100 {
+        let address = Address(0x2000) + AddressDiff(i);
+        assert_eq!(restored.memory.get_byte(address), i as u8);
+    }
+}
+
+#[test]
+fn load_errors_test() {
+    let mut machine = Machine::new();
+    machine.memory.set_byte(Address(0x1234), 0x56);
+    let state = machine.save_state();
+
+    let mut other = Machine::new();
+    assert_eq!(other.load_state(b"not a save state"),
+               Err(StateError::NotASaveState));
+
+    let mut newer = state.clone();
+    newer[8] = VERSION + 1;
+    assert_eq!(other.load_state(&newer),
+               Err(StateError::UnsupportedVersion(VERSION + 1)));
+
+    let mut cmos = Machine::with_variant(Memory::new(), Variant::Cmos);
+    assert_eq!(cmos.load_state(&state),
+               Err(StateError::VariantMismatch(Variant::Nmos,
+                                               Variant::Cmos)));
+
+    // Damage somewhere in the body is caught by the checksum
+    let mut damaged = state.clone();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 0x01;
+    match other.load_state(&damaged) {
+        Err(StateError::Corrupt(_)) => {}
+        result => panic!("loaded a damaged state: {:?}", result),
+    }
+
+    // A header claiming an enormous body doesn't get memory set aside for it
+    let mut huge = state.clone();
+    for i in 10..14 {
+        huge[i] = 0xFF;
+    }
+    match other.load_state(&huge) {
+        Err(StateError::Corrupt(_)) => {}
+        result => panic!("loaded an oversized state: {:?}", result),
+    }
+
+    let truncated = &state[..state.len() - 1];
+    match other.load_state(truncated) {
+        Err(StateError::Corrupt(_)) => {}
+        result => panic!("loaded a truncated state: {:?}", result),
+    }
+
+    // None of that changed anything
+    assert_eq!(other.memory.get_byte(Address(0x1234)), 0);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.memory.get_byte(Address(0x1234)), 0x56);
+}
+
+#[test]
+fn device_state_test() {
+    let range = AddressRangeIncl::new(Address(0xD000), Address(0xD000));
+
+    let mut bus = DeviceBus::new();
+    bus.register_device(range, Box::new(TestCounter { total: 0 }));
+    let mut machine = Machine::with_bus(bus);
+    machine.memory.set_byte(Address(0xD000), 0x10);
+    machine.memory.set_byte(Address(0xD000), 0x05);
+    machine.memory.set_byte(Address(0x0300), 0x77);
+    let state = machine.save_state();
+
+    let mut bus = DeviceBus::new();
+    bus.register_device(range, Box::new(TestCounter { total: 0 }));
+    let mut restored = Machine::with_bus(bus);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.memory.peek_byte(Address(0xD000)), 0x15);
+    assert_eq!(restored.memory.peek_byte(Address(0x0300)), 0x77);
+
+    // A bus without the device doesn't match
+    let mut plain = Machine::with_bus(DeviceBus::new());
+    match plain.load_state(&state) {
+        Err(StateError::Corrupt(_)) => {}
+        result => panic!("loaded into the wrong bus: {:?}", result),
+    }
+}
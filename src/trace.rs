@@ -0,0 +1,426 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// Execution traces in the layout of the nestest log (as written by
+// Nintendulator), so that runs can be compared line by line against logs
+// from other emulators. A line looks like
+//
+//     C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 ...
+//
+// followed by " SP:FD CYC:12", with the registers and cycle count from
+// before the instruction runs.
+// Undocumented instructions get a `*` in front, and operands that read or
+// write memory are followed by the address they work out to and what's
+// there. There's no PPU, so unlike nestest.log there's no PPU column.
+//
+// To check against nestest itself, load the whole of nestest.nes at $BFF0,
+// which puts its 16-byte header just below the program at $C000:
+//
+//     emu6502 --nes -l '$BFF0' --diff-log nestest.log nestest.nes
+
+use address::{Address, AddressDiff};
+use bus::Bus;
+use disassembler::{disassemble_instruction, format_operand};
+use instruction::{AddressingMode, Instruction};
+use machine::Machine;
+use registers::{StackPointer, PS_BRK, PS_UNUSED};
+
+// Formats the instruction at the PC, and the state of the machine before it
+// runs, as a line of trace
+pub fn format_line<B: Bus>(machine: &Machine<B>) -> String {
+    let pc = machine.registers.program_counter;
+    let disassembled = disassemble_instruction(&machine.memory, pc,
+                                               machine.variant);
+    let bytes: Vec<String> = disassembled.bytes
+                                         .iter()
+                                         .map(|b| format!("{:02X}", b))
+                                         .collect();
+
+    let opcode = disassembled.bytes[0];
+    let mark = if machine.variant.is_undocumented_opcode(opcode) { '*' }
+               else { ' ' };
+    let text = match machine.variant.opcodes()[opcode as usize] {
+        Some((instr, am, _)) =>
+            format_instruction(machine, instr, am, &disassembled.bytes[1..]),
+        None => disassembled.text.clone(),
+    };
+
+    // The processor has no B flag, and bit 5 always reads as set
+    let status = (machine.registers.status | PS_UNUSED) - PS_BRK;
+    let StackPointer(sp) = machine.registers.stack_pointer;
+
+    format!("{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} \
+             SP:{:02X} CYC:{}",
+            pc.to_u16(), bytes.connect(" "), mark, text,
+            machine.registers.accumulator as u8,
+            machine.registers.index_x as u8,
+            machine.registers.index_y as u8,
+            status.bits(),
+            sp,
+            machine.cycles)
+}
+
+fn format_instruction<B: Bus>(machine: &Machine<B>, instr: Instruction,
+                              am: AddressingMode, arr: &[u8]) -> String {
+    let pc = machine.registers.program_counter;
+    let memory = &machine.memory;
+    let x = machine.registers.index_x as u8;
+    let y = machine.registers.index_y as u8;
+
+    let peek = |address: Address| memory.peek_byte(address);
+    let peek_pointer = |lo: Address, hi: Address| {
+        Address((peek(lo) as u16) | ((peek(hi) as u16) << 8))
+    };
+    let word = || Address((arr[0] as u16) | ((arr[1] as u16) << 8));
+    let zero_page = |offset: u8| Address(offset as u16);
+
+    // nestest calls ISC ISB
+    let name = match instr {
+        Instruction::ISC => "ISB".to_string(),
+        _ => format!("{:?}", instr),
+    };
+    let is_jump = match instr {
+        Instruction::JMP | Instruction::JSR => true,
+        _ => false,
+    };
+
+    let operand = match am {
+        AddressingMode::ZeroPage => {
+            let address = zero_page(arr[0]);
+            format!("${:02X} = {:02X}", arr[0], peek(address))
+        }
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let (index, register) = match am {
+                AddressingMode::ZeroPageX => (x, 'X'),
+                _ => (y, 'Y'),
+            };
+            let address = zero_page(arr[0].wrapping_add(index));
+            format!("${:02X},{} @ {:02X} = {:02X}", arr[0], register,
+                    address.to_u16(), peek(address))
+        }
+        AddressingMode::Absolute if !is_jump => {
+            format!("${:04X} = {:02X}", word().to_u16(), peek(word()))
+        }
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let (index, register) = match am {
+                AddressingMode::AbsoluteX => (x, 'X'),
+                _ => (y, 'Y'),
+            };
+            let address = word() + AddressDiff(index as i32);
+            format!("${:04X},{} @ {:04X} = {:02X}", word().to_u16(),
+                    register, address.to_u16(), peek(address))
+        }
+        AddressingMode::Indirect => {
+            // With the NMOS bug, where it applies
+            let hi = if machine.variant.has_indirect_jump_bug() {
+                Address((word().to_u16() & 0xFF00)
+                        | (arr[0].wrapping_add(1) as u16))
+            } else {
+                word() + AddressDiff(1)
+            };
+            format!("(${:04X}) = {:04X}", word().to_u16(),
+                    peek_pointer(word(), hi).to_u16())
+        }
+        AddressingMode::IndexedIndirectX => {
+            let start = arr[0].wrapping_add(x);
+            let pointer = peek_pointer(zero_page(start),
+                                       zero_page(start.wrapping_add(1)));
+            format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", arr[0], start,
+                    pointer.to_u16(), peek(pointer))
+        }
+        AddressingMode::IndirectIndexedY => {
+            let pointer = peek_pointer(zero_page(arr[0]),
+                                       zero_page(arr[0].wrapping_add(1)));
+            let address = pointer + AddressDiff(y as i32);
+            format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", arr[0],
+                    pointer.to_u16(), address.to_u16(), peek(address))
+        }
+        _ => format_operand(am, pc, arr),
+    };
+
+    if operand.is_empty() {
+        name
+    } else {
+        format!("{} {}", name, operand)
+    }
+}
+
+// The parts of a trace line that are compared: everything but the
+// disassembly (which emulators annotate differently) and the PPU column
+#[derive(Debug, PartialEq, Eq)]
+pub struct TraceFields {
+    pub pc:     u16,
+    pub bytes:  Vec<u8>,
+    pub a:      u8,
+    pub x:      u8,
+    pub y:      u8,
+    pub p:      u8,
+    pub sp:     u8,
+    pub cycles: Option<u64>,
+}
+
+fn parse_hex(text: &str) -> Option<u64> {
+    if text.is_empty() {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for c in text.chars() {
+        match c.to_digit(16) {
+            Some(digit) => value = (value << 4) | digit as u64,
+            None => return None,
+        }
+    }
+    Some(value)
+}
+
+// Finds "NAME:value" in a line, for the register and cycle columns
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {}:", name);
+    line.find(&key[..]).map(|start| {
+        let rest = line[start + key.len()..].trim_left();
+        match rest.find(' ') {
+            Some(end) => &rest[..end],
+            None => rest,
+        }
+    })
+}
+
+// Parses a line in nestest layout, from this module or another emulator.
+// The cycle count is optional, since not every log has it.
+pub fn parse_line(line: &str) -> Option<TraceFields> {
+    if line.len() < 16 {
+        return None;
+    }
+
+    let pc = match parse_hex(&line[0..4]) {
+        Some(pc) => pc as u16,
+        None => return None,
+    };
+
+    // The opcode bytes are in the columns between the PC and the
+    // disassembly
+    let mut bytes = Vec::new();
+    for word in line[4..15].split(' ').filter(|w| !w.is_empty()) {
+        match parse_hex(word) {
+            Some(byte) if word.len() == 2 => bytes.push(byte as u8),
+            _ => return None,
+        }
+    }
+
+    let register = |name: &str| {
+        field(line, name).and_then(parse_hex).map(|value| value as u8)
+    };
+    // Older logs have the PPU's dot in the CYC column, followed by SL
+    // for the scanline, so there's no cycle count to compare
+    let cycles = match field(line, "CYC") {
+        Some(_) if field(line, "SL").is_some() => None,
+        Some(text) => match text.parse::<u64>() {
+            Ok(cycles) => Some(cycles),
+            Err(_) => return None,
+        },
+        None => None,
+    };
+
+    match (register("A"), register("X"), register("Y"), register("P"),
+           register("SP")) {
+        (Some(a), Some(x), Some(y), Some(p), Some(sp)) =>
+            Some(TraceFields { pc: pc, bytes: bytes, a: a, x: x, y: y,
+                               p: p, sp: sp, cycles: cycles }),
+        _ => None,
+    }
+}
+
+// Compares a line of trace against the expected one, returning what's
+// different, or None if they match. Cycles are only compared if both lines
+// have them.
+pub fn compare_lines(expected: &str, actual: &str) -> Option<String> {
+    let (expected, actual) = match (parse_line(expected), parse_line(actual)) {
+        (Some(expected), Some(actual)) => (expected, actual),
+        (None, _) => return Some("can't parse the expected line".to_string()),
+        (_, None) => return Some("can't parse the actual line".to_string()),
+    };
+
+    let mut differences = Vec::new();
+    if expected.pc != actual.pc {
+        differences.push(format!("PC {:04X} != {:04X}", expected.pc,
+                                 actual.pc));
+    }
+    if expected.bytes != actual.bytes {
+        differences.push("opcode bytes".to_string());
+    }
+    let registers = [("A", expected.a, actual.a), ("X", expected.x, actual.x),
+                     ("Y", expected.y, actual.y), ("P", expected.p, actual.p),
+                     ("SP", expected.sp, actual.sp)];
+    for &(name, expected, actual) in registers.iter() {
+        if expected != actual {
+            differences.push(format!("{} {:02X} != {:02X}", name, expected,
+                                     actual));
+        }
+    }
+    if let (Some(expected), Some(actual)) = (expected.cycles, actual.cycles) {
+        if expected != actual {
+            differences.push(format!("CYC {} != {}", expected, actual));
+        }
+    }
+
+    if differences.is_empty() {
+        None
+    } else {
+        Some(differences.connect(", "))
+    }
+}
+
+#[cfg(test)]
+use std::cell::RefCell;
+
+#[cfg(test)]
+use std::rc::Rc;
+
+#[cfg(test)]
+use instruction::Variant;
+
+#[cfg(test)]
+use memory::Memory;
+
+#[cfg(test)]
+use registers::Status;
+
+// Sets the machine up the way nestest's automated mode starts
+#[cfg(test)]
+fn nestest_machine(program: &[u8]) -> Machine {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0xC000), program);
+    let mut machine = Machine::with_variant(memory, Variant::Ricoh2A03);
+    machine.registers.program_counter = Address(0xC000);
+    machine.registers.stack_pointer = StackPointer(0xFD);
+    machine.registers.status = Status::from_bits_truncate(0x24);
+    machine.cycles = 7;
+    machine
+}
+
+#[test]
+fn format_line_test() {
+    let mut machine = nestest_machine(&[
+        0x4C, 0x03, 0xC0, // JMP $C003
+        0xA2, 0x00,       // LDX #$00
+        0x86, 0x00,       // STX $00
+        0xA9, 0x55,       // LDA #$55
+        0xB5, 0xFF,       // LDA $FF,X
+        0x6C, 0xFF, 0x02, // JMP ($02FF)
+    ]);
+    machine.memory.set_byte(Address(0x00FF), 0x12);
+    // The pointer's high byte comes from $0200, not $0300
+    machine.memory.set_bytes(Address(0x02FF), &[0x00, 0xC1]);
+    machine.memory.set_byte(Address(0x0200), 0xC0);
+
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let traced = lines.clone();
+    machine.set_tracer(move |line: &str| {
+        traced.borrow_mut().push(line.to_string())
+    });
+    for _ in 0..6 {
+        machine.step();
+    }
+
+    let registers = |rest: &str| format!("A:{}", rest);
+    assert_eq!(*lines.borrow(), vec![
+        format!("C000  4C 03 C0  JMP $C003                       {}",
+                registers("00 X:00 Y:00 P:24 SP:FD CYC:7")),
+        format!("C003  A2 00     LDX #$00                        {}",
+                registers("00 X:00 Y:00 P:24 SP:FD CYC:10")),
+        format!("C005  86 00     STX $00 = 00                    {}",
+                registers("00 X:00 Y:00 P:26 SP:FD CYC:12")),
+        format!("C007  A9 55     LDA #$55                        {}",
+                registers("00 X:00 Y:00 P:26 SP:FD CYC:15")),
+        format!("C009  B5 FF     LDA $FF,X @ FF = 12             {}",
+                registers("55 X:00 Y:00 P:24 SP:FD CYC:17")),
+        format!("C00B  6C FF 02  JMP ($02FF) = C000              {}",
+                registers("12 X:00 Y:00 P:24 SP:FD CYC:21")),
+    ]);
+
+    // Tracing can be turned off again
+    machine.clear_tracer();
+    machine.step();
+    assert_eq!(lines.borrow().len(), 6);
+}
+
+#[test]
+fn format_undocumented_test() {
+    let mut machine = nestest_machine(&[
+        0x04, 0xA9,       // NOP $A9
+        0xA3, 0x40,       // LAX ($40,X)
+        0xEB, 0x01,       // SBC #$01
+    ]);
+    machine.memory.set_bytes(Address(0x0040), &[0x00, 0x04]);
+    machine.memory.set_byte(Address(0x0400), 0x55);
+
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        lines.push(format_line(&machine)[..48].to_string());
+        machine.step();
+    }
+
+    assert_eq!(lines, vec![
+        "C000  04 A9    *NOP $A9 = 00                    ",
+        "C002  A3 40    *LAX ($40,X) @ 40 = 0400 = 55    ",
+        "C004  EB 01    *SBC #$01                        ",
+    ]);
+}
+
+#[test]
+fn parse_line_test() {
+    let line = "C72A  04 A9    *NOP $A9 = 00                    \
+                A:00 X:00 Y:00 P:6E SP:FB PPU: 14, 21 CYC:1586";
+    assert_eq!(parse_line(line), Some(TraceFields {
+        pc: 0xC72A, bytes: vec![0x04, 0xA9], a: 0x00, x: 0x00, y: 0x00,
+        p: 0x6E, sp: 0xFB, cycles: Some(1586),
+    }));
+
+    // In older logs, CYC is the PPU dot
+    let line = "C000  4C F5 C5  JMP $C5F5                       \
+                A:00 X:00 Y:00 P:24 SP:FD CYC:  0 SL:241";
+    assert_eq!(parse_line(line).unwrap().cycles, None);
+
+    assert_eq!(parse_line(""), None);
+    assert_eq!(parse_line("C000  4C F5 C5  JMP $C5F5"), None);
+}
+
+#[test]
+fn compare_lines_test() {
+    let expected = "C000  4C F5 C5  JMP $C5F5                       \
+                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7";
+    let same = "C000  4C F5 C5  JMP $C5F5                       \
+                A:00 X:00 Y:00 P:24 SP:FD CYC:7";
+    let different = "C000  4C F5 C5  JMP $C5F5                       \
+                     A:01 X:00 Y:00 P:A4 SP:FD CYC:8";
+
+    assert_eq!(compare_lines(expected, same), None);
+    assert_eq!(compare_lines(expected, different),
+               Some("A 00 != 01, P 24 != A4, CYC 7 != 8".to_string()));
+    assert_eq!(compare_lines(expected, "garbage"),
+               Some("can't parse the actual line".to_string()));
+}
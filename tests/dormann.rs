@@ -0,0 +1,271 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// Runs Klaus Dormann's 6502 functional and interrupt tests, from
+// https://github.com/Klaus2m5/6502_65C02_functional_tests, to completion.
+// Both tests loop forever at a known address when everything passes, and at
+// the failing check otherwise, with the number of the test case they were
+// on at $0200.
+//
+// The images are the prebuilt ones from the suite's bin_files directory.
+// They're GPL-licensed, so they aren't part of this repository: the tests
+// that run them are ignored unless asked for, and look for the images in the
+// directory named by DORMANN_ROMS, or else in tests/roms (see the README
+// there). Once asked for, a missing image fails its test.
+
+extern crate emu6502;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use emu6502::address::Address;
+use emu6502::assembler;
+use emu6502::bus::Bus;
+use emu6502::device::{Device, DeviceBus};
+use emu6502::instruction::Variant;
+use emu6502::machine::{Machine, StopReason};
+use emu6502::memory::Memory;
+use emu6502::range_incl::AddressRangeIncl;
+use emu6502::registers::PS_DISABLE_INTERRUPTS;
+
+// Both tests start here, with the number of the current test case kept at
+// TEST_CASE
+const START:     Address = Address(0x0400);
+const TEST_CASE: Address = Address(0x0200);
+
+// Where the prebuilt images loop when every test has passed
+const FUNCTIONAL_SUCCESS: Address = Address(0x3469);
+const INTERRUPT_SUCCESS:  Address = Address(0x06F5);
+
+// The interrupt test's feedback port. Setting bit 0 asserts IRQ, and
+// setting bit 1 triggers an NMI.
+const FEEDBACK_PORT: Address = Address(0xBFFC);
+const IRQ_BIT: u8 = 0x01;
+const NMI_BIT: u8 = 0x02;
+
+// The functional test passes after about 30 million instructions
+const MAX_INSTRUCTIONS: u64 = 100000000;
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    // Looping forever at this address, on this test case
+    Trapped(Address, u8),
+
+    // The processor stopped, which none of the tests should make it do
+    Stopped(StopReason),
+
+    TimedOut,
+}
+
+fn rom_dir() -> PathBuf {
+    match env::var("DORMANN_ROMS") {
+        Ok(dir) => Path::new(&dir).to_path_buf(),
+        Err(_) => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+                                                       .join("roms"),
+    }
+}
+
+fn load_rom(name: &str) -> Vec<u8> {
+    let path = rom_dir().join(name);
+    let mut image = Vec::new();
+    let result = File::open(&path).and_then(|mut file| {
+        file.read_to_end(&mut image)
+    });
+    if let Err(e) = result {
+        panic!("couldn't read {}: {} (see tests/roms/README.md)",
+               path.display(), e);
+    }
+    assert!(image.len() <= 0x10000, "{} is bigger than memory", name);
+    image
+}
+
+// Runs from START until the PC stops moving. `after_step` is called after
+// each instruction, to let the test's interrupt sources have their say. A
+// loop waiting for an interrupt that's about to be taken isn't a trap.
+fn run_to_trap<B, F>(machine: &mut Machine<B>, mut after_step: F) -> Outcome
+    where B: Bus, F: FnMut(&mut Machine<B>)
+{
+    machine.registers.program_counter = START;
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = machine.registers.program_counter;
+        if let Some(reason) = machine.step() {
+            return Outcome::Stopped(reason);
+        }
+        after_step(machine);
+
+        let irq_pending = machine.memory.irq_asserted()
+            && !machine.registers.status.contains(PS_DISABLE_INTERRUPTS);
+        if machine.registers.program_counter == pc
+            && !machine.nmi_pending && !irq_pending
+        {
+            return Outcome::Trapped(pc, machine.memory.peek_byte(TEST_CASE));
+        }
+    }
+
+    Outcome::TimedOut
+}
+
+fn check_outcome(name: &str, outcome: Outcome, success: Address) {
+    match outcome {
+        Outcome::Trapped(pc, _) if pc == success => {}
+        Outcome::Trapped(pc, test_case) =>
+            panic!("{} failed test case ${:02X}, trapped at ${:04X}", name,
+                   test_case, pc.to_u16()),
+        Outcome::Stopped(reason) =>
+            panic!("{} stopped the processor: {}", name, reason),
+        Outcome::TimedOut =>
+            panic!("{} was still running after {} instructions", name,
+                   MAX_INSTRUCTIONS),
+    }
+}
+
+// The feedback port's value is shared with the test, which triggers an NMI
+// when bit 1 goes from clear to set
+struct FeedbackPort {
+    value: Rc<RefCell<u8>>,
+}
+
+impl Device for FeedbackPort {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        *self.value.borrow()
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        *self.value.borrow_mut() = value;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        *self.value.borrow() & IRQ_BIT != 0
+    }
+}
+
+fn run_functional_test(image: &[u8]) -> Outcome {
+    let mut memory = Memory::new();
+    memory.set_bytes(Address(0), image);
+    let mut machine = Machine::with_variant(memory, Variant::Nmos);
+    run_to_trap(&mut machine, |_| {})
+}
+
+fn run_interrupt_test(image: &[u8]) -> Outcome {
+    let port = Rc::new(RefCell::new(0));
+    let mut bus = DeviceBus::new();
+    bus.backing.set_bytes(Address(0), image);
+    bus.register_device(AddressRangeIncl::new(FEEDBACK_PORT, FEEDBACK_PORT),
+                        Box::new(FeedbackPort { value: port.clone() }));
+
+    let mut machine = Machine::with_variant(bus, Variant::Nmos);
+    let mut nmi_line = false;
+    run_to_trap(&mut machine, |machine| {
+        let asserted = *port.borrow() & NMI_BIT != 0;
+        if asserted && !nmi_line {
+            machine.trigger_nmi();
+        }
+        nmi_line = asserted;
+    })
+}
+
+// Run with `cargo test --test dormann -- --ignored`
+#[test]
+#[ignore]
+fn functional_test() {
+    let image = load_rom("6502_functional_test.bin");
+    check_outcome("6502_functional_test", run_functional_test(&image),
+                  FUNCTIONAL_SUCCESS);
+}
+
+#[test]
+#[ignore]
+fn interrupt_test() {
+    let image = load_rom("6502_interrupt_test.bin");
+    check_outcome("6502_interrupt_test", run_interrupt_test(&image),
+                  INTERRUPT_SUCCESS);
+}
+
+// The rest check the harness itself, with stand-ins for the real tests
+fn assemble(source: &str) -> Vec<u8> {
+    let program = assembler::assemble(source, Variant::Nmos).unwrap();
+    let mut memory = Memory::new();
+    program.load_into(&mut memory);
+    (0..0x10000).map(|a| memory.peek_byte(Address(a as u16))).collect()
+}
+
+#[test]
+fn trap_test() {
+    let image = assemble("
+            .org $0400
+            LDX #$03
+    next:   STX $0200
+            DEX
+            BNE next
+    fail:   BEQ fail
+    ");
+
+    assert_eq!(run_functional_test(&image),
+               Outcome::Trapped(Address(0x0408), 0x01));
+}
+
+#[test]
+fn feedback_port_test() {
+    // An IRQ, then an NMI from inside its handler, each one counted at
+    // $0200 and acknowledged through the port
+    let image = assemble("
+    port    = $BFFC
+
+            .org $0400
+            CLI
+            LDA #$01
+            STA port
+    done:   JMP done
+
+    irq:    INC $0200
+            LDA #$02
+            STA port
+            RTI
+
+    nmi:    INC $0200
+            LDA #$00
+            STA port
+            RTI
+
+            .org $FFFA
+            .word nmi
+            .word $0400
+            .word irq
+    ");
+
+    assert_eq!(run_interrupt_test(&image),
+               Outcome::Trapped(Address(0x0406), 0x02));
+}
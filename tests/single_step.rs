@@ -0,0 +1,423 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+// Runs per-instruction JSON tests in the format of the vectors from
+// https://github.com/SingleStepTests/65x02 (formerly ProcessorTests). Each
+// file holds tests for one opcode, and each test gives the registers and the
+// RAM it touches before and after running one instruction, and the address,
+// data and direction of each bus cycle along the way:
+//
+//     { "name": "a9 00 ea",
+//       "initial": { "pc": 35422, "s": 240, "a": 18, "x": 52, "y": 86,
+//                    "p": 165, "ram": [[35422, 169], [35423, 0]] },
+//       "final":   { ... },
+//       "cycles":  [[35422, 169, "read"], [35423, 0, "read"]] }
+//
+// The instruction is run on a `Machine`, and its cycle count checked against
+// the number of bus cycles. The bus cycles themselves are checked against a
+// `CycleMachine`. Tests without "cycles" only have their state checked.
+//
+// The files under tests/single_step/synthetic are hand-written fixtures in
+// this format, not upstream vectors. They check that the harness reads the
+// format and that a few NMOS instructions behave, but they aren't a
+// substitute for running the real sets, which `upstream_test` does when
+// SINGLE_STEP_TESTS names a directory of them (see the README there).
+
+extern crate "rustc-serialize" as rustc_serialize;
+extern crate emu6502;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use rustc_serialize::json::Json;
+
+use emu6502::address::Address;
+use emu6502::bus::Bus;
+use emu6502::cycle_machine::{BusCycle, CycleMachine};
+use emu6502::instruction::Variant;
+use emu6502::machine::Machine;
+use emu6502::memory::Memory;
+use emu6502::registers::{Registers, StackPointer, Status};
+
+// Neither of these is a real flag, so the value of P on the stack is the
+// only place they show up
+const P_UNUSED_AND_BRK: u8 = 0x30;
+
+// How many failures to show, out of however many there are
+const MAX_REPORTED: usize = 20;
+
+struct State {
+    pc:  u16,
+    s:   u8,
+    a:   u8,
+    x:   u8,
+    y:   u8,
+    p:   u8,
+    ram: Vec<(u16, u8)>,
+}
+
+struct TestCase {
+    name:     String,
+    initial:  State,
+    expected: State,
+    cycles:   Option<Vec<BusCycle>>,
+}
+
+fn number(json: &Json, max: u64, what: &str) -> Result<u64, String> {
+    match json.as_u64() {
+        Some(value) if value <= max => Ok(value),
+        _ => Err(format!("{} should be a number up to {}", what, max)),
+    }
+}
+
+fn member<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.find(key).ok_or(format!("missing \"{}\"", key))
+}
+
+fn parse_state(json: &Json) -> Result<State, String> {
+    let register = |key: &str| {
+        member(json, key).and_then(|value| number(value, 0xFF, key))
+                         .map(|value| value as u8)
+    };
+
+    let pc = try!(member(json, "pc").and_then(|pc| number(pc, 0xFFFF, "pc")));
+    let entries = try!(try!(member(json, "ram")).as_array()
+                           .ok_or("ram should be an array".to_string()));
+    let mut ram = Vec::new();
+    for entry in entries.iter() {
+        match entry.as_array() {
+            Some(pair) if pair.len() == 2 => {
+                let address = try!(number(&pair[0], 0xFFFF, "ram address"));
+                let value = try!(number(&pair[1], 0xFF, "ram value"));
+                ram.push((address as u16, value as u8));
+            }
+            _ => return Err("ram entries should be [address, value]"
+                                .to_string()),
+        }
+    }
+
+    Ok(State { pc: pc as u16, s: try!(register("s")), a: try!(register("a")),
+               x: try!(register("x")), y: try!(register("y")),
+               p: try!(register("p")), ram: ram })
+}
+
+fn parse_cycles(json: &Json) -> Result<Vec<BusCycle>, String> {
+    let entries = try!(json.as_array()
+                           .ok_or("cycles should be an array".to_string()));
+    let mut cycles = Vec::new();
+    for entry in entries.iter() {
+        match entry.as_array() {
+            Some(cycle) if cycle.len() == 3 => {
+                let address = try!(number(&cycle[0], 0xFFFF,
+                                          "cycle address"));
+                let data = try!(number(&cycle[1], 0xFF, "cycle data"));
+                let write = match cycle[2].as_string() {
+                    Some("read") => false,
+                    Some("write") => true,
+                    _ => return Err("cycles should be \"read\" or \"write\""
+                                        .to_string()),
+                };
+                cycles.push(BusCycle { address: Address(address as u16),
+                                       data:    data as u8,
+                                       write:   write });
+            }
+            _ => return Err("cycles should be [address, data, direction]"
+                                .to_string()),
+        }
+    }
+    Ok(cycles)
+}
+
+fn parse_test(json: &Json) -> Result<TestCase, String> {
+    let name = try!(member(json, "name")).as_string()
+                                         .unwrap_or("unnamed")
+                                         .to_string();
+    let result = member(json, "initial").and_then(parse_state).and_then(
+        |initial| member(json, "final").and_then(parse_state).map(
+            |expected| (initial, expected)));
+    let (initial, expected) = try!(result.map_err(|e| {
+        format!("{}: {}", name, e)
+    }));
+    let cycles = match json.find("cycles") {
+        Some(cycles) => Some(try!(parse_cycles(cycles).map_err(|e| {
+            format!("{}: {}", name, e)
+        }))),
+        None => None,
+    };
+
+    Ok(TestCase { name: name, initial: initial, expected: expected,
+                  cycles: cycles })
+}
+
+fn parse_tests(text: &str) -> Result<Vec<TestCase>, String> {
+    let json = try!(Json::from_str(text).map_err(|e| format!("{:?}", e)));
+    let tests = try!(json.as_array()
+                         .ok_or("expected an array of tests".to_string()));
+    let mut out = Vec::new();
+    for test in tests.iter() {
+        out.push(try!(parse_test(test)));
+    }
+    Ok(out)
+}
+
+fn set_registers(registers: &mut Registers, state: &State) {
+    registers.program_counter = Address(state.pc);
+    registers.stack_pointer = StackPointer(state.s);
+    registers.accumulator = state.a as i8;
+    registers.index_x = state.x as i8;
+    registers.index_y = state.y as i8;
+    registers.status = Status::from_bits_truncate(state.p);
+}
+
+fn load_memory(state: &State) -> Memory {
+    let mut memory = Memory::new();
+    for &(address, value) in state.ram.iter() {
+        memory.set_byte(Address(address), value);
+    }
+    memory
+}
+
+// The letters of the flags that are set in `bits`
+fn flag_names(bits: u8) -> String {
+    "NV-BDIZC".chars()
+              .enumerate()
+              .filter(|&(i, _)| bits & (0x80 >> i) != 0)
+              .map(|(_, flag)| flag)
+              .collect()
+}
+
+// Runs the test's instruction and lists everything that didn't end up as
+// expected. Only the RAM the test mentions is checked.
+fn run_test(test: &TestCase) -> Vec<String> {
+    let mut machine = Machine::with_variant(load_memory(&test.initial),
+                                            Variant::Nmos);
+    set_registers(&mut machine.registers, &test.initial);
+    machine.cycles = 0;
+    let mut differences = Vec::new();
+    if let Some(reason) = machine.step() {
+        differences.push(format!("stopped: {}", reason));
+    }
+
+    let expected = &test.expected;
+    let registers = &machine.registers;
+    let StackPointer(s) = registers.stack_pointer;
+    if registers.program_counter != Address(expected.pc) {
+        differences.push(format!("PC {:04X} != {:04X}", expected.pc,
+                                 registers.program_counter.to_u16()));
+    }
+    let checks = [("S", expected.s, s),
+                  ("A", expected.a, registers.accumulator as u8),
+                  ("X", expected.x, registers.index_x as u8),
+                  ("Y", expected.y, registers.index_y as u8)];
+    for &(name, expected, actual) in checks.iter() {
+        if expected != actual {
+            differences.push(format!("{} {:02X} != {:02X}", name, expected,
+                                     actual));
+        }
+    }
+    let p = registers.status.bits();
+    let wrong_flags = (expected.p ^ p) & !P_UNUSED_AND_BRK;
+    if wrong_flags != 0 {
+        differences.push(format!("P {:02X} != {:02X} (flags {})", expected.p,
+                                 p, flag_names(wrong_flags)));
+    }
+    for &(address, value) in expected.ram.iter() {
+        let actual = machine.memory.peek_byte(Address(address));
+        if actual != value {
+            differences.push(format!("${:04X} {:02X} != {:02X}", address,
+                                     value, actual));
+        }
+    }
+
+    if let Some(ref cycles) = test.cycles {
+        if machine.cycles != cycles.len() as u64 {
+            differences.push(format!("{} cycles != {}", cycles.len(),
+                                     machine.cycles));
+        }
+        differences.extend(check_bus_cycles(test, cycles).into_iter());
+    }
+
+    differences
+}
+
+fn format_bus_cycle(cycle: &BusCycle) -> String {
+    format!("{} {:04X} {:02X}", if cycle.write { "write" } else { "read" },
+            cycle.address.to_u16(), cycle.data)
+}
+
+// Clocks a `CycleMachine` through the instruction, comparing each bus cycle
+fn check_bus_cycles(test: &TestCase, expected: &[BusCycle]) -> Vec<String> {
+    let mut machine = CycleMachine::with_bus(load_memory(&test.initial));
+    set_registers(&mut machine.registers, &test.initial);
+
+    let mut actual = vec![machine.clock()];
+    while !machine.at_instruction_boundary() && !machine.jammed
+        && actual.len() <= expected.len()
+    {
+        actual.push(machine.clock());
+    }
+
+    let mut differences = Vec::new();
+    for (i, (expected, actual)) in expected.iter().zip(actual.iter())
+                                           .enumerate() {
+        if expected != actual {
+            differences.push(format!("bus cycle {}: {} != {}", i + 1,
+                                     format_bus_cycle(expected),
+                                     format_bus_cycle(actual)));
+        }
+    }
+    if expected.len() != actual.len() {
+        differences.push(format!("{} bus cycles != {}", expected.len(),
+                                 actual.len()));
+    }
+    differences
+}
+
+// Runs every test in every file in the directory, and describes the
+// failures
+fn run_directory(dir: &Path) -> Result<(usize, Vec<String>), String> {
+    let entries = try!(fs::read_dir(dir).map_err(|e| {
+        format!("couldn't read {}: {}", dir.display(), e)
+    }));
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = try!(entry.map_err(|e| format!("{}", e))).path();
+        if path.extension().map_or(false, |ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut count = 0;
+    let mut failures = Vec::new();
+    for path in paths.iter() {
+        let mut text = String::new();
+        let result = File::open(path).and_then(|mut file| {
+            file.read_to_string(&mut text)
+        });
+        try!(result.map_err(|e| {
+            format!("couldn't read {}: {}", path.display(), e)
+        }));
+        let tests = try!(parse_tests(&text).map_err(|e| {
+            format!("{}: {}", path.display(), e)
+        }));
+
+        for test in tests.iter() {
+            count += 1;
+            let differences = run_test(test);
+            if !differences.is_empty() {
+                failures.push(format!("{}: {}", test.name,
+                                      differences.connect(", ")));
+            }
+        }
+    }
+
+    Ok((count, failures))
+}
+
+fn check_directory(dir: &Path) {
+    let (count, failures) = run_directory(dir).unwrap();
+    assert!(count > 0, "no tests in {}", dir.display());
+    if !failures.is_empty() {
+        let shown: Vec<&str> = failures.iter()
+                                       .take(MAX_REPORTED)
+                                       .map(|failure| &failure[..])
+                                       .collect();
+        panic!("{} of {} tests failed:\n{}", failures.len(), count,
+               shown.connect("\n"));
+    }
+}
+
+#[test]
+fn synthetic_test() {
+    check_directory(&Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests")
+                        .join("single_step")
+                        .join("synthetic"));
+}
+
+// The upstream vectors aren't part of the repository, so this only runs
+// when asked for: `cargo test --test single_step -- --ignored`
+#[test]
+#[ignore]
+fn upstream_test() {
+    match env::var("SINGLE_STEP_TESTS") {
+        Ok(dir) => check_directory(Path::new(&dir)),
+        Err(_) => panic!("set SINGLE_STEP_TESTS to a directory of vectors \
+                          (see tests/single_step/README.md)"),
+    }
+}
+
+// Everything that's wrong gets reported, not just the first thing
+#[test]
+fn report_test() {
+    let tests = parse_tests(r#"[{
+        "name": "e6 80 00",
+        "initial": { "pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                     "ram": [[1024, 230], [1025, 128], [128, 5]] },
+        "final":   { "pc": 1026, "s": 252, "a": 0, "x": 0, "y": 0, "p": 164,
+                     "ram": [[1024, 230], [1025, 128], [128, 7]] },
+        "cycles":  [[1024, 230, "read"], [1025, 128, "read"],
+                    [128, 5, "read"], [128, 6, "write"]]
+    }]"#).unwrap();
+
+    assert_eq!(run_test(&tests[0]), vec![
+        "S FC != FD",
+        "P A4 != 24 (flags N)",
+        "$0080 07 != 06",
+        "4 cycles != 5",
+        "bus cycle 4: write 0080 06 != write 0080 05",
+        "4 bus cycles != 5",
+    ]);
+
+    // A test without cycles only has its state checked
+    let tests = parse_tests(r#"[{
+        "name": "ea 00 00",
+        "initial": { "pc": 0, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                     "ram": [[0, 234]] },
+        "final":   { "pc": 1, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                     "ram": [[0, 234]] }
+    }]"#).unwrap();
+    assert!(tests[0].cycles.is_none());
+    assert_eq!(run_test(&tests[0]), Vec::<String>::new());
+}
+
+#[test]
+fn parse_error_test() {
+    assert!(parse_tests("{}").is_err());
+    assert_eq!(parse_tests(r#"[{ "name": "a9 00 00", "initial": {} }]"#)
+                   .err(),
+               Some("a9 00 00: missing \"pc\"".to_string()));
+    assert_eq!(parse_tests(r#"[{
+        "name": "a9 00 00",
+        "initial": { "pc": 0, "s": 256, "a": 0, "x": 0, "y": 0, "p": 0,
+                     "ram": [] }
+    }]"#).err(),
+               Some("a9 00 00: s should be a number up to 255".to_string()));
+}